@@ -0,0 +1,277 @@
+use raylib::prelude::*;
+
+use crate::{
+    load_texture, log, push_sprite, push_texture, read_asset, vectortoangle, AnimationClip,
+    AnimationState, Animator, Assets, Enemy, LogCategory, LogLevel, Part, ParticalEmitter, Player,
+    PowerUp, Sprite, SpriteBatch, TextureID, Wreck, ENEMY_LAYER,
+};
+
+pub const ANIMATION_FILE: &str = "Images/animations.txt";
+// How far the smoothed turn input has to go before a ship banks.
+const BANK_THRESHOLD: f32 = 0.4;
+const BANK_RATE: f32 = 6.0;
+// Any part below this fraction of its starting health shows the damaged clip.
+const DAMAGED_HEALTH: f32 = 0.5;
+const WRECK_FRICTION: f32 = 1.5;
+
+/// Reads the clip list and cuts each sheet into frames. Has to run before the atlas is built
+/// so the sheets get packed with everything else.
+pub fn load_animations(assets: &mut Assets, rl: &mut RaylibHandle, thread: &RaylibThread) {
+    let text = match read_asset(assets, ANIMATION_FILE) {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).to_string(),
+        Err(err) => {
+            log(
+                LogLevel::Warn,
+                LogCategory::Assets,
+                &format!("{err}, sprites won't be animated"),
+            );
+            return;
+        }
+    };
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let clip = match fields[..] {
+            [name, sheet, frames, frame_time, looping] => {
+                match (frames.parse::<i32>(), frame_time.parse::<f32>()) {
+                    (Ok(frames), Ok(frame_time)) if frames > 0 && frame_time > 0.0 => {
+                        Some((name, sheet, frames, frame_time, looping == "loop"))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let Some((name, sheet, frames, frame_time, looping)) = clip else {
+            log(
+                LogLevel::Warn,
+                LogCategory::Assets,
+                &format!(
+                    "{ANIMATION_FILE}:{}: expected a name, sheet, frame count, seconds per frame and loop or once",
+                    number + 1
+                ),
+            );
+            continue;
+        };
+        let texture = load_texture(assets, rl, thread, sheet);
+        let sheet = &assets.textures[texture];
+        let width = sheet.width as f32 / frames as f32;
+        let height = sheet.height as f32;
+        assets.clips.insert(
+            name.to_string(),
+            AnimationClip {
+                texture,
+                frames: (0..frames)
+                    .map(|frame| Rectangle::new(frame as f32 * width, 0.0, width, height))
+                    .collect(),
+                frame_time,
+                looping,
+            },
+        );
+    }
+}
+
+pub fn new_animator(clips: &[(AnimationState, &str)]) -> Animator {
+    Animator {
+        clips: clips
+            .iter()
+            .map(|(state, name)| (*state, name.to_string()))
+            .collect(),
+        state: AnimationState::Idle,
+        time: 0.0,
+        steer: 0.0,
+        bank: 0.0,
+    }
+}
+
+pub fn has_clip(animator: &Animator, state: AnimationState) -> bool {
+    animator.clips.iter().any(|(other, _)| *other == state)
+}
+
+fn state_clip<'a>(
+    animator: &Animator,
+    assets: &'a Assets,
+    state: AnimationState,
+) -> Option<&'a AnimationClip> {
+    animator
+        .clips
+        .iter()
+        .find(|(other, _)| *other == state)
+        .and_then(|(_, name)| assets.clips.get(name))
+}
+
+fn current_clip<'a>(animator: &Animator, assets: &'a Assets) -> Option<&'a AnimationClip> {
+    state_clip(animator, assets, animator.state)
+        .or_else(|| state_clip(animator, assets, AnimationState::Idle))
+}
+
+/// Switching state starts the new clip from its first frame.
+pub fn set_animation_state(animator: &mut Animator, state: AnimationState) {
+    if animator.state != state {
+        animator.state = state;
+        animator.time = 0.0;
+    }
+}
+
+/// Picks a ship's clip from what it's doing this frame. Dying beats everything, then banking,
+/// then damage, and the engines only show when they're putting out exhaust.
+pub fn animate_ship(
+    animator: &mut Animator,
+    parts: &Vec<Part>,
+    partical_emmiters: &Vec<ParticalEmitter>,
+    dying: bool,
+    dt: f32,
+) {
+    animator.bank += (animator.steer - animator.bank) * f32::min(BANK_RATE * dt, 1.0);
+    let damaged = parts
+        .iter()
+        .any(|part| part.health < part.starting_health * DAMAGED_HEALTH);
+    let thrusting = partical_emmiters
+        .iter()
+        .any(|partical_emmiter| partical_emmiter.speed > 0.0);
+    let state = if dying {
+        AnimationState::Dying
+    } else if animator.bank < -BANK_THRESHOLD {
+        AnimationState::BankLeft
+    } else if animator.bank > BANK_THRESHOLD {
+        AnimationState::BankRight
+    } else if damaged {
+        AnimationState::Damaged
+    } else if thrusting {
+        AnimationState::Thrusting
+    } else {
+        AnimationState::Idle
+    };
+    set_animation_state(animator, state);
+    animator.time += dt;
+}
+
+pub fn animate_player(player: &mut Player, dt: f32) {
+    let dying = player.parts.iter().any(|part| part.health <= 0.0);
+    animate_ship(
+        &mut player.animator,
+        &player.parts,
+        &player.partical_emmiters,
+        dying,
+        dt,
+    );
+}
+
+pub fn animate_enemies(enemies: &mut Vec<Enemy>, dt: f32) {
+    for enemy in enemies {
+        animate_ship(
+            &mut enemy.animator,
+            &enemy.parts,
+            &enemy.partical_emmiters,
+            false,
+            dt,
+        );
+    }
+}
+
+pub fn animate_power_ups(power_ups: &mut Vec<PowerUp>, dt: f32) {
+    for power_up in power_ups {
+        power_up.animator.time += dt;
+    }
+}
+
+/// Once clips hold their last frame, so this is how callers know one has played out. A state
+/// with no clip of its own counts as finished straight away.
+pub fn animation_finished(animator: &Animator, assets: &Assets) -> bool {
+    match state_clip(animator, assets, animator.state) {
+        Some(clip) => !clip.looping && animator.time >= clip.frame_time * clip.frames.len() as f32,
+        None => true,
+    }
+}
+
+/// Queues the animator's current frame centered on `pos`, or the plain texture if it has no
+/// clip for the state it's in.
+pub fn push_animation(
+    batch: &mut SpriteBatch,
+    assets: &Assets,
+    animator: &Animator,
+    texture: TextureID,
+    pos: Vector2,
+    scale: f32,
+    rotation: f32,
+    layer: i32,
+) {
+    let Some(clip) = current_clip(animator, assets) else {
+        push_texture(batch, assets, texture, pos, scale, rotation, layer);
+        return;
+    };
+    let frame = (animator.time / clip.frame_time) as usize;
+    let frame = if clip.looping {
+        frame % clip.frames.len()
+    } else {
+        frame.min(clip.frames.len() - 1)
+    };
+    let source = clip.frames[frame];
+    let size = Vector2::new(source.width * scale, source.height * scale);
+    push_sprite(
+        batch,
+        assets,
+        Sprite {
+            texture: clip.texture,
+            source,
+            dest: Rectangle::new(pos.x, pos.y, size.x, size.y),
+            origin: size / 2.0,
+            rotation,
+            tint: Color::WHITE,
+            layer,
+            additive: false,
+        },
+    );
+}
+
+/// Leaves a wreck behind for enemies that have a dying clip to play.
+pub fn add_wreck(wrecks: &mut Vec<Wreck>, enemy: &Enemy) {
+    if !has_clip(&enemy.animator, AnimationState::Dying) {
+        return;
+    }
+    let mut animator = enemy.animator.clone();
+    set_animation_state(&mut animator, AnimationState::Dying);
+    wrecks.push(Wreck {
+        pos: enemy.pos,
+        vel: enemy.vel,
+        dir: enemy.dir,
+        texture_id: enemy.texture_id,
+        texture_scale: enemy.texture_scale,
+        animator,
+    });
+}
+
+pub fn update_wrecks(wrecks: &mut Vec<Wreck>, assets: &Assets, dt: f32) {
+    for wreck in wrecks.iter_mut() {
+        wreck.vel -= wreck.vel * f32::min(WRECK_FRICTION * dt, 1.0);
+        wreck.pos += wreck.vel * dt;
+        wreck.animator.time += dt;
+    }
+    wrecks.retain(|wreck| !animation_finished(&wreck.animator, assets));
+}
+
+pub fn draw_wrecks(
+    batch: &mut SpriteBatch,
+    assets: &Assets,
+    player: &Player,
+    wrecks: &Vec<Wreck>,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    for wreck in wrecks {
+        push_animation(
+            batch,
+            assets,
+            &wreck.animator,
+            wreck.texture_id,
+            wreck.pos - player.pos
+                + Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0),
+            wreck.texture_scale,
+            vectortoangle(wreck.dir).to_degrees() + 90.0,
+            ENEMY_LAYER,
+        );
+    }
+}
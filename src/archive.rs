@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::{Archive, ArchiveEntry, Huffman};
+
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+const DIRECTORY_ENTRY: u32 = 0x0201_4b50;
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+const TOO_LONG: &str = "compressed data unpacks past its size";
+
+// Base lengths and distances for the deflate length and distance codes, and how many extra
+// bits follow each one.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// The order code length code lengths are stored in for a dynamic block.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn read_u16(data: &[u8], at: usize) -> Result<u16, String> {
+    match data.get(at..at + 2) {
+        Some(bytes) => Ok(u16::from_le_bytes([bytes[0], bytes[1]])),
+        None => Err("archive is truncated".to_string()),
+    }
+}
+
+fn read_u32(data: &[u8], at: usize) -> Result<u32, String> {
+    match data.get(at..at + 4) {
+        Some(bytes) => Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        None => Err("archive is truncated".to_string()),
+    }
+}
+
+/// Reads a zip file's central directory. The file contents stay packed until they're asked for.
+pub fn open_archive(path: &Path) -> Result<Archive, String> {
+    let data = std::fs::read(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    // The end record sits at the very end unless the archive has a comment after it.
+    let end = (0..data.len().saturating_sub(21))
+        .rev()
+        .find(|at| read_u32(&data, *at) == Ok(END_OF_DIRECTORY))
+        .ok_or(format!("{} is not a zip archive", path.display()))?;
+    let count = read_u16(&data, end + 10)? as usize;
+    let mut at = read_u32(&data, end + 16)? as usize;
+    let mut entries = HashMap::new();
+    for _ in 0..count {
+        if read_u32(&data, at)? != DIRECTORY_ENTRY {
+            return Err(format!("{} has a broken directory", path.display()));
+        }
+        let name_length = read_u16(&data, at + 28)? as usize;
+        let extra_length = read_u16(&data, at + 30)? as usize;
+        let comment_length = read_u16(&data, at + 32)? as usize;
+        let name = data
+            .get(at + 46..at + 46 + name_length)
+            .ok_or("archive is truncated".to_string())?;
+        entries.insert(
+            String::from_utf8_lossy(name).replace('\\', "/"),
+            ArchiveEntry {
+                method: read_u16(&data, at + 10)?,
+                crc: read_u32(&data, at + 16)?,
+                packed_size: read_u32(&data, at + 20)? as usize,
+                size: read_u32(&data, at + 24)? as usize,
+                header: read_u32(&data, at + 42)? as usize,
+            },
+        );
+        at += 46 + name_length + extra_length + comment_length;
+    }
+    Ok(Archive { data, entries })
+}
+
+/// Unpacks one file, checking it against the checksum stored in the archive.
+pub fn read_archive(archive: &Archive, name: &str) -> Result<Vec<u8>, String> {
+    let entry = archive
+        .entries
+        .get(name)
+        .ok_or(format!("{name} is not in the archive"))?;
+    let data = &archive.data;
+    if read_u32(data, entry.header)? != LOCAL_HEADER {
+        return Err(format!("{name} has a broken header"));
+    }
+    // The local header repeats the name but can have a different extra field.
+    let start = entry.header
+        + 30
+        + read_u16(data, entry.header + 26)? as usize
+        + read_u16(data, entry.header + 28)? as usize;
+    let packed = data
+        .get(start..start + entry.packed_size)
+        .ok_or("archive is truncated".to_string())?;
+    let bytes = match entry.method {
+        STORED => packed.to_vec(),
+        DEFLATED => inflate(packed, entry.size)?,
+        method => {
+            return Err(format!(
+                "{name} uses unsupported compression method {method}"
+            ))
+        }
+    };
+    if bytes.len() != entry.size || crc32(&bytes) != entry.crc {
+        return Err(format!("{name} is corrupt"));
+    }
+    Ok(bytes)
+}
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn bits(data: &[u8], bit: &mut usize, count: u8) -> Result<u32, String> {
+    let mut value = 0;
+    for index in 0..count {
+        let byte = *data
+            .get(*bit / 8)
+            .ok_or("compressed data ends early".to_string())?;
+        value |= (((byte >> (*bit % 8)) & 1) as u32) << index;
+        *bit += 1;
+    }
+    Ok(value)
+}
+
+/// Canonical huffman table from a list of code lengths, one per symbol.
+fn huffman(lengths: &[u8]) -> Huffman {
+    let mut counts = [0u16; 16];
+    for length in lengths {
+        counts[*length as usize] += 1;
+    }
+    counts[0] = 0;
+    let mut offsets = [0u16; 16];
+    for length in 1..15 {
+        offsets[length + 1] = offsets[length] + counts[length];
+    }
+    let mut symbols = vec![0; lengths.len()];
+    for (symbol, length) in lengths.iter().enumerate() {
+        if *length != 0 {
+            symbols[offsets[*length as usize] as usize] = symbol as u16;
+            offsets[*length as usize] += 1;
+        }
+    }
+    Huffman { counts, symbols }
+}
+
+/// Walks the code a bit at a time. Codes of each length are consecutive, so it only needs to
+/// know how many codes there are of each length.
+fn decode(data: &[u8], bit: &mut usize, table: &Huffman) -> Result<u16, String> {
+    let mut code = 0i32;
+    let mut first = 0i32;
+    let mut index = 0i32;
+    for length in 1..16 {
+        code |= bits(data, bit, 1)? as i32;
+        let count = table.counts[length] as i32;
+        if code - first < count {
+            return Ok(table.symbols[(index + code - first) as usize]);
+        }
+        index += count;
+        first = (first + count) << 1;
+        code <<= 1;
+    }
+    Err("bad huffman code".to_string())
+}
+
+fn dynamic_tables(data: &[u8], bit: &mut usize) -> Result<(Huffman, Huffman), String> {
+    let literal_count = bits(data, bit, 5)? as usize + 257;
+    let distance_count = bits(data, bit, 5)? as usize + 1;
+    let code_length_count = bits(data, bit, 4)? as usize + 4;
+    let mut code_lengths = [0u8; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[*index] = bits(data, bit, 3)? as u8;
+    }
+    let code_length_table = huffman(&code_lengths);
+    let mut lengths: Vec<u8> = vec![];
+    while lengths.len() < literal_count + distance_count {
+        let symbol = decode(data, bit, &code_length_table)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => (
+                *lengths.last().ok_or("repeat with no length".to_string())?,
+                3 + bits(data, bit, 2)?,
+            ),
+            17 => (0, 3 + bits(data, bit, 3)?),
+            _ => (0, 11 + bits(data, bit, 7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(value);
+        }
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err("code lengths overrun".to_string());
+    }
+    Ok((
+        huffman(&lengths[..literal_count]),
+        huffman(&lengths[literal_count..]),
+    ))
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    (huffman(&lengths), huffman(&[5; 30]))
+}
+
+/// A raw deflate stream as stored in zip files, without the zlib header. `size` is what the
+/// archive says it unpacks to, a stream that goes past it is treated as corrupt rather than
+/// left to grow.
+pub fn inflate(data: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    let mut out: Vec<u8> = Vec::with_capacity(size);
+    let mut bit = 0;
+    loop {
+        let last = bits(data, &mut bit, 1)? == 1;
+        let (literals, distances) = match bits(data, &mut bit, 2)? {
+            0 => {
+                // Stored blocks start on a byte boundary with their length and its complement.
+                let at = bit.div_ceil(8);
+                let length = read_u16(data, at)? as usize;
+                if read_u16(data, at + 2)? != !(length as u16) {
+                    return Err("stored block length is corrupt".to_string());
+                }
+                let block = data
+                    .get(at + 4..at + 4 + length)
+                    .ok_or("compressed data ends early".to_string())?;
+                if out.len() + length > size {
+                    return Err(TOO_LONG.to_string());
+                }
+                out.extend_from_slice(block);
+                bit = (at + 4 + length) * 8;
+                if last {
+                    return Ok(out);
+                }
+                continue;
+            }
+            1 => fixed_tables(),
+            2 => dynamic_tables(data, &mut bit)?,
+            _ => return Err("bad block type".to_string()),
+        };
+        loop {
+            let symbol = decode(data, &mut bit, &literals)? as usize;
+            if symbol < 256 {
+                if out.len() == size {
+                    return Err(TOO_LONG.to_string());
+                }
+                out.push(symbol as u8);
+                continue;
+            }
+            if symbol == 256 {
+                break;
+            }
+            let code = symbol - 257;
+            if code >= LENGTH_BASE.len() {
+                return Err("bad length code".to_string());
+            }
+            let length =
+                LENGTH_BASE[code] as usize + bits(data, &mut bit, LENGTH_EXTRA[code])? as usize;
+            let code = decode(data, &mut bit, &distances)? as usize;
+            if code >= DISTANCE_BASE.len() {
+                return Err("bad distance code".to_string());
+            }
+            let distance =
+                DISTANCE_BASE[code] as usize + bits(data, &mut bit, DISTANCE_EXTRA[code])? as usize;
+            if distance > out.len() {
+                return Err("distance reaches before the start".to_string());
+            }
+            if out.len() + length > size {
+                return Err(TOO_LONG.to_string());
+            }
+            // Copied a byte at a time since the match can overlap what it's writing.
+            let from = out.len() - distance;
+            for index in 0..length {
+                out.push(out[from + index]);
+            }
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO: &[u8] = b"hello hello hello hello";
+    const STORED_HELLO: [u8; 28] = [
+        1, 23, 0, 232, 255, 104, 101, 108, 108, 111, 32, 104, 101, 108, 108, 111, 32, 104, 101,
+        108, 108, 111, 32, 104, 101, 108, 108, 111,
+    ];
+    // Fixed codes, mostly one back reference to the first "hello ".
+    const FIXED_HELLO: [u8; 10] = [203, 72, 205, 201, 201, 87, 200, 64, 39, 1];
+    // Skewed enough that zlib picks its own tables over the fixed ones.
+    const LETTERS: &[u8] = b"adab aa bdaa dd caaadcaaaabab ccc abaaa abcbcdcbdcabababdaab";
+    const DYNAMIC_LETTERS: [u8; 39] = [
+        21, 138, 129, 9, 0, 64, 16, 130, 86, 185, 213, 180, 246, 159, 225, 123, 2, 9, 148, 226,
+        193, 217, 161, 189, 0, 253, 192, 137, 36, 183, 51, 133, 49, 141, 115, 254, 45, 247, 1,
+    ];
+
+    /// An archive holding a single file called "name", behind a bare local header.
+    fn one_file_archive(method: u16, packed: &[u8], size: usize, crc: u32) -> Archive {
+        let mut data = vec![0; 30];
+        data[..4].copy_from_slice(&LOCAL_HEADER.to_le_bytes());
+        data.extend_from_slice(packed);
+        Archive {
+            data,
+            entries: HashMap::from([(
+                "name".to_string(),
+                ArchiveEntry {
+                    method,
+                    crc,
+                    packed_size: packed.len(),
+                    size,
+                    header: 0,
+                },
+            )]),
+        }
+    }
+
+    #[test]
+    fn inflates_stored_blocks() {
+        assert_eq!(STORED_HELLO[0] >> 1 & 3, 0);
+        assert_eq!(inflate(&STORED_HELLO, HELLO.len()).unwrap(), HELLO);
+    }
+
+    #[test]
+    fn inflates_fixed_blocks() {
+        assert_eq!(FIXED_HELLO[0] >> 1 & 3, 1);
+        assert_eq!(inflate(&FIXED_HELLO, HELLO.len()).unwrap(), HELLO);
+    }
+
+    #[test]
+    fn inflates_dynamic_blocks() {
+        assert_eq!(DYNAMIC_LETTERS[0] >> 1 & 3, 2);
+        assert_eq!(inflate(&DYNAMIC_LETTERS, LETTERS.len()).unwrap(), LETTERS);
+    }
+
+    #[test]
+    fn truncated_data_is_an_error() {
+        for packed in [&STORED_HELLO[..], &FIXED_HELLO[..], &DYNAMIC_LETTERS[..]] {
+            for end in 0..packed.len() - 1 {
+                assert!(inflate(&packed[..end], 100).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn stops_once_past_the_size() {
+        for (packed, size) in [
+            (&STORED_HELLO[..], HELLO.len()),
+            (&FIXED_HELLO[..], HELLO.len()),
+            (&DYNAMIC_LETTERS[..], LETTERS.len()),
+        ] {
+            assert_eq!(inflate(packed, size - 1), Err(TOO_LONG.to_string()));
+        }
+    }
+
+    #[test]
+    fn checks_the_crc() {
+        let archive = one_file_archive(DEFLATED, &FIXED_HELLO, HELLO.len(), crc32(HELLO));
+        assert_eq!(read_archive(&archive, "name").unwrap(), HELLO);
+        let archive = one_file_archive(DEFLATED, &FIXED_HELLO, HELLO.len(), crc32(HELLO) ^ 1);
+        assert_eq!(
+            read_archive(&archive, "name"),
+            Err("name is corrupt".to_string())
+        );
+        let archive = one_file_archive(STORED, HELLO, HELLO.len(), 0x8D3D_51E3);
+        assert_eq!(read_archive(&archive, "name").unwrap(), HELLO);
+    }
+}
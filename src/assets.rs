@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use raylib::prelude::*;
+use slotmap::{SecondaryMap, SlotMap};
+
+use crate::{log, open_archive, read_archive, Assets, LogCategory, LogLevel, TextureID};
+
+/// Packed copy of the game files, used for anything that isn't there as a loose file.
+pub const ARCHIVE_FILE: &str = "Game.zip";
+const PLACEHOLDER_SIZE: i32 = 32;
+const CIRCLE_SIZE: i32 = 64;
+
+/// The first folder from the executable upwards that has an `Images` folder in it, so the
+/// game finds its files when installed and from `target/debug` alike. Falls back to the
+/// working directory.
+pub fn asset_root() -> PathBuf {
+    if let Ok(exe) = std::env::current_exe() {
+        if let Some(root) = exe
+            .ancestors()
+            .skip(1)
+            .find(|dir| dir.join("Images").is_dir())
+        {
+            return root.to_path_buf();
+        }
+    }
+    PathBuf::from(".")
+}
+
+/// Opens the archive if there is one and makes the plain white square and circle that
+/// rectangles and round particles are drawn with.
+pub fn new_assets(root: PathBuf, rl: &mut RaylibHandle, thread: &RaylibThread) -> Assets {
+    let archive_path = root.join(ARCHIVE_FILE);
+    let archive = if archive_path.is_file() {
+        match open_archive(&archive_path) {
+            Ok(archive) => Some(archive),
+            Err(err) => {
+                log(
+                    LogLevel::Warn,
+                    LogCategory::Assets,
+                    &format!("{err}, only using loose files"),
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let mut assets = Assets {
+        root,
+        archive,
+        textures: SlotMap::with_key(),
+        sizes: SecondaryMap::new(),
+        texture_names: HashMap::new(),
+        images: vec![],
+        atlas: None,
+        regions: SecondaryMap::new(),
+        white: TextureID::default(),
+        circle: TextureID::default(),
+        clips: HashMap::new(),
+    };
+    assets.white = add_texture(
+        &mut assets,
+        rl,
+        thread,
+        "white",
+        Image::gen_image_color(4, 4, Color::WHITE),
+    );
+    let mut circle = Image::gen_image_color(CIRCLE_SIZE, CIRCLE_SIZE, Color::BLANK);
+    circle.draw_circle(
+        CIRCLE_SIZE / 2,
+        CIRCLE_SIZE / 2,
+        CIRCLE_SIZE / 2 - 1,
+        Color::WHITE,
+    );
+    assets.circle = add_texture(&mut assets, rl, thread, "circle", circle);
+    assets
+}
+
+pub fn asset_path(assets: &Assets, name: &str) -> PathBuf {
+    assets.root.join(name)
+}
+
+/// A file's contents, from the loose file if there is one and the archive otherwise.
+pub fn read_asset(assets: &Assets, name: &str) -> Result<Vec<u8>, String> {
+    let path = asset_path(assets, name);
+    match std::fs::read(&path) {
+        Ok(bytes) => Ok(bytes),
+        Err(err) => match &assets.archive {
+            Some(archive) => read_archive(archive, name),
+            None => Err(format!("{}: {err}", path.display())),
+        },
+    }
+}
+
+fn load_image_file(assets: &Assets, name: &str) -> Result<Image, String> {
+    let bytes = read_asset(assets, name)?;
+    let extension = Path::new(name)
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    Image::load_image_from_mem(&extension, &bytes)
+}
+
+/// Uploads an image under a name and keeps the image around to be packed into the atlas.
+fn add_texture(
+    assets: &mut Assets,
+    rl: &mut RaylibHandle,
+    thread: &RaylibThread,
+    name: &str,
+    image: Image,
+) -> TextureID {
+    let texture = rl
+        .load_texture_from_image(thread, &image)
+        .expect("uploading an image that's already in memory should never fail");
+    let id = assets.textures.insert(texture);
+    assets
+        .sizes
+        .insert(id, Vector2::new(image.width as f32, image.height as f32));
+    assets.texture_names.insert(name.to_string(), id);
+    assets.images.push((id, image));
+    id
+}
+
+/// Loads a texture once and hands back the same id every time after. A missing or broken
+/// file gets a magenta checkerboard instead, so it's obvious in game but doesn't crash it.
+pub fn load_texture(
+    assets: &mut Assets,
+    rl: &mut RaylibHandle,
+    thread: &RaylibThread,
+    name: &str,
+) -> TextureID {
+    if let Some(id) = assets.texture_names.get(name) {
+        return *id;
+    }
+    let image = match load_image_file(assets, name) {
+        Ok(image) => image,
+        Err(err) => {
+            log(
+                LogLevel::Warn,
+                LogCategory::Assets,
+                &format!("{err}, using a placeholder for {name}"),
+            );
+            Image::gen_image_checked(
+                PLACEHOLDER_SIZE,
+                PLACEHOLDER_SIZE,
+                PLACEHOLDER_SIZE / 4,
+                PLACEHOLDER_SIZE / 4,
+                Color::MAGENTA,
+                Color::BLACK,
+            )
+        }
+    };
+    add_texture(assets, rl, thread, name, image)
+}
@@ -0,0 +1,479 @@
+use raylib::prelude::*;
+
+use crate::{
+    asset_path, log, read_asset, Assets, Audio, AudioBackend, Director, Enemy, LogCategory,
+    LogLevel, Player, Settings, SoundEffect, SoundInfo, SoundMix, SoundRequest, Voice,
+    MAX_PRESSURE, MIN_PRESSURE,
+};
+
+pub const SOUND_EFFECTS: [SoundEffect; 7] = [
+    SoundEffect::PlayerFire,
+    SoundEffect::EnemyFire,
+    SoundEffect::Hit,
+    SoundEffect::EnemyDeath,
+    SoundEffect::PartDamage,
+    SoundEffect::PowerUp,
+    SoundEffect::Alarm,
+];
+pub const MAX_VOICES: usize = 16;
+/// Below this fraction of starting health on any part the alarm keeps going off.
+pub const LOW_HEALTH: f32 = 0.25;
+const ALARM_INTERVAL: f32 = 1.2;
+// How far away enemies still count towards the music getting busier.
+const THREAT_RANGE: f32 = 1500.0;
+const INTENSITY_RATE: f32 = 0.3;
+// Past this distance from the player a sound is dropped completely.
+const AUDIBLE_RANGE: f32 = 2200.0;
+// Closer than this sounds drift back to the middle, so the ship's own guns aren't hard panned.
+const PAN_RADIUS: f32 = 200.0;
+// Much slower than the bullets so the pitch shift is actually noticeable.
+const SPEED_OF_SOUND: f32 = 2500.0;
+const MIN_PITCH: f32 = 0.75;
+const MAX_PITCH: f32 = 1.3;
+// The null backend only keeps this many, a whole --mute session would otherwise pile up.
+const MAX_RECORDED: usize = 1000;
+
+pub fn sound_info(effect: &SoundEffect) -> SoundInfo {
+    match effect {
+        SoundEffect::PlayerFire => SoundInfo {
+            file: "Sounds/fire.wav",
+            voices: 4,
+            length: 0.12,
+            volume: 0.5,
+        },
+        SoundEffect::EnemyFire => SoundInfo {
+            file: "Sounds/enemy_fire.wav",
+            voices: 4,
+            length: 0.16,
+            volume: 0.4,
+        },
+        SoundEffect::Hit => SoundInfo {
+            file: "Sounds/hit.wav",
+            voices: 3,
+            length: 0.07,
+            volume: 0.6,
+        },
+        SoundEffect::EnemyDeath => SoundInfo {
+            file: "Sounds/explosion.wav",
+            voices: 4,
+            length: 0.9,
+            volume: 0.8,
+        },
+        SoundEffect::PartDamage => SoundInfo {
+            file: "Sounds/part_damage.wav",
+            voices: 2,
+            length: 0.3,
+            volume: 0.8,
+        },
+        SoundEffect::PowerUp => SoundInfo {
+            file: "Sounds/powerup.wav",
+            voices: 1,
+            length: 0.32,
+            volume: 0.7,
+        },
+        SoundEffect::Alarm => SoundInfo {
+            file: "Sounds/alarm.wav",
+            voices: 1,
+            length: 0.5,
+            volume: 0.6,
+        },
+    }
+}
+
+pub fn new_audio() -> Audio {
+    Audio {
+        queue: vec![],
+        voices: vec![],
+        intensity: 0.0,
+        alarm_time: 0.0,
+    }
+}
+
+/// Loads every effect once per voice it's allowed, so overlapping shots don't cut each
+/// other off. Anything missing is left silent rather than stopping the game.
+pub fn load_audio_backend<'aud>(device: &'aud RaylibAudio, assets: &Assets) -> AudioBackend<'aud> {
+    let mut sounds = vec![];
+    for effect in SOUND_EFFECTS.iter() {
+        let info = sound_info(effect);
+        let mut copies = vec![];
+        let wave = read_asset(assets, info.file)
+            .and_then(|bytes| device.new_wave_from_memory(".wav", &bytes));
+        match wave {
+            Ok(wave) => {
+                for _ in 0..info.voices {
+                    match device.new_sound_from_wave(&wave) {
+                        Ok(sound) => copies.push(sound),
+                        Err(err) => {
+                            log(LogLevel::Warn, LogCategory::Assets, &err);
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(err) => log(LogLevel::Warn, LogCategory::Assets, &err),
+        }
+        sounds.push(copies);
+    }
+    // Music streams from disk as it plays, so it has to be a loose file.
+    let load_music =
+        |file: &str| match device.new_music(&asset_path(assets, file).to_string_lossy()) {
+            Ok(music) => Some(music),
+            Err(err) => {
+                log(LogLevel::Warn, LogCategory::Assets, &err);
+                None
+            }
+        };
+    AudioBackend::Device {
+        next: vec![0; sounds.len()],
+        sounds,
+        calm: load_music("Sounds/music_calm.wav"),
+        intense: load_music("Sounds/music_intense.wav"),
+    }
+}
+
+/// Plays nothing and logs what would have played, for running without a sound card.
+pub fn null_audio_backend() -> AudioBackend<'static> {
+    AudioBackend::Null { played: vec![] }
+}
+
+/// The last sounds the null backend has been asked to play, oldest first.
+pub fn played_sounds<'a>(backend: &'a AudioBackend<'_>) -> &'a [SoundEffect] {
+    match backend {
+        AudioBackend::Device { .. } => &[],
+        AudioBackend::Null { played } => played,
+    }
+}
+
+/// Queues a sound coming from somewhere in the world. Sounds from the ship itself just pass
+/// the player's own position and velocity.
+pub fn play_sound(audio: &mut Audio, effect: SoundEffect, pos: Vector2, vel: Vector2) {
+    audio.queue.push(SoundRequest { effect, pos, vel });
+}
+
+/// Volume, pan and pitch of a sound as heard from the player's ship. Pans relative to the way
+/// the ship is facing rather than the screen, fades out towards the edge of the audible
+/// range and shifts pitch up while the source and the ship are closing on each other.
+pub fn sound_mix(player: &Player, request: &SoundRequest) -> Option<SoundMix> {
+    let offset = request.pos - player.pos;
+    let distance = offset.length();
+    if distance >= AUDIBLE_RANGE {
+        return None;
+    }
+    let falloff = 1.0 - distance / AUDIBLE_RANGE;
+    let direction = if distance > 0.0 {
+        offset / distance
+    } else {
+        Vector2::zero()
+    };
+    let right = Vector2::new(-player.dir.y, player.dir.x);
+    let side = direction.dot(right) * f32::min(distance / PAN_RADIUS, 1.0);
+    let closing = (request.vel - player.vel).dot(-direction);
+    Some(SoundMix {
+        volume: falloff * falloff,
+        // raylib pans fully left at 1.0 and fully right at 0.0.
+        pan: 0.5 - side * 0.5,
+        pitch: (SPEED_OF_SOUND / (SPEED_OF_SOUND - closing).max(1.0)).clamp(MIN_PITCH, MAX_PITCH),
+    })
+}
+
+/// Cycles a volume setting in quarter steps, wrapping back round to silent.
+pub fn step_volume(volume: f32) -> f32 {
+    if volume >= 1.0 {
+        0.0
+    } else {
+        f32::min(volume + 0.25, 1.0)
+    }
+}
+
+/// How busy the music should be, from the enemies closing in scaled by the director's
+/// pressure. Bosses count as a full wave on their own.
+fn music_intensity(player: &Player, enemies: &Vec<Enemy>, director: &Director) -> f32 {
+    let threat: f32 = enemies
+        .iter()
+        .filter(|enemy| enemy.pos.distance_to(player.pos) < THREAT_RANGE)
+        .map(|enemy| if enemy.parts.is_empty() { 0.1 } else { 1.0 })
+        .sum();
+    let pressure = (director.pressure - MIN_PRESSURE) / (MAX_PRESSURE - MIN_PRESSURE);
+    (threat.min(1.0) * 0.75 + pressure * 0.25).clamp(0.0, 1.0)
+}
+
+fn start_voice(
+    audio: &mut Audio,
+    backend: &mut AudioBackend,
+    effect: SoundEffect,
+    volume: f32,
+    mix: &SoundMix,
+) {
+    let info = sound_info(&effect);
+    audio.voices.push(Voice {
+        effect,
+        time: info.length,
+    });
+    match backend {
+        AudioBackend::Device { sounds, next, .. } => {
+            let index = SOUND_EFFECTS
+                .iter()
+                .position(|other| *other == effect)
+                .unwrap();
+            let copies = &mut sounds[index];
+            if copies.is_empty() {
+                return;
+            }
+            // Round robin, so when every copy is busy the oldest one gets restarted.
+            let sound = &mut copies[next[index]];
+            sound.set_volume(volume * info.volume * mix.volume);
+            sound.set_pan(mix.pan);
+            sound.set_pitch(mix.pitch);
+            sound.play();
+            next[index] = (next[index] + 1) % copies.len();
+        }
+        AudioBackend::Null { played } => {
+            if played.len() >= MAX_RECORDED {
+                played.remove(0);
+            }
+            played.push(effect);
+        }
+    }
+}
+
+/// Plays this frame's queued sounds within the voice limits, keeps the low health alarm
+/// going and crossfades the music towards the current intensity.
+pub fn update_audio(
+    audio: &mut Audio,
+    backend: &mut AudioBackend,
+    settings: &Settings,
+    player: &Player,
+    enemies: &Vec<Enemy>,
+    director: &Director,
+    running: bool,
+    dt: f32,
+) {
+    let low_health = player
+        .parts
+        .iter()
+        .any(|part| part.health > 0.0 && part.health < part.starting_health * LOW_HEALTH);
+    if running && low_health {
+        audio.alarm_time -= dt;
+        if audio.alarm_time <= 0.0 {
+            play_sound(audio, SoundEffect::Alarm, player.pos, player.vel);
+            audio.alarm_time = ALARM_INTERVAL;
+        }
+    } else {
+        audio.alarm_time = 0.0;
+    }
+
+    for voice in &mut audio.voices {
+        voice.time -= dt;
+    }
+    audio.voices.retain(|voice| voice.time > 0.0);
+
+    let volume = settings.master_volume * settings.effects_volume;
+    let mut mixed: Vec<(SoundEffect, SoundMix)> = std::mem::take(&mut audio.queue)
+        .iter()
+        .filter_map(|request| Some((request.effect, sound_mix(player, request)?)))
+        .collect();
+    // Loudest first, so when a dozen bullets land on the same frame it's the closest one that
+    // gets heard.
+    mixed.sort_by(|a, b| b.1.volume.total_cmp(&a.1.volume));
+    let mut started: Vec<SoundEffect> = vec![];
+    for (effect, mix) in mixed {
+        if started.contains(&effect) || audio.voices.len() >= MAX_VOICES {
+            continue;
+        }
+        let playing = audio
+            .voices
+            .iter()
+            .filter(|voice| voice.effect == effect)
+            .count();
+        if playing >= sound_info(&effect).voices {
+            continue;
+        }
+        start_voice(audio, backend, effect, volume, &mix);
+        started.push(effect);
+    }
+
+    let target = if running {
+        music_intensity(player, enemies, director)
+    } else {
+        0.0
+    };
+    audio.intensity += (target - audio.intensity).clamp(-INTENSITY_RATE * dt, INTENSITY_RATE * dt);
+    if let AudioBackend::Device { calm, intense, .. } = backend {
+        let volume = settings.master_volume * settings.music_volume;
+        // The intense stem layers on top of the calm one, both loop together.
+        for (music, level) in [
+            (calm, 1.0 - audio.intensity * 0.4),
+            (intense, audio.intensity),
+        ] {
+            let Some(music) = music else {
+                continue;
+            };
+            if !music.is_stream_playing() {
+                music.play_stream();
+            }
+            music.set_volume(volume * level);
+            music.update_stream();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{init_player, new_director, Difficulty};
+
+    fn settings() -> Settings {
+        Settings {
+            damage_numbers: true,
+            difficulty: Difficulty::Normal,
+            adaptive: true,
+            master_volume: 1.0,
+            effects_volume: 1.0,
+            music_volume: 1.0,
+        }
+    }
+
+    fn frame(
+        audio: &mut Audio,
+        backend: &mut AudioBackend,
+        player: &Player,
+        effects: &[SoundEffect],
+        running: bool,
+        dt: f32,
+    ) {
+        for effect in effects {
+            play_sound(audio, *effect, player.pos, player.vel);
+        }
+        let director = new_director(player);
+        update_audio(
+            audio,
+            backend,
+            &settings(),
+            player,
+            &vec![],
+            &director,
+            running,
+            dt,
+        );
+    }
+
+    #[test]
+    fn same_effect_plays_once_per_frame() {
+        let player = init_player();
+        let mut audio = new_audio();
+        let mut backend = null_audio_backend();
+        frame(
+            &mut audio,
+            &mut backend,
+            &player,
+            &[
+                SoundEffect::Hit,
+                SoundEffect::Hit,
+                SoundEffect::Hit,
+                SoundEffect::PowerUp,
+            ],
+            true,
+            0.01,
+        );
+        assert_eq!(
+            played_sounds(&backend),
+            &[SoundEffect::Hit, SoundEffect::PowerUp]
+        );
+    }
+
+    #[test]
+    fn effects_stop_at_their_voice_limit() {
+        let player = init_player();
+        let mut audio = new_audio();
+        let mut backend = null_audio_backend();
+        for _ in 0..5 {
+            frame(
+                &mut audio,
+                &mut backend,
+                &player,
+                &[SoundEffect::PartDamage],
+                true,
+                0.001,
+            );
+        }
+        assert_eq!(
+            played_sounds(&backend).len(),
+            sound_info(&SoundEffect::PartDamage).voices
+        );
+    }
+
+    #[test]
+    fn voices_stop_at_max_voices() {
+        let player = init_player();
+        let mut audio = new_audio();
+        let mut backend = null_audio_backend();
+        // Every effect's own limit added up is more than MAX_VOICES.
+        let total: usize = SOUND_EFFECTS
+            .iter()
+            .map(|effect| sound_info(effect).voices)
+            .sum();
+        assert!(total > MAX_VOICES);
+        for _ in 0..10 {
+            frame(
+                &mut audio,
+                &mut backend,
+                &player,
+                &SOUND_EFFECTS,
+                true,
+                0.001,
+            );
+        }
+        assert_eq!(audio.voices.len(), MAX_VOICES);
+        assert_eq!(played_sounds(&backend).len(), MAX_VOICES);
+    }
+
+    #[test]
+    fn alarm_repeats_on_its_interval() {
+        let mut player = init_player();
+        player.parts[0].health = player.parts[0].starting_health * LOW_HEALTH * 0.5;
+        let mut audio = new_audio();
+        let mut backend = null_audio_backend();
+        // Three seconds is the first alarm straight away and two more after that.
+        for _ in 0..30 {
+            frame(&mut audio, &mut backend, &player, &[], true, 0.1);
+        }
+        let alarms = played_sounds(&backend)
+            .iter()
+            .filter(|effect| **effect == SoundEffect::Alarm)
+            .count();
+        assert_eq!(alarms, 3);
+    }
+
+    #[test]
+    fn alarm_is_quiet_while_paused_or_healthy() {
+        let mut player = init_player();
+        let mut audio = new_audio();
+        let mut backend = null_audio_backend();
+        for _ in 0..30 {
+            frame(&mut audio, &mut backend, &player, &[], true, 0.1);
+        }
+        player.parts[0].health = player.parts[0].starting_health * LOW_HEALTH * 0.5;
+        for _ in 0..30 {
+            frame(&mut audio, &mut backend, &player, &[], false, 0.1);
+        }
+        assert!(played_sounds(&backend).is_empty());
+    }
+
+    #[test]
+    fn null_backend_only_keeps_the_latest_sounds() {
+        let player = init_player();
+        let mut audio = new_audio();
+        let mut backend = null_audio_backend();
+        for _ in 0..MAX_RECORDED + 10 {
+            frame(
+                &mut audio,
+                &mut backend,
+                &player,
+                &[SoundEffect::Hit],
+                true,
+                1.0,
+            );
+        }
+        assert_eq!(played_sounds(&backend).len(), MAX_RECORDED);
+    }
+}
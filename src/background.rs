@@ -1,58 +1,196 @@
-use crate::Player;
-use perlin2d::PerlinNoise2D;
+use std::collections::HashMap;
+
+use crate::{log, Background, BackgroundLayer, LayerKind, LogCategory, LogLevel, Player};
 use rand::prelude::*;
 use raylib::prelude::*;
 
-pub fn draw_background(
-    d: &mut RaylibDrawHandle,
+const CHUNK_SIZE: i32 = 512;
+// Spreads the cost over a few frames after a jump instead of hitching.
+const MAX_NEW_CHUNKS: usize = 6;
+
+pub fn new_background(seed: u64) -> Background {
+    Background {
+        seed,
+        layers: vec![
+            BackgroundLayer {
+                parallax: 0.1,
+                kind: LayerKind::Stars,
+                chunks: HashMap::new(),
+            },
+            BackgroundLayer {
+                parallax: 0.35,
+                kind: LayerKind::Dust,
+                chunks: HashMap::new(),
+            },
+            BackgroundLayer {
+                parallax: 0.7,
+                kind: LayerKind::Debris,
+                chunks: HashMap::new(),
+            },
+        ],
+    }
+}
+
+/// splitmix64 over the seed, layer and cell, so neighbouring cells and cells on the axes
+/// all get unrelated streams.
+fn cell_seed(seed: u64, layer: usize, cell: (i32, i32)) -> u64 {
+    let mut hash = seed;
+    for value in [layer as u64, cell.0 as u32 as u64, cell.1 as u32 as u64] {
+        hash = hash.wrapping_add(value).wrapping_add(0x9E37_79B9_7F4A_7C15);
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        hash ^= hash >> 31;
+    }
+    hash
+}
+
+/// Where the layer's camera sits. Far layers move less than the player does.
+fn layer_camera(layer: &BackgroundLayer, player: &Player) -> Vector2 {
+    player.pos * layer.parallax
+}
+
+fn visible_cells(
+    layer: &BackgroundLayer,
+    player: &Player,
+    screenwidth: i32,
+    screenheight: i32,
+) -> Vec<(i32, i32)> {
+    let camera = layer_camera(layer, player);
+    let half = Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0);
+    let first = (camera - half) / CHUNK_SIZE as f32;
+    let last = (camera + half) / CHUNK_SIZE as f32;
+    let mut cells = vec![];
+    for x in first.x.floor() as i32..=last.x.floor() as i32 {
+        for y in first.y.floor() as i32..=last.y.floor() as i32 {
+            cells.push((x, y));
+        }
+    }
+    cells
+}
+
+fn draw_chunk(d: &mut impl RaylibDraw, kind: &LayerKind, rng: &mut StdRng) {
+    let size = CHUNK_SIZE as f32;
+    match kind {
+        LayerKind::Stars => {
+            for _ in 0..70 {
+                let pos = Vector2::new(rng.gen_range(0.0..size), rng.gen_range(0.0..size));
+                let tint = rng.gen_range(0..3);
+                let brightness = rng.gen_range(90..255);
+                let color = match tint {
+                    0 => Color::new(brightness, brightness, 255, 255),
+                    1 => Color::new(255, brightness, brightness / 2 + 100, 255),
+                    _ => Color::new(brightness, brightness, brightness, 255),
+                };
+                if rng.gen_range(0.0..1.0) < 0.05 {
+                    // A few bright ones with a soft glow.
+                    d.draw_circle_v(pos, 4.0, Color::new(color.r, color.g, color.b, 40));
+                    d.draw_circle_v(pos, 1.5, color);
+                } else {
+                    d.draw_rectangle_v(pos, Vector2::new(1.0, 1.0), color);
+                }
+            }
+        }
+        LayerKind::Dust => {
+            // Kept inside the chunk so the clouds never get cut off at its edge.
+            for _ in 0..rng.gen_range(2..6) {
+                let radius = rng.gen_range(40.0..120.0);
+                let pos = Vector2::new(
+                    rng.gen_range(radius..size - radius),
+                    rng.gen_range(radius..size - radius),
+                );
+                let color = if rng.gen_range(0.0..1.0) < 0.5 {
+                    Color::new(90, 60, 150, rng.gen_range(6..14))
+                } else {
+                    Color::new(50, 90, 150, rng.gen_range(6..14))
+                };
+                d.draw_circle_v(pos, radius, color);
+                d.draw_circle_v(pos, radius * 0.6, color);
+            }
+            for _ in 0..30 {
+                let pos = Vector2::new(rng.gen_range(0.0..size), rng.gen_range(0.0..size));
+                d.draw_rectangle_v(pos, Vector2::new(2.0, 2.0), Color::new(180, 170, 200, 60));
+            }
+        }
+        LayerKind::Debris => {
+            for _ in 0..rng.gen_range(0..6) {
+                let pos = Vector2::new(
+                    rng.gen_range(8.0..size - 8.0),
+                    rng.gen_range(8.0..size - 8.0),
+                );
+                let rect_size = Vector2::new(rng.gen_range(2.0..7.0), rng.gen_range(2.0..5.0));
+                let shade = rng.gen_range(80..150);
+                d.draw_rectangle_pro(
+                    Rectangle::new(pos.x, pos.y, rect_size.x, rect_size.y),
+                    rect_size / 2.0,
+                    rng.gen_range(0.0..360.0),
+                    Color::new(shade, shade, shade, 170),
+                );
+            }
+        }
+    }
+}
+
+/// Renders any newly visible chunks into their own textures and drops the ones that have
+/// scrolled off. Must run outside of drawing since it needs the handle to make textures.
+pub fn update_background(
+    background: &mut Background,
+    rl: &mut RaylibHandle,
+    thread: &RaylibThread,
     player: &Player,
     screenwidth: i32,
     screenheight: i32,
 ) {
-    let scale = 15;
-    let noise = PerlinNoise2D::new(1, 1.0, scale as f64, 1.0, 10.0, (1.0, 1.0), 0.0, 0);
-
-    let (sender, receiver) = std::sync::mpsc::channel::<(Vector2, Vector2, Color)>();
-    rayon::scope(|s| {
-        for x in -1..screenwidth / scale + 2 {
-            for y in -1..screenheight / scale + 2 {
-                let noise = &noise;
-                let sender = sender.clone();
-                s.spawn(move |_| {
-                    let x_screen = x * scale;
-                    let y_screen = y * scale;
-                    let x_world = x + player.pos.x as i32 / scale;
-                    let y_world = y + player.pos.y as i32 / scale;
-                    let mut rng = StdRng::seed_from_u64((x_world * y_world) as u64 + 1);
-                    let value = noise.get_noise(x_world as f64, y_world as f64);
-                    if rng.gen_range(0.0..1.0) < 0.1 {
-                        sender
-                            .send((
-                                Vector2::new(
-                                    x_screen as f32 - player.pos.x % scale as f32,
-                                    y_screen as f32 - player.pos.y % scale as f32,
-                                ) + Vector2::new(
-                                    rng.gen_range(-scale / 2..scale / 2) as f32,
-                                    rng.gen_range(-scale / 2..scale / 2) as f32,
-                                ),
-                                // + Vector2::new(-screenwidth as f32 / 2.0, 0.0),
-                                Vector2::new(4.0, 4.0),
-                                Color::new(
-                                    (300.0 * value) as u8,
-                                    (300.0 * value) as u8,
-                                    (300.0 * value) as u8,
-                                    (300.0 * value) as u8,
-                                ),
-                            ))
-                            .unwrap();
+    let mut created = 0;
+    for (layer_index, layer) in background.layers.iter_mut().enumerate() {
+        let cells = visible_cells(layer, player, screenwidth, screenheight);
+        layer.chunks.retain(|cell, _| cells.contains(cell));
+        for cell in cells {
+            if layer.chunks.contains_key(&cell) || created >= MAX_NEW_CHUNKS {
+                continue;
+            }
+            let mut texture =
+                match rl.load_render_texture(thread, CHUNK_SIZE as u32, CHUNK_SIZE as u32) {
+                    Ok(texture) => texture,
+                    Err(err) => {
+                        log(LogLevel::Warn, LogCategory::Assets, &err);
+                        continue;
                     }
-                });
+                };
+            let mut rng = StdRng::seed_from_u64(cell_seed(background.seed, layer_index, cell));
+            {
+                let mut handle = &mut *rl;
+                let mut d = handle.begin_texture_mode(thread, &mut texture);
+                d.clear_background(Color::BLANK);
+                draw_chunk(&mut d, &layer.kind, &mut rng);
             }
+            layer.chunks.insert(cell, texture);
+            created += 1;
         }
+    }
+}
 
-        drop(sender);
-        for (pos, size, color) in receiver {
-            d.draw_rectangle_v(pos, size, color);
+pub fn draw_background(
+    d: &mut RaylibDrawHandle,
+    background: &Background,
+    player: &Player,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    let half = Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0);
+    for layer in &background.layers {
+        let camera = layer_camera(layer, player);
+        for (cell, texture) in &layer.chunks {
+            let pos = Vector2::new(
+                (cell.0 * CHUNK_SIZE) as f32 - camera.x + half.x,
+                (cell.1 * CHUNK_SIZE) as f32 - camera.y + half.y,
+            );
+            // Render textures come out upside down, hence the negative source height.
+            d.draw_texture_rec(
+                texture,
+                Rectangle::new(0.0, 0.0, CHUNK_SIZE as f32, -CHUNK_SIZE as f32),
+                Vector2::new(pos.x.floor(), pos.y.floor()),
+                Color::WHITE,
+            );
         }
-    });
+    }
 }
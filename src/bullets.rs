@@ -1,73 +1,67 @@
 use raylib::prelude::*;
 
-use crate::{particalexplosion, vectortoangle, Bullet, Enemy, Partical, Player};
+use crate::{
+    add_damage_number, emit, enemy_dies, play_sound, push_rectangle, vectortoangle, Assets, Audio,
+    Bullet, DamageNumber, Enemy, Particals, Player, SoundEffect, SpriteBatch, BULLET_HIT,
+    BULLET_LAYER, PART_FLASH_TIME, PLAYER_HIT,
+};
 
-pub fn update_bullets(player: &mut Player, bullets: &mut Vec<Bullet>, enemies: &mut Vec<Enemy>,particals: &mut Vec<Partical>,dt:f32) {
+pub fn update_bullets(player: &mut Player, bullets: &mut Vec<Bullet>, enemies: &mut Vec<Enemy>,particals: &mut Particals,audio: &mut Audio,damage_numbers: &mut Vec<DamageNumber>,dt:f32) {
     for bullet in bullets {
         bullet.pos += bullet.vel * dt;
         bullet.time += dt;
         if bullet.friendly {
             for enemy in enemies.iter_mut() {
                 if bullet.pos.distance_to(enemy.pos) < bullet.size * 2.0 + enemy.size {
-                    enemy.health -=
-                        bullet.damage - bullet.time / bullet.duration * bullet.damage;
-                    particalexplosion(
-                        particals,
-                        bullet.pos,
-                        player.vel,
-                        0.0,
-                        600.0,
-                        50,
-                        Color {
-                            r: 255,
-                            g: 0,
-                            b: 0,
-                            a: 255,
-                        },
-                        Color {
-                            r: 255,
-                            g: 255,
-                            b: 50,
-                            a: 0,
-                        },
-                        0.1,
-                    );
+                    let damage = bullet.damage - bullet.time / bullet.duration * bullet.damage;
+                    if enemy.parts.is_empty() {
+                        enemy.health -= damage;
+                    } else {
+                        // The first part is the core, armored until every other part is destroyed.
+                        let exposed = enemy.parts[1..].iter().all(|part| part.health <= 0.0);
+                        let Some(part_index) = (0..enemy.parts.len()).find(|part_index| {
+                            let part = &enemy.parts[*part_index];
+                            part.health > 0.0 && (*part_index != 0 || exposed) && bullet.pos.distance_to(part.pos) < bullet.size * 2.0 + part.size
+                        }) else {
+                            continue;
+                        };
+                        let part = &mut enemy.parts[part_index];
+                        part.health -= damage;
+                        part.flash = PART_FLASH_TIME;
+                        if part.health <= 0.0 {
+                            enemy_dies(part.pos, enemy.vel, particals, audio);
+                        }
+                        enemy.health = enemy.parts[0].health;
+                    }
+                    if enemy.health <= 0.0 {
+                        enemy.killed_by_player = true;
+                    }
+                    add_damage_number(damage_numbers, bullet.pos, enemy.vel, damage, true);
+                    emit(particals, &BULLET_HIT, bullet.pos, player.vel);
+                    play_sound(audio, SoundEffect::Hit, bullet.pos, enemy.vel);
                 }
             }
         }
         if !bullet.friendly {
             for part in &mut player.parts {
                 if bullet.pos.distance_to(part.pos) < bullet.size * 2.0 + part.size {
-                    part.health -=
-                        bullet.damage - bullet.time / bullet.duration * bullet.damage;
-                    particalexplosion(
-                        particals,
-                        bullet.pos,
-                        player.vel,
-                        0.0,
-                        600.0,
-                        50,
-                        Color {
-                            r: 140,
-                            g: 255,
-                            b: 251,
-                            a: 255,
-                        },
-                        Color {
-                            r: 255,
-                            g: 0,
-                            b: 50,
-                            a: 0,
-                        },
-                        0.1,
-                    )
+                    if player.shield <= 0.0 {
+                        let damage = bullet.damage - bullet.time / bullet.duration * bullet.damage;
+                        part.health -= damage;
+                        part.flash = PART_FLASH_TIME;
+                        add_damage_number(damage_numbers, bullet.pos, player.vel, damage, false);
+                        play_sound(audio, SoundEffect::PartDamage, bullet.pos, player.vel);
+                    } else {
+                        play_sound(audio, SoundEffect::Hit, bullet.pos, player.vel);
+                    }
+                    emit(particals, &PLAYER_HIT, bullet.pos, player.vel)
                 }
             }
         }
     }
 }
 
-pub fn draw_bullets(d:&mut RaylibDrawHandle,player: &Player,bullets: &mut Vec<Bullet>,screenwidth:i32,screenheight:i32) {
+pub fn draw_bullets(batch: &mut SpriteBatch,assets: &Assets,player: &Player,bullets: &mut Vec<Bullet>,screenwidth:i32,screenheight:i32) {
     for bullet in bullets {
         let bullet_scale = 1.0 - bullet.time / bullet.duration;
         let bullet_width = bullet.size * bullet_scale;
@@ -76,7 +70,9 @@ pub fn draw_bullets(d:&mut RaylibDrawHandle,player: &Player,bullets: &mut Vec<Bu
         if !bullet.friendly {
             color = Color::RED
         }
-        d.draw_rectangle_pro(
+        push_rectangle(
+            batch,
+            assets,
             Rectangle::new(
                 bullet.pos.x - player.pos.x + screenwidth as f32 / 2.0,
                 bullet.pos.y - player.pos.y + screenheight as f32 / 2.0,
@@ -86,6 +82,7 @@ pub fn draw_bullets(d:&mut RaylibDrawHandle,player: &Player,bullets: &mut Vec<Bu
             Vector2::new(bullet_width, bullet_length),
             vectortoangle(bullet.vel).to_degrees() + 90.0,
             color,
+            BULLET_LAYER,
         )
     }
 }
\ No newline at end of file
@@ -0,0 +1,339 @@
+use rand::rngs::StdRng;
+use rand::Rng;
+use raylib::prelude::*;
+
+use crate::{
+    angletovector, apply_power_up, asset_path, heal_player, next_wave, record_typed, skip_wait,
+    spawn_group, Assets, Campaign, Console, DifficultyPreset, Enemy, FormationShape, Player,
+    PowerUpType, TimeControl, Wave, MAX_TIME_SCALE, MIN_TIME_SCALE,
+};
+
+/// Commands run as soon as the first run starts, one per line, `#` starts a comment.
+pub const CONSOLE_SCRIPT: &str = "console.txt";
+const MAX_LOG: usize = 200;
+const MAX_HISTORY: usize = 50;
+const CONSOLE_ROWS: usize = 14;
+const FONT_SIZE: f32 = 18.0;
+const DEFAULT_SPAWN_DISTANCE: f32 = 600.0;
+const HELP: [&str; 11] = [
+    "spawn <archetype> [count] [distance]",
+    "god",
+    "heal",
+    "kill_all",
+    "timescale <x>",
+    "give <repair|shield>",
+    "seed",
+    "set <field> <value>",
+    "wave next",
+    "clear",
+    "help",
+];
+const FIELDS: [&str; 4] = [
+    "speed_original",
+    "left_turn_original",
+    "right_turn_original",
+    "shield",
+];
+
+/// The console itself only opens in debug builds.
+pub fn console_enabled() -> bool {
+    cfg!(debug_assertions)
+}
+
+pub fn new_console(assets: &Assets) -> Console {
+    let path = asset_path(assets, CONSOLE_SCRIPT);
+    let script = match std::fs::read_to_string(&path) {
+        Ok(text) => text
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.to_string())
+            .collect(),
+        Err(_) => vec![],
+    };
+    Console {
+        open: false,
+        input: String::new(),
+        history: vec![],
+        browsing: None,
+        log: vec![],
+        script,
+        god: false,
+    }
+}
+
+pub fn console_print(console: &mut Console, text: String) {
+    console.log.push(text);
+    if console.log.len() > MAX_LOG {
+        console.log.remove(0);
+    }
+}
+
+/// Handles typing into the console and hands back anything entered this frame. The toggle key
+/// and typed characters are always drained so they don't pile up while it's closed.
+pub fn update_console(console: &mut Console, rl: &mut RaylibHandle) -> Option<String> {
+    if console_enabled() && rl.is_key_pressed(KeyboardKey::KEY_GRAVE) {
+        console.open = !console.open;
+    }
+    let mut typed = vec![];
+    while let Some(character) = rl.get_char_pressed() {
+        typed.push(character);
+    }
+    record_typed(&typed);
+    if !console.open {
+        return None;
+    }
+    if rl.is_key_pressed(KeyboardKey::KEY_ESCAPE) {
+        console.open = false;
+        return None;
+    }
+    console.input.extend(
+        typed
+            .iter()
+            .filter(|character| !character.is_control() && **character != '`'),
+    );
+    if rl.is_key_pressed(KeyboardKey::KEY_BACKSPACE) {
+        console.input.pop();
+    }
+    if rl.is_key_pressed(KeyboardKey::KEY_UP) && !console.history.is_empty() {
+        let index = match console.browsing {
+            Some(index) => index.saturating_sub(1),
+            None => console.history.len() - 1,
+        };
+        console.browsing = Some(index);
+        console.input = console.history[index].clone();
+    }
+    if rl.is_key_pressed(KeyboardKey::KEY_DOWN) {
+        if let Some(index) = console.browsing {
+            if index + 1 < console.history.len() {
+                console.browsing = Some(index + 1);
+                console.input = console.history[index + 1].clone();
+            } else {
+                console.browsing = None;
+                console.input.clear();
+            }
+        }
+    }
+    if !rl.is_key_pressed(KeyboardKey::KEY_ENTER) {
+        return None;
+    }
+    let line = std::mem::take(&mut console.input).trim().to_string();
+    console.browsing = None;
+    if line.is_empty() {
+        return None;
+    }
+    if console.history.last() != Some(&line) {
+        console.history.push(line.clone());
+        if console.history.len() > MAX_HISTORY {
+            console.history.remove(0);
+        }
+    }
+    Some(line)
+}
+
+fn find_archetype<'a>(archetypes: &'a Vec<Enemy>, name: &str) -> Option<&'a Enemy> {
+    // Names with spaces are typed with underscores, like mine_layer.
+    archetypes
+        .iter()
+        .find(|enemy| enemy.name.to_lowercase().replace(' ', "_") == name.to_lowercase())
+}
+
+/// Runs one console command, printing the result back into the console. Returns whether it
+/// changed the run, so the score can be kept off the tables.
+pub fn run_command(
+    console: &mut Console,
+    time_control: &mut TimeControl,
+    line: &str,
+    player: &mut Player,
+    enemies: &mut Vec<Enemy>,
+    archetypes: &Vec<Enemy>,
+    waves: &mut Vec<Wave>,
+    campaign: &mut Option<Campaign>,
+    difficulty: &DifficultyPreset,
+    rng: &mut StdRng,
+    seed: u64,
+) -> bool {
+    console_print(console, format!("> {line}"));
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let result = match words[..] {
+        ["help"] => Ok(format!("commands: {}", HELP.join(", "))),
+        ["clear"] => {
+            console.log.clear();
+            Ok(String::new())
+        }
+        ["spawn", name, ref rest @ ..] if rest.len() <= 2 => {
+            let count = rest.first().map_or(Ok(1), |count| count.parse::<usize>());
+            let distance = rest.get(1).map_or(Ok(DEFAULT_SPAWN_DISTANCE), |distance| {
+                distance.parse::<f32>()
+            });
+            match (find_archetype(archetypes, name), count, distance) {
+                (Some(enemy), Ok(count), Ok(distance)) => {
+                    let angle = rng.gen_range(-std::f32::consts::PI..std::f32::consts::PI);
+                    spawn_group(
+                        enemy,
+                        &FormationShape::Single,
+                        count,
+                        Some(angletovector(angle) * distance),
+                        player,
+                        enemies,
+                        difficulty,
+                        rng,
+                    );
+                    Ok(format!("spawned {count} {}", enemy.name))
+                }
+                (None, _, _) => Err(format!(
+                    "no archetype called {name}, try {}",
+                    archetypes
+                        .iter()
+                        .map(|enemy| enemy.name.to_lowercase().replace(' ', "_"))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )),
+                _ => Err("usage: spawn <archetype> [count] [distance]".to_string()),
+            }
+        }
+        ["god"] => {
+            console.god = !console.god;
+            Ok(format!(
+                "god mode {}",
+                if console.god { "on" } else { "off" }
+            ))
+        }
+        ["heal"] => {
+            heal_player(player);
+            Ok("healed".to_string())
+        }
+        ["kill_all"] => {
+            for enemy in enemies.iter_mut() {
+                enemy.health = -1.0;
+            }
+            Ok(format!("killed {}", enemies.len()))
+        }
+        ["timescale"] => Ok(format!("time scale is {}", time_control.time_scale)),
+        ["timescale", scale] => match scale.parse::<f32>() {
+            Ok(scale) if (MIN_TIME_SCALE..=MAX_TIME_SCALE).contains(&scale) => {
+                time_control.time_scale = scale;
+                Ok(format!("time scale set to {scale}"))
+            }
+            _ => Err(format!(
+                "time scale has to be between {MIN_TIME_SCALE} and {MAX_TIME_SCALE}"
+            )),
+        },
+        ["give", name] => {
+            let power_type = match name.to_lowercase().as_str() {
+                "repair" => Some(PowerUpType::Repair),
+                "shield" => Some(PowerUpType::Shield),
+                _ => None,
+            };
+            match power_type {
+                Some(power_type) => {
+                    apply_power_up(player, &power_type);
+                    Ok(format!("gave {name}"))
+                }
+                None => Err(format!("no power up called {name}, try repair or shield")),
+            }
+        }
+        ["seed"] => Ok(format!("seed {seed}")),
+        ["set", field, value] => match value.parse::<f32>() {
+            Ok(value) => {
+                let target = match field {
+                    "speed_original" => Some(&mut player.speed_original),
+                    "left_turn_original" => Some(&mut player.left_turn_original),
+                    "right_turn_original" => Some(&mut player.right_turn_original),
+                    "shield" => Some(&mut player.shield),
+                    _ => None,
+                };
+                match target {
+                    Some(target) => {
+                        *target = value;
+                        Ok(format!("{field} = {value}"))
+                    }
+                    None => Err(format!("can't set {field}, try {}", FIELDS.join(", "))),
+                }
+            }
+            Err(_) => Err(format!("{value} isn't a number")),
+        },
+        ["wave", "next"] => match campaign {
+            Some(campaign) => {
+                skip_wait(campaign);
+                Ok("skipped to the next event".to_string())
+            }
+            None => {
+                next_wave(waves, player, enemies, difficulty, rng);
+                Ok("spawned the next wave".to_string())
+            }
+        },
+        [] => Ok(String::new()),
+        _ => Err(format!("don't know {line}, try help")),
+    };
+    let changed = result.is_ok()
+        && !matches!(
+            words[..],
+            [] | ["help"] | ["clear"] | ["seed"] | ["timescale"]
+        );
+    match result {
+        Ok(text) if text.is_empty() => {}
+        Ok(text) => console_print(console, text),
+        Err(err) => console_print(console, format!("error: {err}")),
+    }
+    changed
+}
+
+/// Drops down from the top of the screen over everything else, newest output at the bottom.
+pub fn draw_console(
+    d: &mut RaylibDrawHandle,
+    console: &Console,
+    font: &WeakFont,
+    scale: f32,
+    screenwidth: i32,
+) {
+    if !console.open {
+        return;
+    }
+    let font_size = FONT_SIZE * scale;
+    let row_height = font_size + 4.0 * scale;
+    let height = row_height * (CONSOLE_ROWS + 1) as f32 + 8.0 * scale;
+    d.draw_rectangle_v(
+        Vector2::zero(),
+        Vector2::new(screenwidth as f32, height),
+        Color::new(0, 0, 0, 200),
+    );
+    d.draw_line_ex(
+        Vector2::new(0.0, height),
+        Vector2::new(screenwidth as f32, height),
+        scale,
+        Color::new(140, 255, 251, 120),
+    );
+    let start = console.log.len().saturating_sub(CONSOLE_ROWS);
+    for (row, line) in console.log[start..].iter().enumerate() {
+        let color = if line.starts_with("error") {
+            Color::new(236, 28, 36, 255)
+        } else if line.starts_with('>') {
+            Color::GRAY
+        } else {
+            Color::WHITE
+        };
+        d.draw_text_ex(
+            font,
+            line,
+            Vector2::new(8.0 * scale, 4.0 * scale + row as f32 * row_height),
+            font_size,
+            font_size / 10.0,
+            color,
+        );
+    }
+    // Blinking cursor at the end of the input line.
+    let cursor = if (d.get_time() * 2.0) as i64 % 2 == 0 {
+        "_"
+    } else {
+        ""
+    };
+    d.draw_text_ex(
+        font,
+        &format!("> {}{cursor}", console.input),
+        Vector2::new(8.0 * scale, 4.0 * scale + CONSOLE_ROWS as f32 * row_height),
+        font_size,
+        font_size / 10.0,
+        Color::GOLD,
+    );
+}
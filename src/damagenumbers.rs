@@ -0,0 +1,95 @@
+use rand::Rng;
+use raylib::prelude::*;
+
+use crate::{colorlerp, DamageNumber, HitMarker, Player};
+
+pub fn add_damage_number(
+    damage_numbers: &mut Vec<DamageNumber>,
+    pos: Vector2,
+    vel: Vector2,
+    amount: f32,
+    friendly: bool,
+) {
+    if amount <= 0.0 {
+        return;
+    }
+    damage_numbers.push(DamageNumber {
+        pos,
+        vel: vel + Vector2::new(rand::thread_rng().gen_range(-30.0..30.0), -80.0),
+        amount,
+        friendly,
+        duration: 0.8,
+        time: 0.0,
+    });
+}
+
+pub fn update_damage_numbers(
+    damage_numbers: &mut Vec<DamageNumber>,
+    hit_markers: &mut Vec<HitMarker>,
+    dt: f32,
+) {
+    for damage_number in &mut *damage_numbers {
+        damage_number.pos += damage_number.vel * dt;
+        // Bleed off the inherited ship velocity so the number settles near the hit.
+        damage_number.vel -= damage_number.vel * 3.0 * dt;
+        damage_number.vel.y -= 40.0 * dt;
+        damage_number.time += dt;
+    }
+    damage_numbers.retain(|damage_number| damage_number.time < damage_number.duration);
+    for hit_marker in &mut *hit_markers {
+        hit_marker.time += dt;
+    }
+    hit_markers.retain(|hit_marker| hit_marker.time < hit_marker.duration);
+}
+
+pub fn draw_damage_numbers(
+    d: &mut RaylibDrawHandle,
+    player: &Player,
+    damage_numbers: &Vec<DamageNumber>,
+    hit_markers: &Vec<HitMarker>,
+    font: &WeakFont,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    for damage_number in damage_numbers {
+        let fade = 1.0 - damage_number.time / damage_number.duration;
+        let font_size = 14.0 + 8.0 * f32::min(damage_number.amount, 3.0);
+        let color = if damage_number.friendly {
+            colorlerp(
+                Color::WHITE,
+                Color::GOLD,
+                f32::min(damage_number.amount / 2.0, 1.0),
+            )
+        } else {
+            Color::RED
+        };
+        let text = format!("{:.1}", damage_number.amount);
+        let text_size = font.measure_text(&text, font_size, 1.0);
+        d.draw_text_ex(
+            font,
+            &text,
+            damage_number.pos - player.pos
+                + Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0)
+                - text_size / 2.0,
+            font_size,
+            1.0,
+            Color::new(color.r, color.g, color.b, (255.0 * fade) as u8),
+        );
+    }
+    for hit_marker in hit_markers {
+        let t = hit_marker.time / hit_marker.duration;
+        let pos = hit_marker.pos - player.pos
+            + Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0);
+        let inner = 8.0 + 12.0 * t;
+        let outer = inner + 10.0;
+        let color = Color::new(255, 255, 255, (255.0 * (1.0 - t)) as u8);
+        for corner in [
+            Vector2::new(1.0, 1.0),
+            Vector2::new(1.0, -1.0),
+            Vector2::new(-1.0, 1.0),
+            Vector2::new(-1.0, -1.0),
+        ] {
+            d.draw_line_ex(pos + corner * inner, pos + corner * outer, 3.0, color);
+        }
+    }
+}
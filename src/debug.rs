@@ -1,12 +1,20 @@
 use raylib::prelude::*;
 
-use crate::{colorlerp, Enemy, Partical, Player};
+use crate::{
+    colorlerp, difficulty_name, played_sounds, Audio, AudioBackend, Director, Enemy, Particals,
+    Player, Settings, SpriteBatch, MAX_VOICES, PARTICAL_BUDGET,
+};
 
 pub fn draw_debug_text(
     d: &mut RaylibDrawHandle,
     player: &Player,
     enemies: &Vec<Enemy>,
-    particals: &Vec<Partical>,
+    particals: &Particals,
+    audio: &Audio,
+    audio_backend: &AudioBackend,
+    settings: &Settings,
+    director: &Director,
+    sprite_batch: &SpriteBatch,
     dt: f32,
 ) {
     d.draw_text(
@@ -45,7 +53,7 @@ pub fn draw_debug_text(
         Color::WHITE,
     );
     d.draw_text(
-        format!("Particals: {}", particals.len()).as_str(),
+        format!("Particals: {}/{}", particals.count, PARTICAL_BUDGET).as_str(),
         5,
         110,
         18,
@@ -72,6 +80,69 @@ pub fn draw_debug_text(
         18,
         Color::WHITE,
     );
+    d.draw_text(
+        format!(
+            "Difficulty: {} (adaptive {})",
+            difficulty_name(&settings.difficulty),
+            settings.adaptive
+        )
+        .as_str(),
+        5,
+        190,
+        18,
+        Color::WHITE,
+    );
+    d.draw_text(
+        format!("Pressure: {:.2}", director.pressure).as_str(),
+        5,
+        210,
+        18,
+        Color::WHITE,
+    );
+    d.draw_text(
+        format!(
+            "Kill Rate: {:.2}/s  Damage Rate: {:.3}/s  Health: {:.0}%",
+            director.kill_rate,
+            director.damage_rate,
+            director.health * 100.0
+        )
+        .as_str(),
+        5,
+        230,
+        18,
+        Color::WHITE,
+    );
+    let backend = match audio_backend {
+        AudioBackend::Device { .. } => "device".to_string(),
+        AudioBackend::Null { .. } => {
+            format!("null, {} recorded", played_sounds(audio_backend).len())
+        }
+    };
+    d.draw_text(
+        format!(
+            "Voices: {}/{}  Music: {:.2}  Audio: {}",
+            audio.voices.len(),
+            MAX_VOICES,
+            audio.intensity,
+            backend
+        )
+        .as_str(),
+        5,
+        250,
+        18,
+        Color::WHITE,
+    );
+    d.draw_text(
+        format!(
+            "Sprites: {}  Draw calls: {}",
+            sprite_batch.drawn, sprite_batch.draw_calls
+        )
+        .as_str(),
+        5,
+        270,
+        18,
+        Color::WHITE,
+    );
 }
 
 pub fn draw_debug_enemies(
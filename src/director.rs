@@ -0,0 +1,100 @@
+use crate::{Difficulty, DifficultyPreset, Director, Enemy, Player, Score, Settings};
+
+pub const MIN_PRESSURE: f32 = 0.6;
+pub const MAX_PRESSURE: f32 = 1.6;
+
+pub fn difficulty_preset(difficulty: &Difficulty) -> DifficultyPreset {
+    match difficulty {
+        Difficulty::Easy => DifficultyPreset {
+            spawn_rate: 0.7,
+            enemy_health: 0.75,
+            enemy_speed: 0.85,
+            bullet_damage: 0.6,
+        },
+        Difficulty::Normal => DifficultyPreset {
+            spawn_rate: 1.0,
+            enemy_health: 1.0,
+            enemy_speed: 1.0,
+            bullet_damage: 1.0,
+        },
+        Difficulty::Hard => DifficultyPreset {
+            spawn_rate: 1.4,
+            enemy_health: 1.3,
+            enemy_speed: 1.15,
+            bullet_damage: 1.5,
+        },
+    }
+}
+
+pub fn difficulty_name(difficulty: &Difficulty) -> &'static str {
+    match difficulty {
+        Difficulty::Easy => "Easy",
+        Difficulty::Normal => "Normal",
+        Difficulty::Hard => "Hard",
+    }
+}
+
+pub fn new_director(player: &Player) -> Director {
+    Director {
+        pressure: 1.0,
+        kill_rate: 0.0,
+        damage_rate: 0.0,
+        health: 1.0,
+        last_kills: 0,
+        last_health: player.parts.iter().map(|part| part.health).sum(),
+    }
+}
+
+/// The selected preset with the director's pressure folded into spawn rate and enemy health.
+pub fn current_difficulty(settings: &Settings, director: &Director) -> DifficultyPreset {
+    let mut preset = difficulty_preset(&settings.difficulty);
+    if settings.adaptive {
+        preset.spawn_rate *= director.pressure;
+        preset.enemy_health *= director.pressure;
+    }
+    preset
+}
+
+/// Tracks smoothed kill and damage rates and eases pressure up while the player is coping
+/// and down while they are getting hurt.
+pub fn update_director(director: &mut Director, player: &Player, score: &Score, dt: f32) {
+    let mut health = 0.0;
+    let mut total_health = 0.0;
+    for part in &player.parts {
+        health += f32::max(part.health, 0.0);
+        total_health += part.starting_health;
+    }
+    let damage = f32::max(director.last_health - health, 0.0);
+    let kills = (score.kills - director.last_kills) as f32;
+    director.last_health = health;
+    director.last_kills = score.kills;
+
+    // Exponential moving averages over roughly the last ten seconds.
+    let blend = f32::min(dt / 10.0, 1.0);
+    director.kill_rate += (kills / dt.max(0.001) - director.kill_rate) * blend;
+    director.damage_rate += (damage / dt.max(0.001) - director.damage_rate) * blend;
+    director.health = health / total_health;
+
+    let target =
+        1.0 + director.kill_rate * 0.1 - director.damage_rate * 2.0 + (director.health - 0.5) * 0.6;
+    let target = target.clamp(MIN_PRESSURE, MAX_PRESSURE);
+    // Move slowly so the player feels a trend rather than a switch.
+    director.pressure += (target - director.pressure).clamp(-0.05 * dt, 0.05 * dt);
+    director.pressure = director.pressure.clamp(MIN_PRESSURE, MAX_PRESSURE);
+}
+
+pub fn scale_enemy(enemy: &mut Enemy, difficulty: &DifficultyPreset) {
+    enemy.health *= difficulty.enemy_health;
+    enemy.speed *= difficulty.enemy_speed;
+    enemy.blast_damage *= difficulty.bullet_damage;
+    for part in &mut enemy.parts {
+        part.health *= difficulty.enemy_health;
+        part.starting_health *= difficulty.enemy_health;
+    }
+    for bullet_emmiter in &mut enemy.bullet_emmiters {
+        bullet_emmiter.damage *= difficulty.bullet_damage;
+    }
+    for spawner in &mut enemy.spawners {
+        scale_enemy(&mut spawner.enemy, difficulty);
+    }
+}
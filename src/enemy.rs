@@ -1,21 +1,57 @@
+use rand::rngs::StdRng;
 use rand::Rng;
-use slotmap::SlotMap;
 
 use crate::{
-    angletovector, enemy_dies, get_2_mut, particalexplosion, rotatevector, vectortoangle, Bullet,
-    Enemy, Partical, Player, TextureID,
+    angletovector, damage_mods, draw_part_damage, emit, enemy_dies, environment_at, flock_steering,
+    formation_target, get_2_mut, intercept_point, ion_turn, play_sound, push_animation,
+    push_texture, rotatevector, spawn_partical, update_part_damage, vectortoangle, Assets, Audio,
+    Bullet, BulletEmitter, Enemy, ParticalPreset, Particals, Player, SoundEffect, SpriteBatch,
+    TextureID, World, CHARGE_SPARK, DETONATION, ENEMY_DAMAGE_LAYER, ENEMY_LAYER, EXPIRE_PUFF,
+    FLOCK_RADIUS, MARKER_LAYER, PART_FLASH_TIME, RAM_IMPACT, TURRET_LAYER,
 };
 use raylib::prelude::*;
 
+/// How far through its wind-up an emitter is, from 0 until the wind-up starts to 1 as it fires.
+pub fn windup_charge(bullet_emmiter: &BulletEmitter) -> f32 {
+    if bullet_emmiter.windup <= 0.0 {
+        return 0.0;
+    }
+    (1.0 - (bullet_emmiter.bullet_interval - bullet_emmiter.time) / bullet_emmiter.windup)
+        .clamp(0.0, 1.0)
+}
+
 pub fn update_enemies(
     player: &mut Player,
     enemies: &mut Vec<Enemy>,
-    particals: &mut Vec<Partical>,
+    particals: &mut Particals,
+    audio: &mut Audio,
     bullets: &mut Vec<Bullet>,
+    world: &World,
+    rng: &mut StdRng,
     dt: f32,
 ) {
+    let mut spawned: Vec<Enemy> = vec![];
     for enemy_index in 0..enemies.len() {
+        let formation_target = formation_target(enemies, enemy_index);
+        let flock = flock_steering(enemies, enemy_index);
         let enemy = &mut enemies[enemy_index];
+        let mods = damage_mods(
+            &enemy.parts,
+            &enemy.damage,
+            enemy.partical_emmiters.len(),
+            enemy.bullet_emmiters.len(),
+        );
+        // Every part a boss loses pushes it into a faster, harder firing phase.
+        let phase = enemy.parts.iter().filter(|part| part.health <= 0.0).count();
+        if phase > enemy.phase {
+            enemy.phase = phase;
+            for part in &mut enemy.parts {
+                part.flash = PART_FLASH_TIME * 3.0;
+            }
+        }
+        let enrage = 1.0 + 0.25 * enemy.phase as f32;
+        let environment = environment_at(world, enemy.pos);
+        let ion = 1.0 - environment.ion * 0.6;
         let right = rotatevector(enemy.dir, std::f32::consts::PI / 2.0);
         let sign = if right.dot(player.pos - enemy.pos) > 0.0 {
             1.0
@@ -23,11 +59,13 @@ pub fn update_enemies(
             -1.0
         };
         if enemy.predictive {
-            let mut time_to_reach = 0.0;
-            for _ in 0..10 {
-                enemy.targetpos = player.pos + player.dir * player.vel.length() * time_to_reach;
-                time_to_reach = enemy.targetpos.distance_to(enemy.pos) / enemy.vel.length();
-            }
+            // Steer for where the player will be if we close at full speed.
+            enemy.targetpos = intercept_point(
+                enemy.pos,
+                player.pos,
+                player.vel,
+                f32::max(enemy.vel.length(), enemy.speed),
+            );
         } else {
             if enemy.name == "Basic".to_string() {
                 enemy.targetpos = player.pos
@@ -40,25 +78,91 @@ pub fn update_enemies(
                         std::f32::consts::TAU / 8.0 * sign,
                     ) * 200.0
             }
+
+            if enemy.name == "Mothership".to_string() {
+                enemy.targetpos = player.pos
+                    + rotatevector(
+                        (enemy.pos - player.pos).normalized(),
+                        std::f32::consts::TAU / 16.0 * sign,
+                    ) * 450.0
+            }
+
+            if enemy.name == "Sniper".to_string() || enemy.name == "Carrier".to_string() {
+                let range = if enemy.name == "Sniper".to_string() {
+                    900.0
+                } else {
+                    600.0
+                };
+                enemy.targetpos = player.pos
+                    + rotatevector(
+                        (enemy.pos - player.pos).normalized(),
+                        std::f32::consts::TAU / 8.0 * sign,
+                    ) * range
+            }
+
+            // Cut across ahead of the player so the mines land in their path.
+            if enemy.name == "Mine Layer".to_string() {
+                enemy.targetpos = player.pos + player.vel * 1.5
+            }
+        }
+        match formation_target {
+            Some(target) => enemy.targetpos = target,
+            None => {
+                if enemy.formation.as_ref().is_some_and(|slot| !slot.leader) {
+                    enemy.formation = None;
+                }
+                if enemy.flocking {
+                    enemy.targetpos = enemy.pos
+                        + ((enemy.targetpos - enemy.pos).normalized() + flock) * FLOCK_RADIUS;
+                }
+            }
         }
         if right.dot(enemy.targetpos - enemy.pos) > 0.0 {
-            enemy.dir =
-                angletovector(vectortoangle(enemy.dir) + (enemy.turningspeed.to_radians() * dt))
+            enemy.dir = angletovector(
+                vectortoangle(enemy.dir)
+                    + (enemy.turningspeed * mods.right_turn * ion).to_radians() * dt,
+            );
+            enemy.animator.steer = 1.0;
         } else {
-            enemy.dir =
-                angletovector(vectortoangle(enemy.dir) - (enemy.turningspeed.to_radians() * dt))
+            enemy.dir = angletovector(
+                vectortoangle(enemy.dir)
+                    - (enemy.turningspeed * mods.left_turn * ion).to_radians() * dt,
+            );
+            enemy.animator.steer = -1.0;
         }
+        enemy.dir = ion_turn(enemy.dir, &environment, rng, dt);
+        let mut speed = enemy.speed * mods.speed * enrage;
+        let distance = enemy.pos.distance_to(player.pos);
+        if enemy.name == "Kamikaze".to_string() && distance < 500.0 {
+            speed *= 1.6;
+        }
+        if enemy.blast_radius > 0.0 && distance < enemy.blast_radius * 0.5 {
+            enemy.health = -1.0;
+        }
+        enemy.lifetime -= dt;
         enemy.vel += enemy.dir.normalized()
-            * (enemy.speed
+            * (speed
                 - (enemy.vel.length() * (2.0 + (enemy.vel.normalized().dot(enemy.dir) - 1.0))
                     / 2.0))
             * dt;
 
         enemy.vel -= right * (right.dot(enemy.vel)) * enemy.friction * dt;
+        enemy.vel += environment.gravity * dt;
+        enemy.vel -= enemy.vel * f32::min(environment.drag * dt, 1.0);
 
         enemy.pos += enemy.vel * dt;
 
-        for partical_emmiter in &mut enemy.partical_emmiters {
+        for part in &mut enemy.parts {
+            part.pos = enemy.pos
+                + rotatevector(
+                    part.location,
+                    vectortoangle(enemy.dir) - std::f32::consts::PI / 2.0,
+                )
+        }
+        update_part_damage(&mut enemy.parts, enemy.vel, particals, dt);
+
+        for (index, partical_emmiter) in enemy.partical_emmiters.iter_mut().enumerate() {
+            partical_emmiter.speed = partical_emmiter.speed_orginal * mods.partical[index];
             partical_emmiter.pos = enemy.pos
                 + rotatevector(
                     partical_emmiter.location,
@@ -71,66 +175,110 @@ pub fn update_enemies(
                 ) * rand::thread_rng().gen_range(20.0..40.0);
             partical_emmiter.time += dt;
             while partical_emmiter.time > partical_emmiter.partical_interval {
-                particals.push(Partical {
-                    pos: partical_emmiter.pos,
-                    vel: partical_emmiter.vel,
-                    size: partical_emmiter.size,
-                    shape: partical_emmiter.shape.clone(),
-                    starting_color: partical_emmiter.starting_color,
-                    ending_color: partical_emmiter.ending_color,
-                    duration: partical_emmiter.duration,
-                    time: 0.0,
-                });
+                spawn_partical(
+                    particals,
+                    &partical_emmiter.preset,
+                    partical_emmiter.pos,
+                    partical_emmiter.vel,
+                );
                 partical_emmiter.time -= partical_emmiter.partical_interval;
             }
         }
         for part in &mut player.parts {
             if enemy.pos.distance_to(part.pos) < part.size + enemy.size {
-                enemy.health = -1.0;
-                part.health -= 1.0;
-                particalexplosion(
-                    particals,
-                    part.pos,
-                    player.vel,
-                    0.0,
-                    300.0,
-                    500,
-                    Color {
-                        r: 140,
-                        g: 255,
-                        b: 251,
-                        a: 255,
-                    },
-                    Color {
-                        r: 255,
-                        g: 0,
-                        b: 50,
-                        a: 0,
-                    },
-                    1.0,
+                if enemy.parts.is_empty() {
+                    enemy.health = -1.0;
+                } else {
+                    // Bosses survive ramming and shove the player away instead.
+                    player.vel += (part.pos - enemy.pos).normalized() * 600.0;
+                    // The shove takes a few frames to get clear, only the first one hurts.
+                    if part.flash > 0.0 {
+                        continue;
+                    }
+                }
+                if player.shield <= 0.0 {
+                    part.health -= 1.0;
+                    part.flash = PART_FLASH_TIME;
+                    play_sound(audio, SoundEffect::PartDamage, part.pos, player.vel);
+                }
+                emit(particals, &RAM_IMPACT, part.pos, player.vel);
+            }
+        }
+        for spawner in &mut enemy.spawners {
+            spawner.time += dt;
+            while spawner.time > spawner.interval {
+                let offset = rotatevector(
+                    spawner.location,
+                    vectortoangle(enemy.dir) - std::f32::consts::PI / 2.0,
                 );
+                let mut child = (*spawner.enemy).clone();
+                child.pos = enemy.pos + offset;
+                child.vel = offset.normalized() * spawner.launch_speed;
+                child.dir = enemy.dir;
+                spawned.push(child);
+                spawner.time -= spawner.interval;
             }
         }
-        for bullet_emmiter in &mut enemy.bullet_emmiters {
+        // Turret guns swing round to face the player, boss guns are fixed to the hull.
+        let facing = if enemy.parts.is_empty() {
+            player.pos - enemy.pos
+        } else {
+            enemy.dir
+        };
+        for (index, bullet_emmiter) in enemy.bullet_emmiters.iter_mut().enumerate() {
             bullet_emmiter.pos = enemy.pos
                 + rotatevector(
                     bullet_emmiter.location,
-                    vectortoangle(player.pos - enemy.pos) - std::f32::consts::PI / 2.0,
+                    vectortoangle(facing) - std::f32::consts::PI / 2.0,
                 );
-            let vel =
-                (enemy.vel + player.vel) / 2.0 + (player.pos - enemy.pos).normalized() * 1000.0;
-            bullet_emmiter.time += dt;
+            // Bullets inherit the enemy's velocity, so lead against the player's relative motion.
+            let lead = intercept_point(
+                bullet_emmiter.pos,
+                player.pos,
+                player.vel - enemy.vel,
+                bullet_emmiter.speed,
+            );
+            bullet_emmiter.aim = player.pos + (lead - player.pos) * enemy.accuracy;
+            if mods.gun[index] <= 0.0 {
+                // A destroyed gun shouldn't sit frozen mid wind-up.
+                bullet_emmiter.time = 0.0;
+            }
+            bullet_emmiter.time += dt * mods.gun[index] * enrage;
+            let charge = windup_charge(bullet_emmiter);
+            if charge > 0.0 && rand::thread_rng().gen_range(0.0..1.0) < dt * 60.0 {
+                // Sparks get sucked into the muzzle while the shot charges.
+                let offset = angletovector(
+                    rand::thread_rng().gen_range(-std::f32::consts::PI..std::f32::consts::PI),
+                ) * (10.0 + 20.0 * charge);
+                spawn_partical(
+                    particals,
+                    &ParticalPreset {
+                        start_size: 2.0 + 3.0 * charge,
+                        ..CHARGE_SPARK
+                    },
+                    bullet_emmiter.pos + offset,
+                    enemy.vel - offset / 0.2,
+                );
+            }
             while bullet_emmiter.time > bullet_emmiter.bullet_interval {
                 if true {
+                    let jitter = rng
+                        .gen_range(-enemy.aim_jitter..=enemy.aim_jitter)
+                        .to_radians();
                     bullets.push(Bullet {
                         pos: bullet_emmiter.pos,
-                        vel: vel,
+                        vel: enemy.vel
+                            + rotatevector(
+                                (bullet_emmiter.aim - bullet_emmiter.pos).normalized(),
+                                jitter,
+                            ) * bullet_emmiter.speed,
                         size: bullet_emmiter.size,
                         damage: bullet_emmiter.damage,
                         friendly: bullet_emmiter.friendly,
                         duration: bullet_emmiter.duration,
                         time: 0.0,
                     });
+                    play_sound(audio, SoundEffect::EnemyFire, bullet_emmiter.pos, enemy.vel);
                 }
                 bullet_emmiter.time -= bullet_emmiter.bullet_interval;
             }
@@ -148,25 +296,67 @@ pub fn update_enemies(
             else {
                 continue;
             };
+            // Mines only go off for the player, otherwise layers would blow up on their own.
+            if enemy.name == "Mine".to_string() || other_enemy.name == "Mine".to_string() {
+                continue;
+            }
             if enemy.pos.distance_to(other_enemy.pos) < other_enemy.size + enemy.size {
-                enemy.health = -1.0;
-                other_enemy.health = -1.0;
+                if enemy.parts.is_empty() {
+                    enemy.health = -1.0;
+                }
+                if other_enemy.parts.is_empty() {
+                    other_enemy.health = -1.0;
+                }
             };
         }
     }
-    for enemy in enemies {
+    for enemy in enemies.iter() {
         if enemy.health <= 0.0 {
-            enemy_dies(enemy.pos, enemy.vel, particals);
+            enemy_dies(enemy.pos, enemy.vel, particals, audio);
+            if enemy.blast_radius > 0.0 {
+                detonate(enemy, player, particals, audio);
+            }
+        } else if enemy.lifetime <= 0.0 {
+            emit(particals, &EXPIRE_PUFF, enemy.pos, enemy.vel);
         }
     }
+    // Expired enemies fizzle out without counting as kills.
+    enemies.retain(|enemy| enemy.lifetime > 0.0 || enemy.health <= 0.0);
+    enemies.append(&mut spawned);
 }
 
+/// Area damage that falls off towards the edge of the blast, blocked by the shield.
+fn detonate(enemy: &Enemy, player: &mut Player, particals: &mut Particals, audio: &mut Audio) {
+    if player.shield <= 0.0 {
+        for part in &mut player.parts {
+            let distance = part.pos.distance_to(enemy.pos);
+            if distance < enemy.blast_radius {
+                part.health -= enemy.blast_damage * (1.0 - distance / enemy.blast_radius);
+                part.flash = PART_FLASH_TIME;
+                play_sound(audio, SoundEffect::PartDamage, part.pos, player.vel);
+            }
+        }
+    }
+    emit(
+        particals,
+        &ParticalPreset {
+            force_max: enemy.blast_radius * 3.0,
+            ..DETONATION
+        },
+        enemy.pos,
+        enemy.vel,
+    );
+}
+
+/// Shapes are drawn straight away, the sprites go into the batch.
 pub fn draw_enemies(
     d: &mut RaylibDrawHandle,
+    batch: &mut SpriteBatch,
+    assets: &Assets,
     player: &Player,
     enemies: &Vec<Enemy>,
-    textures: &SlotMap<TextureID, Texture2D>,
-    enemy_warning_image: &Texture2D,
+    world: &World,
+    enemy_warning_texture: TextureID,
     screenwidth: i32,
     screenheight: i32,
 ) {
@@ -175,58 +365,85 @@ pub fn draw_enemies(
             enemy.pos.x - player.pos.x + screenwidth as f32 / 2.0,
             enemy.pos.y - player.pos.y + screenheight as f32 / 2.0,
         );
-        let image: &Texture2D = &textures[enemy.texture_id];
+        if enemy.name == "Mine".to_string() {
+            // Pulse the blast radius so the hazard reads from a distance.
+            let pulse = (enemy.lifetime * 4.0).sin() * 0.5 + 0.5;
+            d.draw_ring(
+                pos,
+                enemy.blast_radius - 2.0,
+                enemy.blast_radius,
+                0.0,
+                360.0,
+                48,
+                Color::new(236, 28, 36, (40.0 + 80.0 * pulse) as u8),
+            );
+        }
+        for bullet_emmiter in &enemy.bullet_emmiters {
+            let charge = windup_charge(bullet_emmiter);
+            if charge > 0.0 && bullet_emmiter.aim_line {
+                // Run the line out to the bullet's full range along the aim.
+                let start = bullet_emmiter.pos - player.pos
+                    + Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0);
+                let end = start
+                    + (bullet_emmiter.aim - bullet_emmiter.pos).normalized()
+                        * bullet_emmiter.speed
+                        * bullet_emmiter.duration;
+                d.draw_line_ex(
+                    start,
+                    end,
+                    1.0 + 2.0 * charge,
+                    Color::new(236, 28, 36, (255.0 * charge) as u8),
+                );
+            }
+        }
 
-        d.draw_texture_pro(
-            image,
-            Rectangle::new(0.0, 0.0, image.width as f32, image.height as f32),
-            Rectangle::new(
-                pos.x,
-                pos.y,
-                image.width as f32 * enemy.texture_scale,
-                image.height as f32 * enemy.texture_scale,
-            ),
-            Vector2::new(
-                image.width as f32 / 2.0 * enemy.texture_scale,
-                image.height as f32 / 2.0 * enemy.texture_scale,
-            ),
+        push_animation(
+            batch,
+            assets,
+            &enemy.animator,
+            enemy.texture_id,
+            pos,
+            enemy.texture_scale,
             vectortoangle(enemy.dir).to_degrees() + 90.0,
-            Color::WHITE,
+            ENEMY_LAYER,
+        );
+        draw_part_damage(
+            batch,
+            assets,
+            enemy.texture_id,
+            enemy.texture_scale,
+            pos,
+            enemy.pos,
+            enemy.dir,
+            &enemy.parts,
+            ENEMY_DAMAGE_LAYER,
         );
         if enemy.name == "Turret".to_string() {
-            d.draw_texture_pro(
-                &textures[enemy.extra_texture_ids[0]],
-                Rectangle::new(
-                    0.0,
-                    0.0,
-                    textures[enemy.extra_texture_ids[0]].width as f32,
-                    textures[enemy.extra_texture_ids[0]].height as f32,
-                ),
-                Rectangle::new(
-                    pos.x,
-                    pos.y + 1.0,
-                    textures[enemy.extra_texture_ids[0]].width as f32 * enemy.texture_scale,
-                    textures[enemy.extra_texture_ids[0]].height as f32 * enemy.texture_scale,
-                ),
-                Vector2::new(
-                    textures[enemy.extra_texture_ids[0]].width as f32 / 2.0 * enemy.texture_scale,
-                    textures[enemy.extra_texture_ids[0]].height as f32 / 2.0 * enemy.texture_scale,
-                ),
+            push_texture(
+                batch,
+                assets,
+                enemy.extra_texture_ids[0],
+                pos + Vector2::new(0.0, 1.0),
+                enemy.texture_scale,
                 vectortoangle((player.pos - enemy.pos).normalized()).to_degrees() + 90.0,
-                Color::WHITE,
+                TURRET_LAYER,
             );
         }
-        if player.pos.distance_to(enemy.pos) > 170.0 {
-            d.draw_texture_v(
-                &enemy_warning_image,
+        // Nebulae hide whatever is inside them from the radar.
+        if player.pos.distance_to(enemy.pos) > 170.0
+            && enemy.name != "Mine".to_string()
+            && !environment_at(world, enemy.pos).hidden
+        {
+            push_texture(
+                batch,
+                assets,
+                enemy_warning_texture,
                 (enemy.pos - player.pos).normalized() * 170.0
-                    + Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0)
-                    - Vector2::new(
-                        enemy_warning_image.width as f32 / 2.0,
-                        enemy_warning_image.height as f32 / 2.0,
-                    ),
-                Color::WHITE,
-            )
+                    + Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0),
+                1.0,
+                0.0,
+                MARKER_LAYER,
+            );
         }
     }
 }
@@ -0,0 +1,115 @@
+use rand::rngs::StdRng;
+use rand::Rng;
+use raylib::prelude::*;
+
+use crate::{angletovector, rotatevector, vectortoangle, Enemy, FormationShape, FormationSlot};
+
+pub const FLOCK_RADIUS: f32 = 150.0;
+const SEPARATION_WEIGHT: f32 = 2.5;
+const ALIGNMENT_WEIGHT: f32 = 0.5;
+const COHESION_WEIGHT: f32 = 0.3;
+const FORMATION_SPACING: f32 = 70.0;
+
+/// Boids steering for a flocking enemy from the other flocking enemies around it. Separation
+/// grows as neighbours get closer so a swarm spreads out before it can collide with itself.
+pub fn flock_steering(enemies: &Vec<Enemy>, enemy_index: usize) -> Vector2 {
+    let enemy = &enemies[enemy_index];
+    let mut separation = Vector2::zero();
+    let mut alignment = Vector2::zero();
+    let mut cohesion = Vector2::zero();
+    let mut count = 0;
+    for (other_index, other) in enemies.iter().enumerate() {
+        if other_index == enemy_index || !other.flocking {
+            continue;
+        }
+        let distance = enemy.pos.distance_to(other.pos);
+        if distance > FLOCK_RADIUS {
+            continue;
+        }
+        separation += (enemy.pos - other.pos).normalized() * (1.0 - distance / FLOCK_RADIUS);
+        alignment += other.vel;
+        cohesion += other.pos;
+        count += 1;
+    }
+    if count == 0 {
+        return Vector2::zero();
+    }
+    separation * SEPARATION_WEIGHT
+        + alignment.normalized() * ALIGNMENT_WEIGHT
+        + (cohesion / count as f32 - enemy.pos).normalized() * COHESION_WEIGHT
+}
+
+/// Where a formation follower should steer: its slot beside the leader, pushed ahead along
+/// the leader's heading so it ends up flying parallel. None for leaders, loners, and
+/// followers whose leader has died.
+pub fn formation_target(enemies: &Vec<Enemy>, enemy_index: usize) -> Option<Vector2> {
+    let slot = enemies[enemy_index].formation.as_ref()?;
+    if slot.leader {
+        return None;
+    }
+    let leader = enemies.iter().find(|enemy| {
+        enemy
+            .formation
+            .as_ref()
+            .is_some_and(|other| other.leader && other.group == slot.group)
+    })?;
+    Some(
+        leader.pos
+            + rotatevector(
+                slot.offset,
+                vectortoangle(leader.dir) - std::f32::consts::PI / 2.0,
+            )
+            + leader.dir * 150.0,
+    )
+}
+
+/// Slot offsets relative to the leader, with +y ahead and +x to the left like part locations.
+pub fn formation_offsets(shape: &FormationShape, amount: usize) -> Vec<Vector2> {
+    let mut offsets = vec![];
+    for index in 1..amount {
+        let row = index.div_ceil(2) as f32;
+        let side = if index % 2 == 1 { 1.0 } else { -1.0 };
+        offsets.push(match shape {
+            FormationShape::Single => Vector2::zero(),
+            FormationShape::V => Vector2::new(side * row, -row) * FORMATION_SPACING,
+            FormationShape::Line => Vector2::new(side * row, 0.0) * FORMATION_SPACING,
+            FormationShape::Ring => {
+                angletovector(std::f32::consts::TAU * index as f32 / (amount - 1) as f32)
+                    * f32::max(FORMATION_SPACING * 1.5, amount as f32 * 20.0)
+            }
+        });
+    }
+    offsets
+}
+
+/// Clones the leader into a full formation, all sharing a group and heading.
+pub fn spawn_formation(
+    leader: &Enemy,
+    shape: &FormationShape,
+    amount: usize,
+    enemies: &mut Vec<Enemy>,
+    rng: &mut StdRng,
+) {
+    let group = rng.gen();
+    let mut leader = leader.clone();
+    leader.formation = Some(FormationSlot {
+        group,
+        offset: Vector2::zero(),
+        leader: true,
+    });
+    for offset in formation_offsets(shape, amount) {
+        let mut follower = leader.clone();
+        follower.pos = leader.pos
+            + rotatevector(
+                offset,
+                vectortoangle(leader.dir) - std::f32::consts::PI / 2.0,
+            );
+        follower.formation = Some(FormationSlot {
+            group,
+            offset,
+            leader: false,
+        });
+        enemies.push(follower);
+    }
+    enemies.push(leader);
+}
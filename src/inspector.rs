@@ -0,0 +1,512 @@
+use raylib::prelude::*;
+
+use crate::{
+    damage_mods, rotatevector, vectortoangle, windup_charge, Enemy, InspectTarget, Inspector,
+    InspectorRow, Player, ShipMods, AUTO_FIRE_CONE,
+};
+
+const PANEL_WIDTH: f32 = 320.0;
+const ROW_HEIGHT: f32 = 17.0;
+const MARGIN: f32 = 10.0;
+// A pinned enemy that moves further than this in one frame is taken to be gone.
+const FOLLOW_RANGE: f32 = 150.0;
+const PICK_MARGIN: f32 = 10.0;
+// How much of a field's step one pixel of mouse drag is worth.
+const DRAG_RATE: f32 = 0.1;
+// The velocity and friction arrows show how far they'd carry the ship in this long.
+const ARROW_TIME: f32 = 0.5;
+const AIM_CONE_LENGTH: f32 = 1200.0;
+
+pub fn new_inspector() -> Inspector {
+    Inspector {
+        target: None,
+        pos: Vector2::zero(),
+        rows: vec![],
+        selected: 0,
+        dragging: None,
+    }
+}
+
+fn panel_rect(rows: usize, scale: f32, screenwidth: i32) -> Rectangle {
+    Rectangle::new(
+        screenwidth as f32 - (PANEL_WIDTH + MARGIN) * scale,
+        MARGIN * scale,
+        PANEL_WIDTH * scale,
+        (rows + 1) as f32 * ROW_HEIGHT * scale + 8.0 * scale,
+    )
+}
+
+fn row_at(inspector: &Inspector, mouse: Vector2, scale: f32, screenwidth: i32) -> Option<usize> {
+    let panel = panel_rect(inspector.rows.len(), scale, screenwidth);
+    if !panel.check_collision_point_rec(mouse) {
+        return None;
+    }
+    // The first row is the title.
+    let row = ((mouse.y - panel.y - 4.0 * scale) / (ROW_HEIGHT * scale)) as usize;
+    row.checked_sub(1).filter(|row| *row < inspector.rows.len())
+}
+
+/// Whatever ship is under `pos`, enemies first since they're drawn on top.
+fn pick(player: &Player, enemies: &Vec<Enemy>, pos: Vector2) -> Option<InspectTarget> {
+    let enemy = enemies
+        .iter()
+        .enumerate()
+        .filter(|(_, enemy)| enemy.pos.distance_to(pos) < enemy.size + PICK_MARGIN)
+        .min_by(|a, b| {
+            a.1.pos
+                .distance_to(pos)
+                .total_cmp(&b.1.pos.distance_to(pos))
+        });
+    if let Some((index, _)) = enemy {
+        return Some(InspectTarget::Enemy(index));
+    }
+    player
+        .parts
+        .iter()
+        .any(|part| part.pos.distance_to(pos) < part.size + PICK_MARGIN)
+        .then_some(InspectTarget::Player)
+}
+
+fn vector_text(vector: Vector2) -> String {
+    format!("{:.1}, {:.1}", vector.x, vector.y)
+}
+
+fn list_text(values: &[f32]) -> String {
+    values
+        .iter()
+        .map(|value| format!("{value:.2}"))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn mods_info(mods: &ShipMods) -> Vec<(String, String)> {
+    vec![
+        ("mod speed".to_string(), format!("{:.2}", mods.speed)),
+        (
+            "mod turn l/r".to_string(),
+            format!("{:.2} {:.2}", mods.left_turn, mods.right_turn),
+        ),
+        ("mod exhaust".to_string(), list_text(&mods.partical)),
+        ("mod guns".to_string(), list_text(&mods.gun)),
+    ]
+}
+
+fn motion_info(pos: Vector2, vel: Vector2, dir: Vector2, friction: f32) -> Vec<(String, String)> {
+    let right = rotatevector(dir, std::f32::consts::PI / 2.0);
+    vec![
+        ("pos".to_string(), vector_text(pos)),
+        (
+            "vel".to_string(),
+            format!("{} ({:.1})", vector_text(vel), vel.length()),
+        ),
+        (
+            "dir".to_string(),
+            format!("{:.1} deg", vectortoangle(dir).to_degrees()),
+        ),
+        (
+            "lateral friction".to_string(),
+            format!("{:.1}", right.dot(vel) * friction),
+        ),
+    ]
+}
+
+fn player_info(player: &Player) -> Vec<(String, String)> {
+    let mut info = motion_info(player.pos, player.vel, player.dir, 1.0);
+    info.push(("speed".to_string(), format!("{:.1}", player.speed)));
+    info.push((
+        "turn l/r".to_string(),
+        format!("{:.1} {:.1}", player.left_turn, player.right_turn),
+    ));
+    for (index, partical_emmiter) in player.partical_emmiters.iter().enumerate() {
+        info.push((
+            format!("exhaust {index}"),
+            format!(
+                "{:.4}/{:.4} speed {:.0}",
+                partical_emmiter.time, partical_emmiter.partical_interval, partical_emmiter.speed
+            ),
+        ));
+    }
+    for (index, bullet_emmiter) in player.bullet_emmiters.iter().enumerate() {
+        info.push((
+            format!("gun {index} timer"),
+            format!("{:.2}", bullet_emmiter.time),
+        ));
+    }
+    info.extend(mods_info(&damage_mods(
+        &player.parts,
+        &player.damage,
+        player.partical_emmiters.len(),
+        player.bullet_emmiters.len(),
+    )));
+    info
+}
+
+/// Every number on the player worth tweaking, with how much one key press changes it.
+fn player_fields(player: &mut Player) -> Vec<(String, &mut f32, f32)> {
+    let mut fields = vec![
+        (
+            "speed_original".to_string(),
+            &mut player.speed_original,
+            10.0,
+        ),
+        (
+            "left_turn_original".to_string(),
+            &mut player.left_turn_original,
+            5.0,
+        ),
+        (
+            "right_turn_original".to_string(),
+            &mut player.right_turn_original,
+            5.0,
+        ),
+        ("shield".to_string(), &mut player.shield, 1.0),
+    ];
+    for part in &mut player.parts {
+        fields.push((format!("{} health", part.name), &mut part.health, 0.5));
+    }
+    for (index, bullet_emmiter) in player.bullet_emmiters.iter_mut().enumerate() {
+        fields.push((
+            format!("gun {index} interval"),
+            &mut bullet_emmiter.bullet_interval,
+            0.05,
+        ));
+    }
+    fields
+}
+
+fn enemy_info(enemy: &Enemy) -> Vec<(String, String)> {
+    let mut info = vec![("name".to_string(), enemy.name.clone())];
+    info.extend(motion_info(enemy.pos, enemy.vel, enemy.dir, enemy.friction));
+    info.push(("targetpos".to_string(), vector_text(enemy.targetpos)));
+    info.push(("lifetime".to_string(), format!("{:.1}", enemy.lifetime)));
+    info.push((
+        "phase / flocking".to_string(),
+        format!(
+            "{} / {}{}",
+            enemy.phase,
+            enemy.flocking,
+            if enemy.formation.is_some() {
+                " in formation"
+            } else {
+                ""
+            }
+        ),
+    ));
+    for (index, partical_emmiter) in enemy.partical_emmiters.iter().enumerate() {
+        info.push((
+            format!("exhaust {index}"),
+            format!(
+                "{:.4}/{:.4} speed {:.0}",
+                partical_emmiter.time, partical_emmiter.partical_interval, partical_emmiter.speed
+            ),
+        ));
+    }
+    for (index, bullet_emmiter) in enemy.bullet_emmiters.iter().enumerate() {
+        info.push((
+            format!("gun {index} timer"),
+            format!(
+                "{:.2} charge {:.2}",
+                bullet_emmiter.time,
+                windup_charge(bullet_emmiter)
+            ),
+        ));
+    }
+    for (index, spawner) in enemy.spawners.iter().enumerate() {
+        info.push((
+            format!("spawner {index}"),
+            format!("{:.1}/{:.1}", spawner.time, spawner.interval),
+        ));
+    }
+    if !enemy.parts.is_empty() {
+        info.extend(mods_info(&damage_mods(
+            &enemy.parts,
+            &enemy.damage,
+            enemy.partical_emmiters.len(),
+            enemy.bullet_emmiters.len(),
+        )));
+    }
+    info
+}
+
+fn enemy_fields(enemy: &mut Enemy) -> Vec<(String, &mut f32, f32)> {
+    let mut fields = vec![
+        ("speed".to_string(), &mut enemy.speed, 10.0),
+        ("turningspeed".to_string(), &mut enemy.turningspeed, 5.0),
+        ("accuracy".to_string(), &mut enemy.accuracy, 0.05),
+        ("aim_jitter".to_string(), &mut enemy.aim_jitter, 0.5),
+        ("friction".to_string(), &mut enemy.friction, 0.1),
+        ("size".to_string(), &mut enemy.size, 1.0),
+        ("health".to_string(), &mut enemy.health, 0.5),
+    ];
+    if enemy.blast_radius > 0.0 {
+        fields.push(("blast_radius".to_string(), &mut enemy.blast_radius, 10.0));
+    }
+    for part in &mut enemy.parts {
+        fields.push((format!("{} health", part.name), &mut part.health, 1.0));
+    }
+    for (index, bullet_emmiter) in enemy.bullet_emmiters.iter_mut().enumerate() {
+        fields.push((
+            format!("gun {index} interval"),
+            &mut bullet_emmiter.bullet_interval,
+            0.05,
+        ));
+    }
+    fields
+}
+
+/// Enemies get removed from the middle of the list, so find the pinned one again by where it
+/// was last frame. Has to run every frame anything could have removed enemies, even when the
+/// inspector isn't taking input, so the index is never stale.
+pub fn follow_target(inspector: &mut Inspector, enemies: &Vec<Enemy>) {
+    let Some(InspectTarget::Enemy(_)) = inspector.target else {
+        return;
+    };
+    let last = inspector.pos;
+    inspector.target = enemies
+        .iter()
+        .enumerate()
+        .filter(|(_, enemy)| enemy.pos.distance_to(last) < FOLLOW_RANGE)
+        .min_by(|a, b| {
+            a.1.pos
+                .distance_to(last)
+                .total_cmp(&b.1.pos.distance_to(last))
+        })
+        .map(|(index, enemy)| {
+            inspector.pos = enemy.pos;
+            InspectTarget::Enemy(index)
+        });
+}
+
+/// Picks what to inspect on left click and applies edits. Up and down pick a field, left and
+/// right nudge it (ten times as far with shift), or drag sideways on a field with the mouse.
+/// Returns whether anything was edited.
+pub fn update_inspector(
+    inspector: &mut Inspector,
+    rl: &RaylibHandle,
+    player: &mut Player,
+    enemies: &mut Vec<Enemy>,
+    scale: f32,
+    screenwidth: i32,
+    screenheight: i32,
+) -> bool {
+    let mouse = rl.get_mouse_position();
+    if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+        match row_at(inspector, mouse, scale, screenwidth) {
+            Some(row) if inspector.rows[row].editable => {
+                inspector.selected = row;
+                inspector.dragging = Some(row);
+            }
+            Some(_) => {}
+            None => {
+                let pos = mouse - Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0)
+                    + player.pos;
+                inspector.target = pick(player, enemies, pos);
+                inspector.pos = pos;
+                inspector.selected = 0;
+            }
+        }
+    }
+    if !rl.is_mouse_button_down(MouseButton::MOUSE_BUTTON_LEFT) {
+        inspector.dragging = None;
+    }
+
+    let (info, mut fields) = match inspector.target {
+        Some(InspectTarget::Player) => (player_info(player), player_fields(player)),
+        Some(InspectTarget::Enemy(index)) if index < enemies.len() => (
+            enemy_info(&enemies[index]),
+            enemy_fields(&mut enemies[index]),
+        ),
+        _ => {
+            inspector.target = None;
+            inspector.rows.clear();
+            return false;
+        }
+    };
+
+    // Editable fields sit after the read-only rows.
+    let first = info.len();
+    let last = first + fields.len();
+    let mut edited = false;
+    if fields.is_empty() {
+        inspector.selected = 0;
+    } else {
+        inspector.selected = inspector.selected.clamp(first, last - 1);
+        if rl.is_key_pressed(KeyboardKey::KEY_UP) && inspector.selected > first {
+            inspector.selected -= 1;
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_DOWN) && inspector.selected + 1 < last {
+            inspector.selected += 1;
+        }
+        let boost = if rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) {
+            10.0
+        } else {
+            1.0
+        };
+        let (_, value, step) = &mut fields[inspector.selected - first];
+        if rl.is_key_pressed(KeyboardKey::KEY_LEFT) {
+            **value -= *step * boost;
+            edited = true;
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_RIGHT) {
+            **value += *step * boost;
+            edited = true;
+        }
+        if let Some(row) = inspector
+            .dragging
+            .filter(|row| *row >= first && *row < last)
+        {
+            let (_, value, step) = &mut fields[row - first];
+            **value += rl.get_mouse_delta().x * *step * DRAG_RATE;
+            edited |= rl.get_mouse_delta().x != 0.0;
+        }
+    }
+
+    inspector.rows = info
+        .into_iter()
+        .map(|(label, value)| InspectorRow {
+            label,
+            value,
+            editable: false,
+        })
+        .chain(fields.iter().map(|(label, value, _)| InspectorRow {
+            label: label.clone(),
+            value: format!("{:.3}", **value),
+            editable: true,
+        }))
+        .collect();
+    edited
+}
+
+fn draw_arrow(d: &mut RaylibDrawHandle, start: Vector2, end: Vector2, color: Color) {
+    d.draw_line_ex(start, end, 2.0, color);
+    if start.distance_to(end) < 1.0 {
+        return;
+    }
+    let back = (start - end).normalized() * 8.0;
+    d.draw_line_ex(end, end + rotatevector(back, 0.5), 2.0, color);
+    d.draw_line_ex(end, end + rotatevector(back, -0.5), 2.0, color);
+}
+
+/// Velocity in green, facing in white, the sideways friction in red. The player also shows
+/// the cone enemies have to be in for the guns to fire on their own, enemies show where
+/// they're steering and aiming.
+fn draw_motion(
+    d: &mut RaylibDrawHandle,
+    center: Vector2,
+    vel: Vector2,
+    dir: Vector2,
+    friction: f32,
+) {
+    let right = rotatevector(dir, std::f32::consts::PI / 2.0);
+    draw_arrow(d, center, center + vel * ARROW_TIME, Color::GREEN);
+    draw_arrow(d, center, center + dir * 60.0, Color::WHITE);
+    draw_arrow(
+        d,
+        center,
+        center - right * right.dot(vel) * friction * ARROW_TIME,
+        Color::RED,
+    );
+}
+
+pub fn draw_inspector(
+    d: &mut RaylibDrawHandle,
+    inspector: &Inspector,
+    player: &Player,
+    enemies: &Vec<Enemy>,
+    font: &WeakFont,
+    scale: f32,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    let offset = Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0) - player.pos;
+    let title = match inspector.target {
+        Some(InspectTarget::Player) => {
+            let center = player.pos + offset;
+            let half_angle = (1.0 - AUTO_FIRE_CONE).acos().to_degrees();
+            let facing = vectortoangle(player.dir).to_degrees();
+            d.draw_circle_sector(
+                center,
+                AIM_CONE_LENGTH,
+                facing - half_angle,
+                facing + half_angle,
+                24,
+                Color::new(255, 255, 255, 20),
+            );
+            for side in [-half_angle, half_angle] {
+                let edge = (facing + side).to_radians();
+                d.draw_line_ex(
+                    center,
+                    center + Vector2::new(edge.cos(), edge.sin()) * AIM_CONE_LENGTH,
+                    1.0,
+                    Color::new(255, 255, 255, 80),
+                );
+            }
+            draw_motion(d, center, player.vel, player.dir, 1.0);
+            "Player".to_string()
+        }
+        Some(InspectTarget::Enemy(index)) => {
+            let Some(enemy) = enemies.get(index) else {
+                return;
+            };
+            let center = enemy.pos + offset;
+            d.draw_line_ex(
+                center,
+                enemy.targetpos + offset,
+                1.0,
+                Color::new(255, 161, 0, 120),
+            );
+            for bullet_emmiter in &enemy.bullet_emmiters {
+                d.draw_line_ex(
+                    bullet_emmiter.pos + offset,
+                    bullet_emmiter.aim + offset,
+                    1.0,
+                    Color::new(236, 28, 36, 120),
+                );
+            }
+            d.draw_ring(
+                center,
+                enemy.size + 4.0,
+                enemy.size + 6.0,
+                0.0,
+                360.0,
+                32,
+                Color::GOLD,
+            );
+            draw_motion(d, center, enemy.vel, enemy.dir, enemy.friction);
+            format!("Enemy {index}")
+        }
+        None => return,
+    };
+
+    let panel = panel_rect(inspector.rows.len(), scale, screenwidth);
+    d.draw_rectangle_rec(panel, Color::new(0, 0, 0, 180));
+    d.draw_rectangle_lines_ex(panel, scale, Color::new(140, 255, 251, 60));
+    let font_size = (ROW_HEIGHT - 3.0) * scale;
+    let mut pos = Vector2::new(panel.x + 6.0 * scale, panel.y + 4.0 * scale);
+    d.draw_text_ex(font, &title, pos, font_size, font_size / 10.0, Color::GOLD);
+    for (index, row) in inspector.rows.iter().enumerate() {
+        pos.y += ROW_HEIGHT * scale;
+        let selected = row.editable && index == inspector.selected;
+        if selected {
+            d.draw_rectangle_v(
+                Vector2::new(panel.x, pos.y - scale),
+                Vector2::new(panel.width, ROW_HEIGHT * scale),
+                Color::new(140, 255, 251, 40),
+            );
+        }
+        let color = if row.editable {
+            Color::new(140, 255, 251, 255)
+        } else {
+            Color::LIGHTGRAY
+        };
+        d.draw_text_ex(font, &row.label, pos, font_size, font_size / 10.0, color);
+        d.draw_text_ex(
+            font,
+            &row.value,
+            pos + Vector2::new(panel.width * 0.5, 0.0),
+            font_size,
+            font_size / 10.0,
+            if selected { Color::GOLD } else { Color::WHITE },
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use std::fs;
+
+use rand::rngs::StdRng;
+use raylib::prelude::*;
+
+use crate::{
+    archetype, asset_path, log, spawn_group, Assets, Campaign, DifficultyPreset, Enemy,
+    FormationShape, Level, LevelEvent, LogCategory, LogLevel, Player, PowerUp, PowerUpType,
+};
+
+pub const LEVEL_DIR: &str = "Levels";
+
+/// Parses a mission script. One command per line, `#` starts a comment:
+///
+/// ```text
+/// name First Contact
+/// message 3 Scouts inbound
+/// spawn Basic 5 v at 0 -1500
+/// wait 4
+/// clear
+/// boss Mothership
+/// powerup Repair at 300 0
+/// background 20 10 30
+/// win
+/// ```
+///
+/// Spawn and power-up positions are relative to the player; without `at` enemies arrive
+/// from a random direction like wave spawns.
+pub fn load_level(path: &str, archetypes: &Vec<Enemy>) -> Result<Level, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+    let mut level = Level {
+        name: path.to_string(),
+        events: vec![],
+        index: 0,
+        wait: 0.0,
+        message: String::new(),
+        message_time: 0.0,
+        complete: false,
+    };
+    for (line_index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: &str| format!("{path}:{}: {message}", line_index + 1);
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |index: usize| -> Result<f32, String> {
+            words
+                .get(index)
+                .and_then(|word| word.parse::<f32>().ok())
+                .ok_or_else(|| error(&format!("expected a number after `{}`", words[0])))
+        };
+        // `at x y` can trail spawn and power-up commands.
+        let position = |index: usize| -> Result<Option<Vector2>, String> {
+            match words.get(index) {
+                None => Ok(None),
+                Some(&"at") => Ok(Some(Vector2::new(number(index + 1)?, number(index + 2)?))),
+                Some(word) => Err(error(&format!("unexpected `{word}`"))),
+            }
+        };
+        let archetype = |index: usize| -> Result<String, String> {
+            let name = words
+                .get(index)
+                .ok_or_else(|| error("expected an enemy name"))?;
+            // Multi-word archetypes are written with underscores, e.g. Mine_Layer.
+            let name = name.replace('_', " ");
+            if archetypes.iter().any(|enemy| enemy.name == name) {
+                Ok(name)
+            } else {
+                Err(error(&format!("unknown enemy `{name}`")))
+            }
+        };
+        match words[0] {
+            "name" => level.name = words[1..].join(" "),
+            "message" => {
+                let time = number(1)?;
+                let text = words.get(2..).unwrap_or(&[]).join(" ");
+                level.events.push(LevelEvent::Message(text, time));
+            }
+            "spawn" => {
+                let (formation, next) = match words.get(3) {
+                    Some(&"single") => (FormationShape::Single, 4),
+                    Some(&"v") => (FormationShape::V, 4),
+                    Some(&"ring") => (FormationShape::Ring, 4),
+                    Some(&"line") => (FormationShape::Line, 4),
+                    _ => (FormationShape::Single, 3),
+                };
+                level.events.push(LevelEvent::Spawn {
+                    archetype: archetype(1)?,
+                    amount: number(2)? as usize,
+                    formation,
+                    pos: position(next)?,
+                });
+            }
+            "wait" => level.events.push(LevelEvent::Wait(number(1)?)),
+            "clear" => level.events.push(LevelEvent::Clear),
+            "boss" => level.events.push(LevelEvent::Boss(archetype(1)?)),
+            "powerup" => {
+                let power_type = match words.get(1) {
+                    Some(&"Shield") => PowerUpType::Shield,
+                    Some(&"Repair") => PowerUpType::Repair,
+                    _ => return Err(error("expected Shield or Repair")),
+                };
+                level
+                    .events
+                    .push(LevelEvent::PowerUp(power_type, position(2)?));
+            }
+            "background" => level.events.push(LevelEvent::Background(Color::new(
+                number(1)? as u8,
+                number(2)? as u8,
+                number(3)? as u8,
+                255,
+            ))),
+            "win" => level.events.push(LevelEvent::Win),
+            command => return Err(error(&format!("unknown command `{command}`"))),
+        }
+    }
+    Ok(level)
+}
+
+/// Every `.level` file in the level folder, played in file name order.
+pub fn load_campaign(assets: &Assets, archetypes: &Vec<Enemy>) -> Result<Campaign, String> {
+    let dir = asset_path(assets, LEVEL_DIR);
+    let mut paths: Vec<String> = fs::read_dir(&dir)
+        .map_err(|err| format!("{}: {err}", dir.display()))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path().to_string_lossy().to_string())
+        .filter(|path| path.ends_with(".level"))
+        .collect();
+    paths.sort();
+    if paths.is_empty() {
+        return Err(format!("{}: no levels found", dir.display()));
+    }
+    let mut levels = vec![];
+    for path in paths {
+        levels.push(load_level(&path, archetypes)?);
+    }
+    Ok(Campaign {
+        levels,
+        current: 0,
+        background: Color::new(10, 10, 10, 255),
+    })
+}
+
+pub fn campaign_finished(campaign: &Campaign) -> bool {
+    campaign.current >= campaign.levels.len()
+}
+
+/// Stops the current level waiting, whether on a timer or for the enemies to be cleared.
+pub fn skip_wait(campaign: &mut Campaign) {
+    let Some(level) = campaign.levels.get_mut(campaign.current) else {
+        return;
+    };
+    level.wait = 0.0;
+    if let Some(LevelEvent::Clear) = level.events.get(level.index) {
+        level.index += 1;
+    }
+}
+
+/// Runs the current mission's events in order until one has to wait, either on a timer or
+/// for the field to be cleared. A finished mission shows its message, then the next starts.
+pub fn update_campaign(
+    campaign: &mut Campaign,
+    archetypes: &Vec<Enemy>,
+    power_up_templates: &Vec<PowerUp>,
+    player: &Player,
+    enemies: &mut Vec<Enemy>,
+    power_ups: &mut Vec<PowerUp>,
+    difficulty: &DifficultyPreset,
+    rng: &mut StdRng,
+    dt: f32,
+) {
+    let Some(level) = campaign.levels.get_mut(campaign.current) else {
+        return;
+    };
+    level.message_time -= dt;
+    if level.complete {
+        if level.message_time <= 0.0 {
+            campaign.current += 1;
+            log(
+                LogLevel::Info,
+                LogCategory::Waves,
+                &format!("mission {} complete", campaign.current),
+            );
+        }
+        return;
+    }
+    if level.wait > 0.0 {
+        level.wait -= dt;
+        return;
+    }
+    while let Some(event) = level.events.get(level.index) {
+        match event {
+            LevelEvent::Message(text, duration) => {
+                level.message = text.clone();
+                level.message_time = *duration;
+            }
+            LevelEvent::Spawn {
+                archetype: name,
+                amount,
+                formation,
+                pos,
+            } => {
+                spawn_group(
+                    archetype(archetypes, name),
+                    formation,
+                    *amount,
+                    *pos,
+                    player,
+                    enemies,
+                    difficulty,
+                    rng,
+                );
+            }
+            LevelEvent::Wait(time) => {
+                level.wait = *time;
+                level.index += 1;
+                return;
+            }
+            LevelEvent::Clear => {
+                if !enemies.is_empty() {
+                    return;
+                }
+            }
+            LevelEvent::Boss(name) => {
+                spawn_group(
+                    archetype(archetypes, name),
+                    &FormationShape::Single,
+                    1,
+                    None,
+                    player,
+                    enemies,
+                    difficulty,
+                    rng,
+                );
+                level.message = format!("Warning: {name} approaching");
+                level.message_time = 3.0;
+            }
+            LevelEvent::PowerUp(power_type, pos) => {
+                let template = power_up_templates
+                    .iter()
+                    .find(|power_up| power_up.power_type == *power_type);
+                if let Some(template) = template {
+                    let mut power_up = template.clone();
+                    power_up.pos = player.pos + pos.unwrap_or(player.dir * 400.0);
+                    power_up.respawn = false;
+                    power_ups.push(power_up);
+                }
+            }
+            LevelEvent::Background(color) => campaign.background = *color,
+            LevelEvent::Win => {
+                level.complete = true;
+                level.message = format!("{} complete", level.name);
+                level.message_time = 3.0;
+                return;
+            }
+        }
+        level.index += 1;
+    }
+}
@@ -0,0 +1,305 @@
+use raylib::prelude::*;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::{
+    asset_root, date_string, difficulty_name, mode_name, today, CrashState, GameMode, InputFrame,
+    LogCategory, LogLevel, Logger, Settings,
+};
+
+pub const LOG_FILE: &str = "game.log";
+// game.log plus game.1.log and game.2.log from before.
+const LOG_FILES: usize = 3;
+const MAX_LOG_BYTES: u64 = 1024 * 1024;
+// Anything quieter only goes to the file.
+const PRINT_LEVEL: LogLevel = LogLevel::Info;
+const INPUT_SECONDS: f32 = 10.0;
+// The keys the game reads, in the order they're packed into an input frame.
+const RECORDED_KEYS: [(KeyboardKey, &str); 29] = [
+    (KeyboardKey::KEY_A, "A"),
+    (KeyboardKey::KEY_D, "D"),
+    (KeyboardKey::KEY_ESCAPE, "Esc"),
+    (KeyboardKey::KEY_ENTER, "Enter"),
+    (KeyboardKey::KEY_UP, "Up"),
+    (KeyboardKey::KEY_DOWN, "Down"),
+    (KeyboardKey::KEY_LEFT, "Left"),
+    (KeyboardKey::KEY_RIGHT, "Right"),
+    (KeyboardKey::KEY_Q, "Q"),
+    (KeyboardKey::KEY_ONE, "1"),
+    (KeyboardKey::KEY_TWO, "2"),
+    (KeyboardKey::KEY_THREE, "3"),
+    (KeyboardKey::KEY_FOUR, "4"),
+    (KeyboardKey::KEY_FIVE, "5"),
+    (KeyboardKey::KEY_SIX, "6"),
+    (KeyboardKey::KEY_GRAVE, "`"),
+    (KeyboardKey::KEY_F3, "F3"),
+    (KeyboardKey::KEY_F4, "F4"),
+    (KeyboardKey::KEY_F5, "F5"),
+    (KeyboardKey::KEY_F6, "F6"),
+    (KeyboardKey::KEY_F11, "F11"),
+    (KeyboardKey::KEY_LEFT_SHIFT, "Shift"),
+    (KeyboardKey::KEY_COMMA, ","),
+    (KeyboardKey::KEY_PERIOD, "."),
+    (KeyboardKey::KEY_F7, "F7"),
+    (KeyboardKey::KEY_F8, "F8"),
+    (KeyboardKey::KEY_F9, "F9"),
+    (KeyboardKey::KEY_F10, "F10"),
+    (KeyboardKey::KEY_BACKSPACE, "Backspace"),
+];
+
+static LOGGER: Mutex<Option<Logger>> = Mutex::new(None);
+static CRASH_STATE: Mutex<Option<CrashState>> = Mutex::new(None);
+
+/// Logs go next to the assets, not wherever the game happened to be started from.
+fn rotated_log(index: usize) -> PathBuf {
+    if index == 0 {
+        asset_root().join(LOG_FILE)
+    } else {
+        asset_root().join(LOG_FILE.replace(".log", &format!(".{index}.log")))
+    }
+}
+
+/// Shuffles every old log up one, dropping the oldest, so game.log starts empty.
+fn rotate_logs() {
+    for index in (1..LOG_FILES).rev() {
+        let from = rotated_log(index - 1);
+        if fs::metadata(&from).is_ok() {
+            let _ = fs::rename(&from, rotated_log(index));
+        }
+    }
+}
+
+fn open_log() -> Option<File> {
+    let path = rotated_log(0);
+    match File::create(&path) {
+        Ok(file) => Some(file),
+        Err(err) => {
+            println!("{}: {err}, only logging to the console", path.display());
+            None
+        }
+    }
+}
+
+/// Starts a fresh log file and installs the panic hook. Has to run before anything that might
+/// want to log.
+pub fn init_logging() {
+    rotate_logs();
+    *lock(&LOGGER) = Some(Logger {
+        file: open_log(),
+        written: 0,
+        start: Instant::now(),
+    });
+    *lock(&CRASH_STATE) = Some(CrashState {
+        seed: 0,
+        mode: "",
+        time: 0.0,
+        counts: vec![],
+        settings: String::new(),
+        inputs: VecDeque::new(),
+        typed: String::new(),
+    });
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        write_crash_report(info);
+        default_hook(info);
+    }));
+    log(
+        LogLevel::Info,
+        LogCategory::Game,
+        &format!("started {}", date_string(today())),
+    );
+}
+
+// A panic somewhere else while holding the lock shouldn't stop the crash report being written.
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn level_name(level: LogLevel) -> &'static str {
+    match level {
+        LogLevel::Debug => "DEBUG",
+        LogLevel::Info => "INFO",
+        LogLevel::Warn => "WARN",
+        LogLevel::Error => "ERROR",
+    }
+}
+
+fn category_name(category: LogCategory) -> &'static str {
+    match category {
+        LogCategory::Assets => "assets",
+        LogCategory::Waves => "waves",
+        LogCategory::Collisions => "collisions",
+        LogCategory::Input => "input",
+        LogCategory::Game => "game",
+    }
+}
+
+/// Writes one line to the log file, timestamped with seconds since startup. Info and up is
+/// printed as well, the way errors always have been.
+pub fn log(level: LogLevel, category: LogCategory, message: &str) {
+    if level >= PRINT_LEVEL {
+        println!("{message}");
+    }
+    let mut logger = lock(&LOGGER);
+    let Some(logger) = logger.as_mut() else {
+        return;
+    };
+    let line = format!(
+        "[{:10.3}] {:5} {}: {message}\n",
+        logger.start.elapsed().as_secs_f32(),
+        level_name(level),
+        category_name(category)
+    );
+    if logger.written + line.len() as u64 > MAX_LOG_BYTES {
+        logger.file = None;
+        rotate_logs();
+        logger.file = open_log();
+        logger.written = 0;
+    }
+    if let Some(file) = logger.file.as_mut() {
+        if file.write_all(line.as_bytes()).is_ok() {
+            logger.written += line.len() as u64;
+        }
+    }
+}
+
+/// Keeps the last few seconds of input so a crash can be played back from the seed.
+pub fn record_input(rl: &RaylibHandle, frame_time: f32, dt: f32) {
+    let mut keys = 0;
+    for (bit, (key, _)) in RECORDED_KEYS.iter().enumerate() {
+        if rl.is_key_down(*key) {
+            keys |= 1 << bit;
+        }
+    }
+    let mut crash_state = lock(&CRASH_STATE);
+    let Some(crash_state) = crash_state.as_mut() else {
+        return;
+    };
+    crash_state.inputs.push_back(InputFrame {
+        frame_time,
+        dt,
+        keys,
+        mouse: rl.get_mouse_position(),
+        click: rl.is_mouse_button_down(MouseButton::MOUSE_BUTTON_LEFT),
+        typed: std::mem::take(&mut crash_state.typed),
+    });
+    let mut recorded: f32 = crash_state
+        .inputs
+        .iter()
+        .map(|input| input.frame_time)
+        .sum();
+    while recorded > INPUT_SECONDS {
+        match crash_state.inputs.pop_front() {
+            Some(input) => recorded -= input.frame_time,
+            None => break,
+        }
+    }
+}
+
+/// Characters typed this frame, the console drains them before `record_input` runs so it
+/// hands them over here.
+pub fn record_typed(typed: &[char]) {
+    if let Some(crash_state) = lock(&CRASH_STATE).as_mut() {
+        crash_state.typed.extend(typed);
+    }
+}
+
+pub fn record_crash_state(
+    seed: u64,
+    mode: &GameMode,
+    time: f32,
+    counts: &[(&'static str, usize)],
+    settings: &Settings,
+) {
+    let mut crash_state = lock(&CRASH_STATE);
+    let Some(crash_state) = crash_state.as_mut() else {
+        return;
+    };
+    crash_state.seed = seed;
+    crash_state.mode = mode_name(mode);
+    crash_state.time = time;
+    crash_state.counts = counts.to_vec();
+    crash_state.settings = format!(
+        "difficulty {}, adaptive {}, damage numbers {}, volume {:.2} effects {:.2} music {:.2}",
+        difficulty_name(&settings.difficulty),
+        settings.adaptive,
+        settings.damage_numbers,
+        settings.master_volume,
+        settings.effects_volume,
+        settings.music_volume
+    );
+}
+
+fn key_names(keys: u32) -> String {
+    let names: Vec<&str> = RECORDED_KEYS
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| keys & (1 << bit) != 0)
+        .map(|(_, (_, name))| *name)
+        .collect();
+    if names.is_empty() {
+        "-".to_string()
+    } else {
+        names.join("+")
+    }
+}
+
+/// Run from the panic hook. Everything is written in one go so a second panic halfway
+/// through doesn't leave half a report.
+fn write_crash_report(info: &std::panic::PanicHookInfo) {
+    let mut report = format!(
+        "Space Game crash report\n{}\n\n{info}\n\n",
+        date_string(today())
+    );
+    if let Some(crash_state) = lock(&CRASH_STATE).as_ref() {
+        report += &format!(
+            "seed {}\nmode {}\ntime {:.3}\n{}\n",
+            crash_state.seed, crash_state.mode, crash_state.time, crash_state.settings
+        );
+        for (name, count) in &crash_state.counts {
+            report += &format!("{name} {count}\n");
+        }
+        report += "\n# input, one frame per line: frame_time dt keys mouse_x mouse_y click typed\n";
+        for input in &crash_state.inputs {
+            report += &format!(
+                "{:.5} {:.5} {} {:.0} {:.0} {} {:?}\n",
+                input.frame_time,
+                input.dt,
+                key_names(input.keys),
+                input.mouse.x,
+                input.mouse.y,
+                input.click as u8,
+                input.typed
+            );
+        }
+    }
+    report += &format!(
+        "\n# backtrace\n{}\n",
+        std::backtrace::Backtrace::force_capture()
+    );
+    let path = asset_root().join(format!(
+        "crash-{}.txt",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    ));
+    match fs::write(&path, report) {
+        Ok(()) => log(
+            LogLevel::Error,
+            LogCategory::Game,
+            &format!("crashed, report written to {}", path.display()),
+        ),
+        Err(err) => log(
+            LogLevel::Error,
+            LogCategory::Game,
+            &format!("crashed, and couldn't write {}: {err}", path.display()),
+        ),
+    }
+}
@@ -1,25 +1,67 @@
+use animation::*;
+use archive::*;
+use assets::*;
+use audio::*;
 use background::*;
 use bullets::*;
+use console::*;
+use damagenumbers::*;
 use debug::*;
+use director::*;
 use enemy::*;
+use flocking::*;
+use inspector::*;
+use levels::*;
+use logging::*;
+use modes::*;
+use noise::{Fbm, Perlin};
 use particals::*;
 use player::*;
 use powerups::*;
-use rand::Rng;
+use profiler::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use raylib::prelude::*;
-use slotmap::{new_key_type, SlotMap};
+use score::*;
+use slotmap::{new_key_type, SecondaryMap, SlotMap};
+use sprites::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::path::PathBuf;
+use std::time::Instant;
+use targeting::*;
+use timecontrol::*;
 use ui::*;
 use waves::*;
+use world::*;
 
+mod animation;
+mod archive;
+mod assets;
+mod audio;
 mod background;
 mod bullets;
+mod console;
+mod damagenumbers;
 mod debug;
+mod director;
 mod enemy;
+mod flocking;
+mod inspector;
+mod levels;
+mod logging;
+mod modes;
 mod particals;
 mod player;
 mod powerups;
+mod profiler;
+mod score;
+mod sprites;
+mod targeting;
+mod timecontrol;
 mod ui;
 mod waves;
+mod world;
 
 new_key_type! {
     struct TextureID;
@@ -40,6 +82,8 @@ struct Player {
     speed: f32,
     left_turn: f32,
     right_turn: f32,
+    shield: f32,
+    animator: Animator,
 }
 #[derive(Clone)]
 struct Part {
@@ -49,6 +93,13 @@ struct Part {
     starting_health: f32,
     size: f32,
     name: String,
+    flash: f32,
+    damage_emmiters: Vec<DamageEmitter>,
+}
+#[derive(Clone)]
+struct DamageEmitter {
+    threshold: f32,
+    partical_emmiter: ParticalEmitter,
 }
 #[derive(Clone)]
 struct Enemy {
@@ -60,6 +111,8 @@ struct Enemy {
     speed: f32,
     turningspeed: f32,
     predictive: bool,
+    accuracy: f32,
+    aim_jitter: f32,
     texture_scale: f32,
     friction: f32,
     size: f32,
@@ -68,6 +121,165 @@ struct Enemy {
     bullet_emmiters: Vec<BulletEmitter>,
     texture_id: TextureID,
     extra_texture_ids: Vec<TextureID>,
+    points: u32,
+    parts: Vec<Part>,
+    damage: Vec<Damage>,
+    phase: usize,
+    flocking: bool,
+    formation: Option<FormationSlot>,
+    blast_radius: f32,
+    blast_damage: f32,
+    lifetime: f32,
+    killed_by_player: bool,
+    spawners: Vec<EnemySpawner>,
+    animator: Animator,
+}
+#[derive(Clone)]
+struct EnemySpawner {
+    location: Vector2,
+    launch_speed: f32,
+    interval: f32,
+    time: f32,
+    enemy: Box<Enemy>,
+}
+#[derive(Clone)]
+struct FormationSlot {
+    group: u32,
+    offset: Vector2,
+    leader: bool,
+}
+#[derive(Clone)]
+struct Asteroid {
+    pos: Vector2,
+    vel: Vector2,
+    radius: f32,
+    health: f32,
+    rotation: f32,
+    spin: f32,
+    shape: Vec<f32>,
+    chunk: (i32, i32),
+    flash: f32,
+}
+#[derive(Clone)]
+struct Debris {
+    pos: Vector2,
+    vel: Vector2,
+    size: Vector2,
+    rotation: f32,
+    spin: f32,
+    color: Color,
+    chunk: (i32, i32),
+}
+/// Every texture the game has loaded, looked up by the name it was loaded with. Once the
+/// atlas is built `regions` says where in it each texture's pixels ended up. `sizes` is kept
+/// apart from the textures so sprites can be queued without a GPU.
+struct Assets {
+    root: PathBuf,
+    archive: Option<Archive>,
+    textures: SlotMap<TextureID, Texture2D>,
+    sizes: SecondaryMap<TextureID, Vector2>,
+    texture_names: HashMap<String, TextureID>,
+    images: Vec<(TextureID, Image)>,
+    atlas: Option<TextureID>,
+    regions: SecondaryMap<TextureID, Rectangle>,
+    white: TextureID,
+    circle: TextureID,
+    clips: HashMap<String, AnimationClip>,
+}
+/// A row of frames cut out of one sprite sheet.
+struct AnimationClip {
+    texture: TextureID,
+    frames: Vec<Rectangle>,
+    frame_time: f32,
+    looping: bool,
+}
+/// Which clip plays in each state, by name. A state without a clip shows the idle clip, and
+/// without one of those the plain texture. `steer` is the turn input this frame and `bank`
+/// follows it smoothly so ships that wiggle towards their target don't flicker.
+#[derive(Clone)]
+struct Animator {
+    clips: Vec<(AnimationState, String)>,
+    state: AnimationState,
+    time: f32,
+    steer: f32,
+    bank: f32,
+}
+/// What's left of an enemy playing out its dying clip after it's been removed.
+#[derive(Clone)]
+struct Wreck {
+    pos: Vector2,
+    vel: Vector2,
+    dir: Vector2,
+    texture_id: TextureID,
+    texture_scale: f32,
+    animator: Animator,
+}
+#[derive(Clone)]
+struct Sprite {
+    texture: TextureID,
+    source: Rectangle,
+    dest: Rectangle,
+    origin: Vector2,
+    rotation: f32,
+    tint: Color,
+    layer: i32,
+    additive: bool,
+}
+struct SpriteBatch {
+    sprites: Vec<Sprite>,
+    drawn: usize,
+    draw_calls: usize,
+}
+struct Archive {
+    data: Vec<u8>,
+    entries: HashMap<String, ArchiveEntry>,
+}
+struct ArchiveEntry {
+    method: u16,
+    crc: u32,
+    packed_size: usize,
+    size: usize,
+    header: usize,
+}
+/// Codes of each bit length and the symbols they decode to, in code order.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+struct BackgroundLayer {
+    parallax: f32,
+    kind: LayerKind,
+    chunks: HashMap<(i32, i32), RenderTexture2D>,
+}
+struct Background {
+    seed: u64,
+    layers: Vec<BackgroundLayer>,
+}
+#[derive(Clone)]
+struct Zone {
+    pos: Vector2,
+    radius: f32,
+    kind: ZoneKind,
+    strength: f32,
+    blobs: Vec<(Vector2, f32)>,
+    chunk: (i32, i32),
+}
+struct Environment {
+    gravity: Vector2,
+    drag: f32,
+    ion: f32,
+    hidden: bool,
+}
+#[derive(Clone)]
+struct World {
+    seed: u64,
+    density: Fbm<Perlin>,
+    chunks: HashSet<(i32, i32)>,
+    asteroids: Vec<Asteroid>,
+    debris: Vec<Debris>,
+    zones: Vec<Zone>,
+    sector: (i32, i32),
+    sector_time: f32,
 }
 #[derive(Clone)]
 struct Bullet {
@@ -79,16 +291,41 @@ struct Bullet {
     duration: f32,
     time: f32,
 }
+/// Every live partical, stored column by column. The first `count` slots are alive and
+/// the rest are free, so the columns never grow past the budget they're made with.
+struct Particals {
+    count: usize,
+    pos: Vec<Vector2>,
+    vel: Vec<Vector2>,
+    rotation: Vec<f32>,
+    spin: Vec<f32>,
+    drag: Vec<f32>,
+    start_size: Vec<f32>,
+    end_size: Vec<f32>,
+    size_curve: Vec<Curve>,
+    shape: Vec<ParticalShape>,
+    starting_color: Vec<Color>,
+    ending_color: Vec<Color>,
+    color_curve: Vec<Curve>,
+    duration: Vec<f32>,
+    time: Vec<f32>,
+}
 #[derive(Clone)]
-struct Partical {
-    pos: Vector2,
-    vel: Vector2,
-    size: f32,
+struct ParticalPreset {
+    amount: usize,
+    force_min: f32,
+    force_max: f32,
     shape: ParticalShape,
+    start_size: f32,
+    end_size: f32,
+    size_curve: Curve,
     starting_color: Color,
     ending_color: Color,
+    color_curve: Curve,
     duration: f32,
-    time: f32,
+    drag: f32,
+    spin: f32,
+    priority: ParticalPriority,
 }
 #[derive(Clone)]
 struct ParticalEmitter {
@@ -96,11 +333,7 @@ struct ParticalEmitter {
     location: Vector2,
     speed_orginal: f32,
     vel: Vector2,
-    size: f32,
-    shape: ParticalShape,
-    starting_color: Color,
-    ending_color: Color,
-    duration: f32,
+    preset: ParticalPreset,
     partical_interval: f32,
     time: f32,
     speed: f32,
@@ -111,11 +344,24 @@ struct BulletEmitter {
     pos: Vector2,
     location: Vector2,
     size: f32,
+    speed: f32,
     damage: f32,
     friendly: bool,
     duration: f32,
     bullet_interval: f32,
     time: f32,
+    windup: f32,
+    aim_line: bool,
+    aim: Vector2,
+}
+
+#[derive(Clone)]
+struct ShipMods {
+    speed: f32,
+    left_turn: f32,
+    right_turn: f32,
+    partical: Vec<f32>,
+    gun: Vec<f32>,
 }
 
 #[derive(Clone)]
@@ -127,12 +373,17 @@ struct Damage {
     scale: f32,
 }
 
+#[derive(Clone)]
 struct PowerUp {
     pos: Vector2,
     power_type: PowerUpType,
-    texture: Texture2D,
+    texture: TextureID,
+    respawn: bool,
+    collected: bool,
+    animator: Animator,
 }
 
+#[derive(Clone)]
 struct Wave {
     interval: f32,
     min_interval: f32,
@@ -140,9 +391,313 @@ struct Wave {
     double_spawn_chance: f32,
     max_double_spawn_chance: f32,
     time: f32,
+    milestones: Vec<f32>,
+    formation: FormationShape,
+    formation_size: usize,
     enemy: Enemy,
 }
 
+#[derive(Clone)]
+struct DamageNumber {
+    pos: Vector2,
+    vel: Vector2,
+    amount: f32,
+    friendly: bool,
+    duration: f32,
+    time: f32,
+}
+
+#[derive(Clone)]
+struct HitMarker {
+    pos: Vector2,
+    duration: f32,
+    time: f32,
+}
+
+struct Settings {
+    damage_numbers: bool,
+    difficulty: Difficulty,
+    adaptive: bool,
+    master_volume: f32,
+    effects_volume: f32,
+    music_volume: f32,
+}
+
+/// The drop-down developer console. `browsing` is how far back through `history` the up
+/// arrow has gone, `script` holds the startup commands until a run starts.
+struct Console {
+    open: bool,
+    input: String,
+    history: Vec<String>,
+    browsing: Option<usize>,
+    log: Vec<String>,
+    script: Vec<String>,
+    god: bool,
+}
+
+/// Per system frame timings. Times are microseconds since `epoch` so they can go straight
+/// into a trace file, `scopes` and `counts` fill up over the frame and move into `history`.
+struct Profiler {
+    epoch: Instant,
+    frame_start: f64,
+    scopes: Vec<ProfileScope>,
+    counts: Vec<(&'static str, usize)>,
+    history: VecDeque<ProfileFrame>,
+    open: bool,
+}
+
+struct ProfileScope {
+    name: &'static str,
+    start: f64,
+    duration: f64,
+}
+
+struct ProfileFrame {
+    start: f64,
+    duration: f64,
+    scopes: Vec<ProfileScope>,
+    counts: Vec<(&'static str, usize)>,
+}
+
+/// The log file that's being written. `written` counts bytes so it can be rotated before it
+/// gets too big, `start` is what log lines are timestamped from.
+struct Logger {
+    file: Option<File>,
+    written: u64,
+    start: Instant,
+}
+
+/// What a crash report says about the run, kept up to date every frame since the panic hook
+/// can't see any of the game's state.
+struct CrashState {
+    seed: u64,
+    mode: &'static str,
+    time: f32,
+    counts: Vec<(&'static str, usize)>,
+    settings: String,
+    inputs: VecDeque<InputFrame>,
+    // Characters typed since the last input frame was recorded.
+    typed: String,
+}
+
+/// One frame of recorded input, `keys` has a bit for each key the game reads and `typed` is
+/// the characters typed that frame.
+struct InputFrame {
+    frame_time: f32,
+    dt: f32,
+    keys: u32,
+    mouse: Vector2,
+    click: bool,
+    typed: String,
+}
+
+/// Debug pause, single stepping and slow motion. `history` is the rewind buffer, oldest
+/// first, and `scrub` is the snapshot being looked at while paused.
+struct TimeControl {
+    paused: bool,
+    step: bool,
+    time_scale: f32,
+    history: VecDeque<Snapshot>,
+    scrub: Option<usize>,
+}
+
+/// Everything the simulation needs to pick up again from one tick.
+struct Snapshot {
+    time: f32,
+    player: Player,
+    enemies: Vec<Enemy>,
+    bullets: Vec<Bullet>,
+    power_ups: Vec<PowerUp>,
+    wrecks: Vec<Wreck>,
+    world: World,
+    waves: Vec<Wave>,
+    campaign: Option<Campaign>,
+    score: Score,
+    director: Director,
+    spawn_rng: StdRng,
+    rng: StdRng,
+}
+
+/// Debug panel pinned to one ship. `rows` is rebuilt from the live values every frame, `pos`
+/// is where a pinned enemy was last frame so it can be found again after the list shifts.
+struct Inspector {
+    target: Option<InspectTarget>,
+    pos: Vector2,
+    rows: Vec<InspectorRow>,
+    selected: usize,
+    dragging: Option<usize>,
+}
+struct InspectorRow {
+    label: String,
+    value: String,
+    editable: bool,
+}
+
+/// Sounds asked for this frame and the ones still playing, kept apart from the backend so
+/// the voice limits work the same with or without a sound card.
+struct Audio {
+    queue: Vec<SoundRequest>,
+    voices: Vec<Voice>,
+    intensity: f32,
+    alarm_time: f32,
+}
+
+struct SoundRequest {
+    effect: SoundEffect,
+    pos: Vector2,
+    vel: Vector2,
+}
+
+struct SoundMix {
+    volume: f32,
+    pan: f32,
+    pitch: f32,
+}
+
+struct Voice {
+    effect: SoundEffect,
+    time: f32,
+}
+
+struct SoundInfo {
+    file: &'static str,
+    voices: usize,
+    length: f32,
+    volume: f32,
+}
+
+enum AudioBackend<'aud> {
+    Device {
+        sounds: Vec<Vec<Sound<'aud>>>,
+        next: Vec<usize>,
+        calm: Option<Music<'aud>>,
+        intense: Option<Music<'aud>>,
+    },
+    Null {
+        played: Vec<SoundEffect>,
+    },
+}
+
+struct DifficultyPreset {
+    spawn_rate: f32,
+    enemy_health: f32,
+    enemy_speed: f32,
+    bullet_damage: f32,
+}
+
+#[derive(Clone)]
+struct Director {
+    pressure: f32,
+    kill_rate: f32,
+    damage_rate: f32,
+    health: f32,
+    last_kills: u32,
+    last_health: f32,
+}
+
+#[derive(Clone)]
+struct Score {
+    points: u32,
+    kills: u32,
+    combo: u32,
+    combo_time: f32,
+    cheated: bool,
+}
+
+enum Anchor {
+    TopLeft,
+    Top,
+    Bottom,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(PartialEq)]
+enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+#[derive(Clone)]
+struct Level {
+    name: String,
+    events: Vec<LevelEvent>,
+    index: usize,
+    wait: f32,
+    message: String,
+    message_time: f32,
+    complete: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+#[derive(Clone, Copy)]
+enum LogCategory {
+    Assets,
+    Waves,
+    Collisions,
+    Input,
+    Game,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum GameMode {
+    Endless,
+    TimeAttack,
+    BossRush,
+    Daily,
+    Campaign,
+}
+
+struct HighScore {
+    table: String,
+    points: u32,
+    kills: u32,
+    time: f32,
+    date: String,
+}
+
+#[derive(Clone)]
+struct Campaign {
+    levels: Vec<Level>,
+    current: usize,
+    background: Color,
+}
+
+#[derive(Clone)]
+enum LevelEvent {
+    Message(String, f32),
+    Spawn {
+        archetype: String,
+        amount: usize,
+        formation: FormationShape,
+        pos: Option<Vector2>,
+    },
+    Wait(f32),
+    Clear,
+    Boss(String),
+    PowerUp(PowerUpType, Option<Vector2>),
+    Background(Color),
+    Win,
+}
+
+#[derive(Clone)]
+enum FormationShape {
+    Single,
+    V,
+    Ring,
+    Line,
+}
+
+#[derive(Clone, PartialEq)]
 enum PowerUpType {
     Shield,
     Repair,
@@ -163,6 +718,46 @@ enum PartMod {
     Speed,
 }
 
+enum LayerKind {
+    Stars,
+    Dust,
+    Debris,
+}
+
+#[derive(Clone, PartialEq)]
+enum ZoneKind {
+    GravityWell,
+    Nebula,
+    IonStorm,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum SoundEffect {
+    PlayerFire,
+    EnemyFire,
+    Hit,
+    EnemyDeath,
+    PartDamage,
+    PowerUp,
+    Alarm,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum InspectTarget {
+    Player,
+    Enemy(usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum AnimationState {
+    Idle,
+    Thrusting,
+    BankLeft,
+    BankRight,
+    Damaged,
+    Dying,
+}
+
 #[derive(Clone)]
 enum ParticalShape {
     Square,
@@ -170,6 +765,20 @@ enum ParticalShape {
     RotSquare,
 }
 
+#[derive(Clone)]
+enum ParticalPriority {
+    Low,
+    Normal,
+    High,
+}
+
+#[derive(Clone)]
+enum Curve {
+    Linear,
+    EaseIn,
+    EaseOut,
+}
+
 fn main() {
     let mut debug = false;
     let (mut rl, thread) = raylib::init()
@@ -178,43 +787,310 @@ fn main() {
         .resizable()
         //.fullscreen()
         .build();
+    init_logging();
     rl.set_exit_key(None);
-    let default_font = rl.get_font_default();
+    let mut assets = new_assets(asset_root(), &mut rl, &thread);
+    let mut console = new_console(&assets);
+    let mut inspector = new_inspector();
+    let mut time_control = new_time_control();
+    let mut profiler = new_profiler();
+    let font_path = asset_path(&assets, "Fonts/DejaVuSansMono-Bold.ttf");
+    let hud_font = match rl.load_font_ex(&thread, &font_path.to_string_lossy(), 64, None) {
+        Ok(font) => font.make_weak(),
+        Err(err) => {
+            log(
+                LogLevel::Warn,
+                LogCategory::Assets,
+                &format!("{err}, falling back to the default font"),
+            );
+            rl.get_font_default()
+        }
+    };
+
+    // --mute runs on the null backend, for machines without a sound card.
+    let audio_device = if std::env::args().any(|arg| arg == "--mute") {
+        None
+    } else {
+        match RaylibAudio::init_audio_device() {
+            Ok(device) => Some(device),
+            Err(err) => {
+                log(
+                    LogLevel::Warn,
+                    LogCategory::Game,
+                    &format!("{err}, running without sound"),
+                );
+                None
+            }
+        }
+    };
+    let mut audio_backend = match &audio_device {
+        Some(device) => load_audio_backend(device, &assets),
+        None => null_audio_backend(),
+    };
+    let mut audio = new_audio();
 
     let mut player = init_player();
 
     let mut enemies: Vec<Enemy> = vec![];
 
+    let mut wrecks: Vec<Wreck> = vec![];
+
     let mut bullets: Vec<Bullet> = vec![];
 
-    let mut particals: Vec<Partical> = vec![];
+    let mut particals = new_particals(PARTICAL_BUDGET);
+
+    let mut damage_numbers: Vec<DamageNumber> = vec![];
 
-    let mut power_ups: Vec<PowerUp> = vec![PowerUp {
-        pos: player.pos,
-        power_type: PowerUpType::Repair,
-        texture: rl.load_texture(&thread, "Images/Repair.png").unwrap(),
-    }];
+    let mut hit_markers: Vec<HitMarker> = vec![];
 
-    let mut textures: SlotMap<TextureID, Texture2D> = SlotMap::with_key();
-    let mut waves: Vec<Wave> = init_waves(&mut textures, &mut rl, &thread);
+    let mut settings = Settings {
+        damage_numbers: true,
+        difficulty: Difficulty::Normal,
+        adaptive: true,
+        master_volume: 1.0,
+        effects_volume: 1.0,
+        music_volume: 0.75,
+    };
 
-    let ship_image = rl.load_texture(&thread, "Images/V1Ship.png").unwrap();
-    let enemy_warning_image = rl.load_texture(&thread, "Images/EnemyWarning.png").unwrap();
+    let mut director = new_director(&player);
+
+    let archetypes: Vec<Enemy> = init_archetypes(&mut assets, &mut rl, &thread);
+    let mut waves: Vec<Wave> = init_waves(&archetypes);
+
+    let power_up_templates: Vec<PowerUp> = vec![
+        PowerUp {
+            pos: Vector2::zero(),
+            power_type: PowerUpType::Repair,
+            texture: load_texture(&mut assets, &mut rl, &thread, "Images/Repair.png"),
+            respawn: true,
+            collected: false,
+            animator: new_animator(&[(AnimationState::Idle, "repair_spin")]),
+        },
+        PowerUp {
+            pos: Vector2::zero(),
+            power_type: PowerUpType::Shield,
+            texture: load_texture(&mut assets, &mut rl, &thread, "Images/Shield.png"),
+            respawn: true,
+            collected: false,
+            animator: new_animator(&[(AnimationState::Idle, "shield_spin")]),
+        },
+    ];
+    let mut campaign: Option<Campaign> = None;
+
+    let mut score = new_score();
+    // Next to the assets like everything else, so the table is the same wherever it's run from.
+    let high_score_path = asset_path(&assets, HIGH_SCORE_FILE);
+    let mut high_scores = load_high_scores(&high_score_path);
+
+    let mut mode = GameMode::Endless;
+    let mut selected_mode = 0;
+    let mut mode_select = true;
+    let mut game_over: Option<bool> = None;
+    let mut rank: Option<usize> = None;
+    let mut seed = mode_seed(&mode);
+    // Waves, missions and pickups draw from `spawn_rng` only, everything that depends on how
+    // the run is played (ion storms, aim jitter, debris) uses `rng`.
+    let mut spawn_rng = StdRng::seed_from_u64(seed ^ SPAWN_SALT);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut world = new_world(seed);
+    let mut power_ups = init_power_ups(&player, &power_up_templates, &mut spawn_rng);
+    let mut background = new_background(0x5EED_57A8);
+    // Skip the menu straight into the missions.
+    let mut quick_start = if std::env::args().any(|arg| arg == "--campaign") {
+        Some(GameMode::Campaign)
+    } else {
+        None
+    };
+
+    let ship_texture = load_texture(&mut assets, &mut rl, &thread, "Images/V1Ship.png");
+    let enemy_warning_texture =
+        load_texture(&mut assets, &mut rl, &thread, "Images/EnemyWarning.png");
+    load_animations(&mut assets, &mut rl, &thread);
+    build_atlas(&mut assets, &mut rl, &thread);
+    let mut sprite_batch = new_sprite_batch();
 
     let mut playing: bool = true;
     let mut time = 0.0;
     while !rl.window_should_close() {
-        let dt = rl.get_frame_time();
+        end_profile_frame(&mut profiler);
+        let frame_time = rl.get_frame_time();
         let screenwidth = rl.get_screen_width();
         let screenheight = rl.get_screen_height();
-        if playing {
-            time += dt;
-        }
-        if rl.is_key_released(KeyboardKey::KEY_F3) {
+        let hud_scale =
+            f32::max(screenheight as f32 / 720.0, 0.5) * f32::max(rl.get_window_scale_dpi().y, 1.0);
+        // Checked before the console updates so the key that closes it doesn't reach the game.
+        let typing = console.open;
+        let mut commands: Vec<String> = update_console(&mut console, &mut rl).into_iter().collect();
+        if !typing && rl.is_key_released(KeyboardKey::KEY_F3) {
             debug = !debug;
+            log(
+                LogLevel::Debug,
+                LogCategory::Input,
+                &format!("debug view {}", if debug { "on" } else { "off" }),
+            );
+            // The time keys only work in the debug view, so don't leave the game stuck.
+            if !debug {
+                reset_time_control(&mut time_control);
+            }
+        }
+        if !typing && rl.is_key_released(KeyboardKey::KEY_F4) {
+            settings.damage_numbers = !settings.damage_numbers;
         }
-        if rl.is_key_released(KeyboardKey::KEY_ESCAPE) {
+        if !typing {
+            update_profiler(&mut profiler, &rl);
+        }
+        if debug && !mode_select && !typing && update_time_control(&mut time_control, &rl) {
+            load_snapshot(
+                &time_control,
+                &mut time,
+                &mut player,
+                &mut enemies,
+                &mut bullets,
+                &mut power_ups,
+                &mut wrecks,
+                &mut world,
+                &mut waves,
+                &mut campaign,
+                &mut score,
+                &mut director,
+                &mut spawn_rng,
+                &mut rng,
+            );
+            // The snapshot's score is from before, rewinding counts as a debug tool too.
+            score.cheated = true;
+        }
+        // A step runs one fixed tick and then the simulation freezes again.
+        let dt = if time_control.step {
+            STEP_TIME
+        } else {
+            frame_time * time_control.time_scale
+        };
+        let frozen = time_control.paused && !time_control.step;
+        let mut start = quick_start.take();
+        if typing {
+            // Keys go to the console while it's open.
+        } else if mode_select {
+            if rl.is_key_released(KeyboardKey::KEY_UP) {
+                selected_mode = (selected_mode + GAME_MODES.len() - 1) % GAME_MODES.len();
+            }
+            if rl.is_key_released(KeyboardKey::KEY_DOWN) {
+                selected_mode = (selected_mode + 1) % GAME_MODES.len();
+            }
+            if rl.is_key_released(KeyboardKey::KEY_ENTER) {
+                start = Some(GAME_MODES[selected_mode]);
+            }
+        } else if game_over.is_some() {
+            if rl.is_key_released(KeyboardKey::KEY_ENTER) {
+                mode_select = true;
+            }
+        } else if rl.is_key_released(KeyboardKey::KEY_ESCAPE) {
             playing = !playing;
+            log(
+                LogLevel::Debug,
+                LogCategory::Input,
+                if playing { "resumed" } else { "paused" },
+            );
+        }
+        if !playing && !mode_select && game_over.is_none() && !typing {
+            if rl.is_key_released(KeyboardKey::KEY_ONE) {
+                settings.difficulty = Difficulty::Easy;
+            }
+            if rl.is_key_released(KeyboardKey::KEY_TWO) {
+                settings.difficulty = Difficulty::Normal;
+            }
+            if rl.is_key_released(KeyboardKey::KEY_THREE) {
+                settings.difficulty = Difficulty::Hard;
+            }
+            if rl.is_key_released(KeyboardKey::KEY_A) {
+                settings.adaptive = !settings.adaptive;
+            }
+            if rl.is_key_released(KeyboardKey::KEY_FOUR) {
+                settings.master_volume = step_volume(settings.master_volume);
+            }
+            if rl.is_key_released(KeyboardKey::KEY_FIVE) {
+                settings.effects_volume = step_volume(settings.effects_volume);
+            }
+            if rl.is_key_released(KeyboardKey::KEY_SIX) {
+                settings.music_volume = step_volume(settings.music_volume);
+            }
+            if rl.is_key_released(KeyboardKey::KEY_Q) {
+                mode_select = true;
+            }
+        }
+
+        if let Some(new_mode) = start {
+            mode = new_mode;
+            campaign = match mode_campaign(&mode, &assets, &archetypes) {
+                Ok(campaign) => campaign,
+                Err(err) => {
+                    log(
+                        LogLevel::Warn,
+                        LogCategory::Waves,
+                        &format!("{err}, falling back to endless mode"),
+                    );
+                    mode = GameMode::Endless;
+                    None
+                }
+            };
+            // The world and the spawns share a seed so a daily run is the same everywhere.
+            seed = mode_seed(&mode);
+            spawn_rng = StdRng::seed_from_u64(seed ^ SPAWN_SALT);
+            rng = StdRng::seed_from_u64(seed);
+            world = new_world(seed);
+            player = init_player();
+            enemies.clear();
+            wrecks.clear();
+            bullets.clear();
+            clear_particals(&mut particals);
+            damage_numbers.clear();
+            hit_markers.clear();
+            power_ups = init_power_ups(&player, &power_up_templates, &mut spawn_rng);
+            waves = init_waves(&archetypes);
+            score = new_score();
+            director = new_director(&player);
+            time = 0.0;
+            playing = true;
+            mode_select = false;
+            game_over = None;
+            rank = None;
+            clear_snapshots(&mut time_control);
+            log(
+                LogLevel::Info,
+                LogCategory::Game,
+                &format!("starting {} with seed {seed}", mode_name(&mode)),
+            );
+            if console_enabled() {
+                commands.append(&mut console.script);
+            }
+        }
+        let difficulty = mode_difficulty(&mode, &settings, &director);
+        for command in commands {
+            log(
+                LogLevel::Debug,
+                LogCategory::Input,
+                &format!("console: {command}"),
+            );
+            if mode_select {
+                console_print(&mut console, "start a run first".to_string());
+                continue;
+            }
+            let changed = run_command(
+                &mut console,
+                &mut time_control,
+                &command,
+                &mut player,
+                &mut enemies,
+                &archetypes,
+                &mut waves,
+                &mut campaign,
+                &difficulty,
+                &mut spawn_rng,
+                seed,
+            );
+            // Anything that changes the run keeps it off the high score tables.
+            if changed {
+                score.cheated = true;
+            }
         }
         if rl.is_key_released(KeyboardKey::KEY_F11) {
             if rl.is_window_fullscreen() {
@@ -226,66 +1102,439 @@ fn main() {
             }
         }
 
-        if playing {
-            update_waves(&mut waves, &player, &mut enemies, dt);
+        let running = playing && !mode_select && game_over.is_none() && !console.open && !frozen;
+        let mut scope = profile_now(&profiler);
+        // Stepping a tick at a time or playing in slow motion is as much a debug tool as the
+        // console's timescale.
+        if running && (time_control.step || time_control.time_scale < 1.0) {
+            score.cheated = true;
+        }
+        if running {
+            time += dt;
+            update_director(&mut director, &player, &score, dt);
+            let difficulty = mode_difficulty(&mode, &settings, &director);
+            match &mut campaign {
+                Some(campaign) => update_campaign(
+                    campaign,
+                    &archetypes,
+                    &power_up_templates,
+                    &player,
+                    &mut enemies,
+                    &mut power_ups,
+                    &difficulty,
+                    &mut spawn_rng,
+                    dt,
+                ),
+                None => update_waves(
+                    &mut waves,
+                    &player,
+                    &mut enemies,
+                    &difficulty,
+                    &mut spawn_rng,
+                    time,
+                    dt,
+                ),
+            }
+            profile_scope(&mut profiler, "update_waves", &mut scope);
             update_player(
                 &mut player,
                 &mut enemies,
                 &mut bullets,
                 &mut particals,
+                &mut audio,
+                &world,
                 &rl,
+                &mut rng,
+                dt,
+            );
+            profile_scope(&mut profiler, "update_player", &mut scope);
+            update_bullets(
+                &mut player,
+                &mut bullets,
+                &mut enemies,
+                &mut particals,
+                &mut audio,
+                &mut damage_numbers,
+                dt,
+            );
+            profile_scope(&mut profiler, "update_bullets", &mut scope);
+            update_enemies(
+                &mut player,
+                &mut enemies,
+                &mut particals,
+                &mut audio,
+                &mut bullets,
+                &world,
+                &mut rng,
                 dt,
             );
-            update_bullets(&mut player, &mut bullets, &mut enemies, &mut particals, dt);
-            update_enemies(&mut player, &mut enemies, &mut particals, &mut bullets, dt);
+            profile_scope(&mut profiler, "update_enemies", &mut scope);
+            update_world(
+                &mut world,
+                &mut player,
+                &mut enemies,
+                &mut bullets,
+                &mut particals,
+                &mut audio,
+                &mut damage_numbers,
+                &mut rng,
+                dt,
+            );
+            profile_scope(&mut profiler, "update_world", &mut scope);
+            if console.god {
+                heal_player(&mut player);
+            }
+            update_score(&mut score, dt);
+            update_damage_numbers(&mut damage_numbers, &mut hit_markers, dt);
+            animate_enemies(&mut enemies, dt);
+            animate_power_ups(&mut power_ups, dt);
+            update_wrecks(&mut wrecks, &assets, dt);
+            profile_scope(&mut profiler, "update_misc", &mut scope);
+        }
+        // Keeps going after a game over so the ship gets to finish blowing up.
+        if playing && !mode_select && !frozen {
+            animate_player(&mut player, dt);
+        }
+        for enemy in &enemies {
+            if enemy.health <= 0.0 {
+                log(
+                    LogLevel::Debug,
+                    LogCategory::Collisions,
+                    &format!(
+                        "{} destroyed at {:.0}, {:.0}",
+                        enemy.name, enemy.pos.x, enemy.pos.y
+                    ),
+                );
+                add_wreck(&mut wrecks, enemy);
+                // Enemies also die crashing into each other, ramming or blowing themselves up,
+                // only the ones the player shot down count.
+                if enemy.killed_by_player {
+                    add_kill(&mut score, enemy);
+                    hit_markers.push(HitMarker {
+                        pos: enemy.pos,
+                        duration: 0.4,
+                        time: 0.0,
+                    });
+                }
+            }
         }
         enemies.retain(|enemy| (enemy.health > 0.0));
         bullets.retain(|bullet| bullet.time < bullet.duration);
+        if running && debug {
+            save_snapshot(
+                &mut time_control,
+                time,
+                &player,
+                &enemies,
+                &bullets,
+                &power_ups,
+                &wrecks,
+                &world,
+                &waves,
+                &campaign,
+                &score,
+                &director,
+                &spawn_rng,
+                &rng,
+            );
+        }
+        // After everything that removes enemies, so the pinned index is right when drawing.
+        follow_target(&mut inspector, &enemies);
+        if debug && !mode_select && !typing {
+            let edited = update_inspector(
+                &mut inspector,
+                &rl,
+                &mut player,
+                &mut enemies,
+                hud_scale,
+                screenwidth,
+                screenheight,
+            );
+            if edited {
+                score.cheated = true;
+            }
+        }
 
+        let counts = [
+            ("enemies", enemies.len()),
+            ("bullets", bullets.len()),
+            ("particals", particals.count),
+            ("power ups", power_ups.len()),
+            ("wrecks", wrecks.len()),
+            ("asteroids", world.asteroids.len()),
+            ("debris", world.debris.len()),
+            ("damage numbers", damage_numbers.len()),
+        ];
+        profile_counts(&mut profiler, &counts);
+        record_crash_state(seed, &mode, time, &counts, &settings);
+        record_input(&rl, frame_time, dt);
+        let mut scope = profile_now(&profiler);
+        update_background(
+            &mut background,
+            &mut rl,
+            &thread,
+            &player,
+            screenwidth,
+            screenheight,
+        );
         let mut d = rl.begin_drawing(&thread);
 
-        d.clear_background(Color::new(10, 10, 10, 255));
-        draw_background(&mut d, &player, screenwidth, screenheight);
-        if playing {
+        match &campaign {
+            Some(campaign) => d.clear_background(campaign.background),
+            None => d.clear_background(Color::new(10, 10, 10, 255)),
+        }
+        draw_background(&mut d, &background, &player, screenwidth, screenheight);
+        profile_scope(&mut profiler, "draw_background", &mut scope);
+        draw_world(&mut d, &player, &world, screenwidth, screenheight);
+        profile_scope(&mut profiler, "draw_world", &mut scope);
+        if running {
             update_particals(&mut particals, dt);
         }
-        draw_particals(&mut d, &player, &mut particals, screenwidth, screenheight);
+        profile_scope(&mut profiler, "update_particals", &mut scope);
+        draw_particals(
+            &mut sprite_batch,
+            &assets,
+            &player,
+            &particals,
+            screenwidth,
+            screenheight,
+        );
+        profile_scope(&mut profiler, "draw_particals", &mut scope);
         power_ups_update(
-            &mut d,
+            &mut sprite_batch,
+            &assets,
             &mut player,
             &mut power_ups,
+            &mut audio,
+            &mut rng,
             screenwidth,
             screenheight,
         );
-        if debug {
-            draw_debug_player(&mut d, &player, screenwidth, screenheight);
-        }
-        draw_player(&mut d, &player, &ship_image, screenwidth, screenheight);
-        if debug {
-            draw_debug_enemies(&mut d, &player, &enemies, screenwidth, screenheight);
-        }
+        profile_scope(&mut profiler, "draw_power_ups", &mut scope);
+        draw_player(
+            &mut sprite_batch,
+            &assets,
+            &player,
+            ship_texture,
+            screenwidth,
+            screenheight,
+        );
+        profile_scope(&mut profiler, "draw_player", &mut scope);
+        draw_wrecks(
+            &mut sprite_batch,
+            &assets,
+            &player,
+            &wrecks,
+            screenwidth,
+            screenheight,
+        );
+        profile_scope(&mut profiler, "draw_wrecks", &mut scope);
         draw_enemies(
             &mut d,
+            &mut sprite_batch,
+            &assets,
             &player,
             &enemies,
-            &textures,
-            &enemy_warning_image,
+            &world,
+            enemy_warning_texture,
+            screenwidth,
+            screenheight,
+        );
+        profile_scope(&mut profiler, "draw_enemies", &mut scope);
+        draw_bullets(
+            &mut sprite_batch,
+            &assets,
+            &player,
+            &mut bullets,
             screenwidth,
             screenheight,
         );
-        draw_bullets(&mut d, &player, &mut bullets, screenwidth, screenheight);
-        draw_timer(&mut d, time, screenwidth);
-        draw_part_health(&mut d, &player, &default_font, screenwidth);
+        profile_scope(&mut profiler, "draw_bullets", &mut scope);
+        draw_sprite_batch(&mut d, &mut sprite_batch, &assets);
+        profile_scope(&mut profiler, "draw_sprite_batch", &mut scope);
+        draw_shield(&mut d, &player, screenwidth, screenheight);
+        if debug {
+            draw_debug_player(&mut d, &player, screenwidth, screenheight);
+            draw_debug_enemies(&mut d, &player, &enemies, screenwidth, screenheight);
+        }
+        if settings.damage_numbers {
+            draw_damage_numbers(
+                &mut d,
+                &player,
+                &damage_numbers,
+                &hit_markers,
+                &hud_font,
+                screenwidth,
+                screenheight,
+            );
+        }
+        if mode_select {
+            draw_mode_select(
+                &mut d,
+                selected_mode,
+                &high_scores,
+                &hud_font,
+                hud_scale,
+                screenwidth,
+                screenheight,
+            );
+        } else {
+            draw_hud(
+                &mut d,
+                &player,
+                &enemies,
+                &world,
+                &score,
+                time,
+                &hud_font,
+                hud_scale,
+                screenwidth,
+                screenheight,
+            );
+            draw_sector(
+                &mut d,
+                &world,
+                &hud_font,
+                hud_scale,
+                screenwidth,
+                screenheight,
+            );
+            if mode == GameMode::TimeAttack {
+                draw_time_attack(
+                    &mut d,
+                    &score,
+                    time,
+                    &hud_font,
+                    hud_scale,
+                    screenwidth,
+                    screenheight,
+                );
+            }
+        }
+
+        if let Some(campaign) = campaign.as_ref().filter(|_| !mode_select) {
+            draw_campaign(
+                &mut d,
+                campaign,
+                &hud_font,
+                hud_scale,
+                screenwidth,
+                screenheight,
+            );
+        }
+        if let Some(won) = game_over.filter(|_| !mode_select) {
+            draw_game_over(
+                &mut d,
+                &mode,
+                won,
+                &score,
+                time,
+                &high_scores,
+                rank,
+                &hud_font,
+                hud_scale,
+                screenwidth,
+                screenheight,
+            );
+        } else if !playing && !mode_select {
+            draw_pause_menu(
+                &mut d,
+                &settings,
+                &hud_font,
+                hud_scale,
+                screenwidth,
+                screenheight,
+            );
+        }
 
         if debug {
-            draw_debug_text(&mut d, &player, &enemies, &particals, dt);
+            draw_debug_text(
+                &mut d,
+                &player,
+                &enemies,
+                &particals,
+                &audio,
+                &audio_backend,
+                &settings,
+                &director,
+                &sprite_batch,
+                frame_time,
+            );
+            if !mode_select {
+                draw_inspector(
+                    &mut d,
+                    &inspector,
+                    &player,
+                    &enemies,
+                    &hud_font,
+                    hud_scale,
+                    screenwidth,
+                    screenheight,
+                );
+            }
+        }
+        // Outside the debug block, the console can change the speed with it closed.
+        if !mode_select {
+            draw_time_control(
+                &mut d,
+                &time_control,
+                &hud_font,
+                hud_scale,
+                screenwidth,
+                screenheight,
+            );
         }
+        draw_console(&mut d, &console, &hud_font, hud_scale, screenwidth);
+        profile_scope(&mut profiler, "draw_hud", &mut scope);
+        draw_profiler(
+            &mut d,
+            &profiler,
+            &hud_font,
+            hud_scale,
+            screenwidth,
+            screenheight,
+        );
 
-        for part in &player.parts {
-            if part.health <= 0.0 {
-                playing = false
+        if running {
+            if let Some(won) = mode_result(&mode, &player, &score, &campaign, time) {
+                game_over = Some(won);
+                log(
+                    LogLevel::Info,
+                    LogCategory::Game,
+                    &format!(
+                        "{} run {} after {time:.1}s with {} points",
+                        mode_name(&mode),
+                        if won { "won" } else { "lost" },
+                        score.points
+                    ),
+                );
+                let entry = finish_run(&mode, won, &mut score, time);
+                if score.cheated {
+                    log(
+                        LogLevel::Info,
+                        LogCategory::Game,
+                        "debug tools were used, run not ranked",
+                    );
+                } else {
+                    rank = add_high_score(&mut high_scores, entry);
+                    save_high_scores(&high_score_path, &high_scores);
+                }
             }
         }
+        update_audio(
+            &mut audio,
+            &mut audio_backend,
+            &settings,
+            &player,
+            &enemies,
+            &director,
+            running,
+            dt,
+        );
+        profile_scope(&mut profiler, "update_audio", &mut scope);
+        // Ending the frame swaps buffers and waits out the frame limit.
+        drop(d);
+        profile_scope(&mut profiler, "present", &mut scope);
     }
 }
 
@@ -321,54 +1570,7 @@ fn rotatevector(vector: Vector2, angle: f32) -> Vector2 {
     }
 }
 
-fn enemy_dies(pos: Vector2, vel: Vector2, particals: &mut Vec<Partical>) {
-    particalexplosion(
-        particals,
-        pos,
-        vel,
-        0.0,
-        300.0,
-        500,
-        Color {
-            r: 200,
-            g: 200,
-            b: 50,
-            a: 255,
-        },
-        Color {
-            r: 255,
-            g: 0,
-            b: 0,
-            a: 100,
-        },
-        0.3,
-    );
-}
-
-fn particalexplosion(
-    particals: &mut Vec<Partical>,
-    pos: Vector2,
-    vel: Vector2,
-    force_min: f32,
-    force_max: f32,
-    amount: usize,
-    start_color: Color,
-    ending_color: Color,
-    duration: f32,
-) {
-    for _ in 0..amount {
-        particals.push(Partical {
-            pos: pos,
-            vel: vel
-                + angletovector(
-                    rand::thread_rng().gen_range(-std::f32::consts::PI..std::f32::consts::PI),
-                ) * rand::thread_rng().gen_range(force_min..force_max),
-            size: 5.0,
-            shape: ParticalShape::Square,
-            starting_color: start_color,
-            ending_color: ending_color,
-            duration: duration,
-            time: 0.0,
-        });
-    }
+fn enemy_dies(pos: Vector2, vel: Vector2, particals: &mut Particals, audio: &mut Audio) {
+    emit(particals, &ENEMY_DEATH, pos, vel);
+    play_sound(audio, SoundEffect::EnemyDeath, pos, vel);
 }
@@ -0,0 +1,210 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::Rng;
+use raylib::prelude::*;
+
+use crate::{
+    campaign_finished, current_difficulty, difficulty_preset, load_campaign, Assets, Campaign,
+    Difficulty, DifficultyPreset, Director, Enemy, FormationShape, GameMode, HighScore, Level,
+    LevelEvent, Player, PowerUpType, Score, Settings,
+};
+
+pub const GAME_MODES: [GameMode; 5] = [
+    GameMode::Endless,
+    GameMode::TimeAttack,
+    GameMode::BossRush,
+    GameMode::Daily,
+    GameMode::Campaign,
+];
+pub const TIME_ATTACK_LIMIT: f32 = 180.0;
+pub const TIME_ATTACK_QUOTA: u32 = 60;
+const TIME_ATTACK_BONUS: f32 = 50.0;
+
+pub fn mode_name(mode: &GameMode) -> &'static str {
+    match mode {
+        GameMode::Endless => "Endless",
+        GameMode::TimeAttack => "Time Attack",
+        GameMode::BossRush => "Boss Rush",
+        GameMode::Daily => "Daily Challenge",
+        GameMode::Campaign => "Campaign",
+    }
+}
+
+pub fn mode_description(mode: &GameMode) -> String {
+    match mode {
+        GameMode::Endless => "Survive as long as you can".to_string(),
+        GameMode::TimeAttack => format!(
+            "{} kills in {}:{:02}",
+            TIME_ATTACK_QUOTA,
+            TIME_ATTACK_LIMIT as u32 / 60,
+            TIME_ATTACK_LIMIT as u32 % 60
+        ),
+        GameMode::BossRush => "Bosses back to back".to_string(),
+        GameMode::Daily => format!("Same spawns for everyone on {}", date_string(today())),
+        GameMode::Campaign => "Scripted missions".to_string(),
+    }
+}
+
+/// Which high score table a run goes in. Each day gets its own daily table.
+pub fn high_score_table(mode: &GameMode) -> String {
+    match mode {
+        GameMode::Endless => "endless".to_string(),
+        GameMode::TimeAttack => "time_attack".to_string(),
+        GameMode::BossRush => "boss_rush".to_string(),
+        GameMode::Daily => format!("daily_{}", date_string(today())),
+        GameMode::Campaign => "campaign".to_string(),
+    }
+}
+
+/// Days since the unix epoch in UTC, so everyone playing on the same day shares a seed.
+pub fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() / 86400)
+        .unwrap_or(0)
+}
+
+/// YYYY-MM-DD for a day number, using Howard Hinnant's civil_from_days.
+pub fn date_string(day: u64) -> String {
+    let z = day as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    format!("{y:04}-{m:02}-{d:02}")
+}
+
+/// Mixed into the run seed for the spawn rng, so spawns and placement get a stream of their
+/// own that nothing the player does can draw from.
+pub const SPAWN_SALT: u64 = 0x5BA7_0F5E_ED00_0001;
+
+/// Seed for the run. The daily challenge hashes the date so the whole team gets the same
+/// run, every other mode is random.
+pub fn mode_seed(mode: &GameMode) -> u64 {
+    match mode {
+        GameMode::Daily => (today() ^ 0x5EED).wrapping_mul(0x9E37_79B9_7F4A_7C15),
+        _ => rand::thread_rng().gen(),
+    }
+}
+
+/// The script a mode plays instead of the endless waves, if it has one.
+pub fn mode_campaign(
+    mode: &GameMode,
+    assets: &Assets,
+    archetypes: &Vec<Enemy>,
+) -> Result<Option<Campaign>, String> {
+    match mode {
+        GameMode::Campaign => load_campaign(assets, archetypes).map(Some),
+        GameMode::BossRush => Ok(Some(boss_rush())),
+        _ => Ok(None),
+    }
+}
+
+fn boss_round(name: &str, mut events: Vec<LevelEvent>) -> Level {
+    events.push(LevelEvent::Clear);
+    events.push(LevelEvent::PowerUp(PowerUpType::Repair, None));
+    events.push(LevelEvent::Wait(4.0));
+    events.push(LevelEvent::Win);
+    Level {
+        name: name.to_string(),
+        events,
+        index: 0,
+        wait: 0.0,
+        message: String::new(),
+        message_time: 0.0,
+        complete: false,
+    }
+}
+
+fn boss_rush() -> Campaign {
+    Campaign {
+        levels: vec![
+            boss_round(
+                "Mothership",
+                vec![LevelEvent::Boss("Mothership".to_string())],
+            ),
+            boss_round(
+                "Mothership and Carrier",
+                vec![
+                    LevelEvent::Boss("Mothership".to_string()),
+                    LevelEvent::Boss("Carrier".to_string()),
+                ],
+            ),
+            boss_round(
+                "Twin Motherships",
+                vec![
+                    LevelEvent::Background(Color::new(25, 8, 12, 255)),
+                    LevelEvent::Boss("Mothership".to_string()),
+                    LevelEvent::Wait(10.0),
+                    LevelEvent::Boss("Mothership".to_string()),
+                    LevelEvent::Spawn {
+                        archetype: "Kamikaze".to_string(),
+                        amount: 5,
+                        formation: FormationShape::V,
+                        pos: None,
+                    },
+                ],
+            ),
+        ],
+        current: 0,
+        background: Color::new(10, 10, 10, 255),
+    }
+}
+
+/// The daily challenge ignores the difficulty settings so every run is comparable. Time
+/// attack spawns faster so the quota is reachable.
+pub fn mode_difficulty(
+    mode: &GameMode,
+    settings: &Settings,
+    director: &Director,
+) -> DifficultyPreset {
+    match mode {
+        GameMode::Daily => difficulty_preset(&Difficulty::Normal),
+        GameMode::TimeAttack => {
+            let mut preset = current_difficulty(settings, director);
+            preset.spawn_rate *= 1.5;
+            preset
+        }
+        _ => current_difficulty(settings, director),
+    }
+}
+
+/// Some(true) once the run is won, Some(false) once it's lost and None while it's going.
+pub fn mode_result(
+    mode: &GameMode,
+    player: &Player,
+    score: &Score,
+    campaign: &Option<Campaign>,
+    time: f32,
+) -> Option<bool> {
+    if player.parts.iter().any(|part| part.health <= 0.0) {
+        return Some(false);
+    }
+    match mode {
+        GameMode::TimeAttack if score.kills >= TIME_ATTACK_QUOTA => Some(true),
+        GameMode::TimeAttack if time >= TIME_ATTACK_LIMIT => Some(false),
+        GameMode::BossRush | GameMode::Campaign => campaign
+            .as_ref()
+            .is_some_and(campaign_finished)
+            .then_some(true),
+        _ => None,
+    }
+}
+
+/// Final score for the table. Beating the time attack quota early pays for the time left.
+pub fn finish_run(mode: &GameMode, won: bool, score: &mut Score, time: f32) -> HighScore {
+    if *mode == GameMode::TimeAttack && won {
+        score.points += ((TIME_ATTACK_LIMIT - time).max(0.0) * TIME_ATTACK_BONUS) as u32;
+    }
+    HighScore {
+        table: high_score_table(mode),
+        points: score.points,
+        kills: score.kills,
+        time,
+        date: date_string(today()),
+    }
+}
@@ -1,46 +1,360 @@
-
-use crate::{colorlerp, Partical, ParticalShape, Player};
+use rand::Rng;
 use raylib::prelude::*;
 
-pub fn update_particals(particals: &mut Vec<Partical>, dt: f32) {
-    for partical in &mut *particals {
-        partical.pos += partical.vel * dt;
-        partical.time += dt;
+use crate::{
+    angletovector, colorlerp, push_circle, push_rectangle, Assets, Curve, ParticalPreset,
+    ParticalPriority, ParticalShape, Particals, Player, SpriteBatch, PARTICAL_LAYER,
+};
+
+pub const PARTICAL_BUDGET: usize = 6000;
+
+pub const PLAYER_EXHAUST: ParticalPreset = ParticalPreset {
+    amount: 0,
+    force_min: 0.0,
+    force_max: 0.0,
+    shape: ParticalShape::Square,
+    start_size: 5.0,
+    end_size: 2.0,
+    size_curve: Curve::Linear,
+    starting_color: Color::new(140, 255, 251, 255),
+    ending_color: Color::new(255, 0, 50, 0),
+    color_curve: Curve::Linear,
+    duration: 1.0,
+    drag: 0.0,
+    spin: 0.0,
+    priority: ParticalPriority::Low,
+};
+pub const ENEMY_EXHAUST: ParticalPreset = ParticalPreset {
+    starting_color: Color::new(255, 255, 0, 255),
+    ..PLAYER_EXHAUST
+};
+pub const TURRET_EXHAUST: ParticalPreset = ParticalPreset {
+    start_size: 10.0,
+    end_size: 4.0,
+    ..ENEMY_EXHAUST
+};
+pub const MOTHERSHIP_EXHAUST: ParticalPreset = ParticalPreset {
+    start_size: 12.0,
+    end_size: 5.0,
+    starting_color: Color::new(255, 120, 0, 255),
+    ..PLAYER_EXHAUST
+};
+/// Billows out and thins as it rises off a damaged part.
+pub const DAMAGE_SMOKE: ParticalPreset = ParticalPreset {
+    amount: 0,
+    force_min: 0.0,
+    force_max: 0.0,
+    shape: ParticalShape::Circle,
+    start_size: 9.0,
+    end_size: 20.0,
+    size_curve: Curve::EaseOut,
+    starting_color: Color::new(120, 120, 120, 180),
+    ending_color: Color::new(40, 40, 40, 0),
+    color_curve: Curve::EaseIn,
+    duration: 1.2,
+    drag: 1.5,
+    spin: 0.0,
+    priority: ParticalPriority::Low,
+};
+pub const DAMAGE_SPARKS: ParticalPreset = ParticalPreset {
+    amount: 0,
+    force_min: 0.0,
+    force_max: 0.0,
+    shape: ParticalShape::Square,
+    start_size: 3.0,
+    end_size: 1.0,
+    size_curve: Curve::Linear,
+    starting_color: Color::new(255, 240, 120, 255),
+    ending_color: Color::new(255, 80, 0, 0),
+    color_curve: Curve::Linear,
+    duration: 0.3,
+    drag: 0.0,
+    spin: 0.0,
+    priority: ParticalPriority::Normal,
+};
+pub const ENEMY_DEATH: ParticalPreset = ParticalPreset {
+    amount: 300,
+    force_min: 0.0,
+    force_max: 450.0,
+    shape: ParticalShape::RotSquare,
+    start_size: 6.0,
+    end_size: 2.0,
+    size_curve: Curve::EaseIn,
+    starting_color: Color::new(200, 200, 50, 255),
+    ending_color: Color::new(255, 0, 0, 100),
+    color_curve: Curve::Linear,
+    duration: 0.4,
+    drag: 3.0,
+    spin: 12.0,
+    priority: ParticalPriority::High,
+};
+pub const BULLET_HIT: ParticalPreset = ParticalPreset {
+    amount: 50,
+    force_min: 0.0,
+    force_max: 600.0,
+    shape: ParticalShape::Square,
+    start_size: 5.0,
+    end_size: 2.0,
+    size_curve: Curve::Linear,
+    starting_color: Color::new(255, 0, 0, 255),
+    ending_color: Color::new(255, 255, 50, 0),
+    color_curve: Curve::Linear,
+    duration: 0.1,
+    drag: 0.0,
+    spin: 0.0,
+    priority: ParticalPriority::Normal,
+};
+pub const PLAYER_HIT: ParticalPreset = ParticalPreset {
+    starting_color: Color::new(140, 255, 251, 255),
+    ending_color: Color::new(255, 0, 50, 0),
+    ..BULLET_HIT
+};
+pub const RAM_IMPACT: ParticalPreset = ParticalPreset {
+    amount: 300,
+    force_max: 300.0,
+    shape: ParticalShape::RotSquare,
+    duration: 1.0,
+    drag: 2.0,
+    spin: 8.0,
+    priority: ParticalPriority::High,
+    ..PLAYER_HIT
+};
+/// Scale `force_max` to the blast radius when using it.
+pub const DETONATION: ParticalPreset = ParticalPreset {
+    amount: 400,
+    force_min: 0.0,
+    force_max: 300.0,
+    shape: ParticalShape::Circle,
+    start_size: 8.0,
+    end_size: 3.0,
+    size_curve: Curve::EaseOut,
+    starting_color: Color::new(255, 200, 80, 255),
+    ending_color: Color::new(255, 0, 0, 0),
+    color_curve: Curve::EaseIn,
+    duration: 0.35,
+    drag: 1.0,
+    spin: 0.0,
+    priority: ParticalPriority::High,
+};
+pub const EXPIRE_PUFF: ParticalPreset = ParticalPreset {
+    amount: 40,
+    force_max: 100.0,
+    duration: 0.6,
+    priority: ParticalPriority::Normal,
+    ..DAMAGE_SMOKE
+};
+/// Size it by how far the charge has got.
+pub const CHARGE_SPARK: ParticalPreset = ParticalPreset {
+    amount: 0,
+    force_min: 0.0,
+    force_max: 0.0,
+    shape: ParticalShape::Circle,
+    start_size: 2.0,
+    end_size: 1.0,
+    size_curve: Curve::Linear,
+    starting_color: Color::new(255, 80, 60, 255),
+    ending_color: Color::new(255, 255, 255, 0),
+    color_curve: Curve::Linear,
+    duration: 0.2,
+    drag: 0.0,
+    spin: 0.0,
+    priority: ParticalPriority::Normal,
+};
+pub const ROCK_CHIPS: ParticalPreset = ParticalPreset {
+    amount: 20,
+    force_min: 0.0,
+    force_max: 300.0,
+    shape: ParticalShape::RotSquare,
+    start_size: 4.0,
+    end_size: 2.0,
+    size_curve: Curve::Linear,
+    starting_color: Color::new(160, 150, 140, 255),
+    ending_color: Color::new(80, 70, 60, 0),
+    color_curve: Curve::EaseIn,
+    duration: 0.15,
+    drag: 2.0,
+    spin: 10.0,
+    priority: ParticalPriority::Normal,
+};
+/// Scale `amount` and `force_max` to the size of the rock.
+pub const ROCK_BREAK: ParticalPreset = ParticalPreset {
+    start_size: 6.0,
+    duration: 0.6,
+    priority: ParticalPriority::High,
+    ..ROCK_CHIPS
+};
+
+pub fn new_particals(budget: usize) -> Particals {
+    Particals {
+        count: 0,
+        pos: vec![Vector2::zero(); budget],
+        vel: vec![Vector2::zero(); budget],
+        rotation: vec![0.0; budget],
+        spin: vec![0.0; budget],
+        drag: vec![0.0; budget],
+        start_size: vec![0.0; budget],
+        end_size: vec![0.0; budget],
+        size_curve: vec![Curve::Linear; budget],
+        shape: vec![ParticalShape::Square; budget],
+        starting_color: vec![Color::BLANK; budget],
+        ending_color: vec![Color::BLANK; budget],
+        color_curve: vec![Curve::Linear; budget],
+        duration: vec![0.0; budget],
+        time: vec![0.0; budget],
+    }
+}
+
+pub fn clear_particals(particals: &mut Particals) {
+    particals.count = 0;
+}
+
+/// Low priority effects only get half the budget and normal ones most of it, so there's
+/// always room left for explosions.
+fn priority_budget(particals: &Particals, priority: &ParticalPriority) -> usize {
+    let budget = particals.pos.len();
+    match priority {
+        ParticalPriority::Low => budget / 2,
+        ParticalPriority::Normal => budget * 85 / 100,
+        ParticalPriority::High => budget,
+    }
+}
+
+/// Adds one partical moving at `vel`. Returns false when its priority is out of budget.
+pub fn spawn_partical(
+    particals: &mut Particals,
+    preset: &ParticalPreset,
+    pos: Vector2,
+    vel: Vector2,
+) -> bool {
+    if particals.count >= priority_budget(particals, &preset.priority) {
+        return false;
+    }
+    let index = particals.count;
+    let mut rng = rand::thread_rng();
+    particals.pos[index] = pos;
+    particals.vel[index] = vel;
+    particals.rotation[index] = rng.gen_range(0.0..std::f32::consts::TAU);
+    particals.spin[index] = rng.gen_range(-preset.spin..=preset.spin);
+    particals.drag[index] = preset.drag;
+    particals.start_size[index] = preset.start_size;
+    particals.end_size[index] = preset.end_size;
+    particals.size_curve[index] = preset.size_curve.clone();
+    particals.shape[index] = preset.shape.clone();
+    particals.starting_color[index] = preset.starting_color;
+    particals.ending_color[index] = preset.ending_color;
+    particals.color_curve[index] = preset.color_curve.clone();
+    particals.duration[index] = preset.duration;
+    particals.time[index] = 0.0;
+    particals.count += 1;
+    true
+}
+
+/// A burst of `preset.amount` particals flying out of `pos` on top of `vel`.
+pub fn emit(particals: &mut Particals, preset: &ParticalPreset, pos: Vector2, vel: Vector2) {
+    let mut rng = rand::thread_rng();
+    for _ in 0..preset.amount {
+        let force = if preset.force_max > preset.force_min {
+            rng.gen_range(preset.force_min..preset.force_max)
+        } else {
+            preset.force_min
+        };
+        let dir = angletovector(rng.gen_range(-std::f32::consts::PI..std::f32::consts::PI));
+        if !spawn_partical(particals, preset, pos, vel + dir * force) {
+            break;
+        }
+    }
+}
+
+fn curve(curve: &Curve, t: f32) -> f32 {
+    match curve {
+        Curve::Linear => t,
+        Curve::EaseIn => t * t,
+        Curve::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+    }
+}
+
+/// Moves the last live partical into a dead one's slot.
+fn kill_partical(particals: &mut Particals, index: usize) {
+    let last = particals.count - 1;
+    particals.pos.swap(index, last);
+    particals.vel.swap(index, last);
+    particals.rotation.swap(index, last);
+    particals.spin.swap(index, last);
+    particals.drag.swap(index, last);
+    particals.start_size.swap(index, last);
+    particals.end_size.swap(index, last);
+    particals.size_curve.swap(index, last);
+    particals.shape.swap(index, last);
+    particals.starting_color.swap(index, last);
+    particals.ending_color.swap(index, last);
+    particals.color_curve.swap(index, last);
+    particals.duration.swap(index, last);
+    particals.time.swap(index, last);
+    particals.count = last;
+}
+
+pub fn update_particals(particals: &mut Particals, dt: f32) {
+    let mut index = 0;
+    while index < particals.count {
+        particals.time[index] += dt;
+        if particals.time[index] >= particals.duration[index] {
+            // Whatever was last is now here and still needs updating.
+            kill_partical(particals, index);
+            continue;
+        }
+        let drag = f32::min(particals.drag[index] * dt, 1.0);
+        particals.vel[index] = particals.vel[index] * (1.0 - drag);
+        particals.pos[index] += particals.vel[index] * dt;
+        particals.rotation[index] += particals.spin[index] * dt;
+        index += 1;
     }
-    particals.retain(|partical| partical.time < partical.duration);
 }
 
 pub fn draw_particals(
-    d: &mut RaylibDrawHandle,
+    batch: &mut SpriteBatch,
+    assets: &Assets,
     player: &Player,
-    particals: &mut Vec<Partical>,
+    particals: &Particals,
     screenwidth: i32,
     screenheight: i32,
 ) {
-    for partical in particals {
-        let lerped_color = colorlerp(
-            partical.starting_color,
-            partical.ending_color,
-            partical.time / partical.duration,
+    let offset = Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0) - player.pos;
+    for index in 0..particals.count {
+        let pos = particals.pos[index] + offset;
+        if pos.x < -20.0
+            || pos.y < -20.0
+            || pos.x > screenwidth as f32 + 20.0
+            || pos.y > screenheight as f32 + 20.0
+        {
+            continue;
+        }
+        let t = particals.time[index] / particals.duration[index];
+        let size = particals.start_size[index]
+            + (particals.end_size[index] - particals.start_size[index])
+                * curve(&particals.size_curve[index], t);
+        let color = colorlerp(
+            particals.starting_color[index],
+            particals.ending_color[index],
+            curve(&particals.color_curve[index], t),
         );
-        match partical.shape {
-            ParticalShape::Square => d.draw_rectangle_v(
-                Vector2::new(
-                    partical.pos.x - player.pos.x + screenwidth as f32 / 2.0 - partical.size / 2.0,
-                    partical.pos.y - player.pos.y + screenheight as f32 / 2.0 - partical.size / 2.0,
-                ),
-                Vector2::new(partical.size, partical.size),
-                lerped_color,
-            ),
-            ParticalShape::Circle => d.draw_circle_v(
-                Vector2::new(
-                    partical.pos.x - player.pos.x + screenwidth as f32 / 2.0,
-                    partical.pos.y - player.pos.y + screenheight as f32 / 2.0,
-                ),
-                partical.size / 2.0,
-                lerped_color,
+        let rect = Rectangle::new(pos.x, pos.y, size, size);
+        let origin = Vector2::new(size / 2.0, size / 2.0);
+        match particals.shape[index] {
+            ParticalShape::Square => {
+                push_rectangle(batch, assets, rect, origin, 0.0, color, PARTICAL_LAYER)
+            }
+            ParticalShape::Circle => {
+                push_circle(batch, assets, pos, size / 2.0, color, PARTICAL_LAYER)
+            }
+            ParticalShape::RotSquare => push_rectangle(
+                batch,
+                assets,
+                rect,
+                origin,
+                particals.rotation[index].to_degrees(),
+                color,
+                PARTICAL_LAYER,
             ),
-            ParticalShape::RotSquare => {}
         }
     }
 }
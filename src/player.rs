@@ -1,11 +1,59 @@
+use rand::rngs::StdRng;
 use rand::Rng;
 
 use crate::{
-    angletovector, rotatevector, vectortoangle, Bullet, BulletEmitter, Damage, DamageType, Enemy,
-    Part, PartMod, Partical, ParticalEmitter, ParticalShape, Player,
+    angletovector, environment_at, ion_turn, new_animator, play_sound, push_animation, push_sprite,
+    rotatevector, spawn_partical, vectortoangle, AnimationState, Assets, Audio, Bullet,
+    BulletEmitter, Damage, DamageEmitter, DamageType, Enemy, Part, PartMod, ParticalEmitter,
+    Particals, Player, ShipMods, SoundEffect, Sprite, SpriteBatch, TextureID, World, DAMAGE_SMOKE,
+    DAMAGE_SPARKS, FLASH_LAYER, PLAYER_DAMAGE_LAYER, PLAYER_EXHAUST, PLAYER_LAYER,
 };
 use raylib::prelude::*;
 
+pub const PART_FLASH_TIME: f32 = 0.15;
+/// The guns fire on their own while an enemy is within this much of dead ahead, measured as
+/// how far the dot product with the facing falls short of 1.
+pub const AUTO_FIRE_CONE: f32 = 0.25;
+
+/// Every part back to full health.
+pub fn heal_player(player: &mut Player) {
+    for part in &mut player.parts {
+        part.health = part.starting_health;
+    }
+}
+
+/// Smoke below two thirds health and sparks below one third, emitted from the part itself.
+pub fn part_damage_emmiters() -> Vec<DamageEmitter> {
+    vec![
+        DamageEmitter {
+            threshold: 0.66,
+            partical_emmiter: ParticalEmitter {
+                pos: Vector2::zero(),
+                location: Vector2::zero(),
+                vel: Vector2::zero(),
+                speed_orginal: 40.0,
+                preset: DAMAGE_SMOKE,
+                partical_interval: 1.0 / 40.0,
+                time: 0.0,
+                speed: 0.0,
+            },
+        },
+        DamageEmitter {
+            threshold: 0.33,
+            partical_emmiter: ParticalEmitter {
+                pos: Vector2::zero(),
+                location: Vector2::zero(),
+                vel: Vector2::zero(),
+                speed_orginal: 250.0,
+                preset: DAMAGE_SPARKS,
+                partical_interval: 1.0 / 30.0,
+                time: 0.0,
+                speed: 0.0,
+            },
+        },
+    ]
+}
+
 pub fn init_player() -> Player {
     Player {
         pos: Vector2 { x: 50.0, y: 50.0 },
@@ -22,6 +70,8 @@ pub fn init_player() -> Player {
                 starting_health: 4.0,
                 size: 17.0,
                 name: "Left Engine".to_string(),
+                flash: 0.0,
+                damage_emmiters: part_damage_emmiters(),
             },
             Part {
                 pos: Vector2::zero(),
@@ -30,6 +80,8 @@ pub fn init_player() -> Player {
                 starting_health: 4.0,
                 size: 17.0,
                 name: "Right Engine".to_string(),
+                flash: 0.0,
+                damage_emmiters: part_damage_emmiters(),
             },
             Part {
                 pos: Vector2::zero(),
@@ -38,6 +90,8 @@ pub fn init_player() -> Player {
                 starting_health: 3.0,
                 size: 20.0,
                 name: "Main Body".to_string(),
+                flash: 0.0,
+                damage_emmiters: part_damage_emmiters(),
             },
         ],
         damage: vec![
@@ -83,21 +137,7 @@ pub fn init_player() -> Player {
                 location: Vector2::new(21.0, -26.0),
                 vel: Vector2::zero(),
                 speed_orginal: 200.0,
-                size: 5.0,
-                shape: ParticalShape::Square,
-                starting_color: Color {
-                    r: 140,
-                    g: 255,
-                    b: 251,
-                    a: 255,
-                },
-                ending_color: Color {
-                    r: 255,
-                    g: 0,
-                    b: 50,
-                    a: 0,
-                },
-                duration: 1.0,
+                preset: PLAYER_EXHAUST,
                 partical_interval: 1.0 / 400.0,
                 time: 0.0,
                 speed: 0.0,
@@ -107,21 +147,7 @@ pub fn init_player() -> Player {
                 location: Vector2::new(-21.0, -26.0),
                 vel: Vector2::zero(),
                 speed_orginal: 200.0,
-                size: 5.0,
-                shape: ParticalShape::Square,
-                starting_color: Color {
-                    r: 140,
-                    g: 255,
-                    b: 251,
-                    a: 255,
-                },
-                ending_color: Color {
-                    r: 255,
-                    g: 0,
-                    b: 50,
-                    a: 0,
-                },
-                duration: 1.0,
+                preset: PLAYER_EXHAUST,
                 partical_interval: 1.0 / 400.0,
                 time: 0.0,
                 speed: 0.0,
@@ -132,26 +158,42 @@ pub fn init_player() -> Player {
                 pos: Vector2::zero(),
                 location: Vector2 { x: 17.0, y: 13.0 },
                 size: 5.0,
+                speed: 500.0,
                 damage: 2.0,
                 friendly: true,
                 duration: 2.0,
                 bullet_interval: 1.0 / 7.5,
                 time: 0.0,
+                windup: 0.0,
+                aim_line: false,
+                aim: Vector2::zero(),
             },
             BulletEmitter {
                 pos: Vector2::zero(),
                 location: Vector2 { x: -17.0, y: 13.0 },
                 size: 5.0,
+                speed: 500.0,
                 damage: 2.0,
                 friendly: true,
                 duration: 2.0,
                 bullet_interval: 1.0 / 5.0,
                 time: 1.0 / 7.5 / 2.0,
+                windup: 0.0,
+                aim_line: false,
+                aim: Vector2::zero(),
             },
         ],
         speed: 0.0,
         left_turn: 0.0,
         right_turn: 0.0,
+        shield: 0.0,
+        animator: new_animator(&[
+            (AnimationState::Thrusting, "ship_thrust"),
+            (AnimationState::BankLeft, "ship_bank_left"),
+            (AnimationState::BankRight, "ship_bank_right"),
+            (AnimationState::Damaged, "ship_damaged"),
+            (AnimationState::Dying, "ship_dying"),
+        ]),
     }
 }
 
@@ -159,69 +201,49 @@ pub fn update_player(
     player: &mut Player,
     enemies: &mut Vec<Enemy>,
     bullets: &mut Vec<Bullet>,
-    particals: &mut Vec<Partical>,
+    particals: &mut Particals,
+    audio: &mut Audio,
+    world: &World,
     rl: &RaylibHandle,
+    rng: &mut StdRng,
     dt: f32,
 ) {
-    player.left_turn = player.left_turn_original;
-    player.right_turn = player.right_turn_original;
-    player.speed = player.speed_original;
+    player.shield = f32::max(player.shield - dt, 0.0);
+    let mods = damage_mods(
+        &player.parts,
+        &player.damage,
+        player.partical_emmiters.len(),
+        player.bullet_emmiters.len(),
+    );
+    let environment = environment_at(world, player.pos);
+    let ion = 1.0 - environment.ion * 0.6;
+    player.left_turn = player.left_turn_original * mods.left_turn * ion;
+    player.right_turn = player.right_turn_original * mods.right_turn * ion;
+    player.speed = player.speed_original * mods.speed;
 
-    for partical_emmiter in &mut player.partical_emmiters {
-        partical_emmiter.speed = partical_emmiter.speed_orginal
+    for (index, partical_emmiter) in player.partical_emmiters.iter_mut().enumerate() {
+        partical_emmiter.speed = partical_emmiter.speed_orginal * mods.partical[index]
     }
 
     let mut fire: bool = false;
     for enemy in enemies {
-        for partical_emmiter in &mut enemy.partical_emmiters {
-            partical_emmiter.speed = partical_emmiter.speed_orginal
-        }
-        if ((enemy.pos - player.pos).normalized().dot(player.dir) - 1.0).abs() < 0.25 {
+        if ((enemy.pos - player.pos).normalized().dot(player.dir) - 1.0).abs() < AUTO_FIRE_CONE {
             fire = true
         }
     }
 
-    for damage in &player.damage {
-        let mut health = 0.0;
-        let mut total_health = 0.0;
-        for src in &damage.src {
-            health += player.parts[*src].health;
-            total_health += player.parts[*src].starting_health;
-        }
-        let value = health / total_health;
-        match damage.damage_type {
-            DamageType::Mult => match damage.des {
-                PartMod::Partical => {
-                    player.partical_emmiters[damage.index].speed *= value * damage.scale
-                }
-                PartMod::Gun => {
-                    player.bullet_emmiters[damage.index].bullet_interval *= value * damage.scale
-                }
-                PartMod::TurnLeft => player.left_turn *= value * damage.scale,
-                PartMod::TurnRight => player.right_turn *= value * damage.scale,
-                PartMod::Speed => player.speed *= value * damage.scale,
-            },
-            DamageType::Div => match damage.des {
-                PartMod::Partical => {
-                    player.partical_emmiters[damage.index].speed /= value * damage.scale
-                }
-                PartMod::Gun => {
-                    player.bullet_emmiters[damage.index].bullet_interval /= value * damage.scale
-                }
-                PartMod::TurnLeft => player.left_turn /= value * damage.scale,
-                PartMod::TurnRight => player.right_turn /= value * damage.scale,
-                PartMod::Speed => player.speed /= value * damage.scale,
-            },
-        }
-    }
+    player.animator.steer = 0.0;
     if rl.is_key_down(KeyboardKey::KEY_A) {
         player.dir =
             angletovector(vectortoangle(player.dir) - (player.left_turn.to_radians() * dt));
+        player.animator.steer -= 1.0;
     }
     if rl.is_key_down(KeyboardKey::KEY_D) {
         player.dir =
             angletovector(vectortoangle(player.dir) + (player.right_turn.to_radians() * dt));
+        player.animator.steer += 1.0;
     }
+    player.dir = ion_turn(player.dir, &environment, rng, dt);
     player.vel += player.dir.normalized()
         * (player.speed
             - (player.vel.length() * (2.0 + (player.vel.normalized().dot(player.dir) - 1.0))
@@ -229,6 +251,8 @@ pub fn update_player(
         * dt;
     let right = rotatevector(player.dir, std::f32::consts::PI / 2.0);
     player.vel -= right * (right.dot(player.vel)) * 1.0 * dt;
+    player.vel += environment.gravity * dt;
+    player.vel -= player.vel * f32::min(environment.drag * dt, 1.0);
     player.pos += player.vel * dt;
     for part in &mut player.parts {
         part.pos = player.pos
@@ -237,6 +261,7 @@ pub fn update_player(
                 vectortoangle(player.dir) - std::f32::consts::PI / 2.0,
             )
     }
+    update_part_damage(&mut player.parts, player.vel, particals, dt);
     for partical_emmiter in &mut player.partical_emmiters {
         partical_emmiter.pos = player.pos
             + rotatevector(
@@ -249,28 +274,24 @@ pub fn update_player(
                 rand::thread_rng().gen_range(-std::f32::consts::PI..std::f32::consts::PI),
             ) * rand::thread_rng().gen_range(20.0..40.0);
         while partical_emmiter.time > partical_emmiter.partical_interval {
-            particals.push(Partical {
-                pos: partical_emmiter.pos,
-                vel: partical_emmiter.vel,
-                size: partical_emmiter.size,
-                shape: partical_emmiter.shape.clone(),
-                starting_color: partical_emmiter.starting_color,
-                ending_color: partical_emmiter.ending_color,
-                duration: partical_emmiter.duration,
-                time: 0.0,
-            });
+            spawn_partical(
+                particals,
+                &partical_emmiter.preset,
+                partical_emmiter.pos,
+                partical_emmiter.vel,
+            );
             partical_emmiter.time -= partical_emmiter.partical_interval;
         }
         partical_emmiter.time += dt;
     }
 
-    for bullet_emmiter in &mut player.bullet_emmiters {
+    for (index, bullet_emmiter) in player.bullet_emmiters.iter_mut().enumerate() {
         bullet_emmiter.pos = player.pos
             + rotatevector(
                 bullet_emmiter.location,
                 vectortoangle(player.dir) - std::f32::consts::PI / 2.0,
             );
-        let vel = player.vel + player.dir * 500.0;
+        let vel = player.vel + player.dir * bullet_emmiter.speed;
         while bullet_emmiter.time > bullet_emmiter.bullet_interval {
             if fire {
                 bullets.push(Bullet {
@@ -282,35 +303,214 @@ pub fn update_player(
                     duration: bullet_emmiter.duration,
                     time: 0.0,
                 });
+                play_sound(
+                    audio,
+                    SoundEffect::PlayerFire,
+                    bullet_emmiter.pos,
+                    player.vel,
+                );
             }
             bullet_emmiter.time -= bullet_emmiter.bullet_interval;
         }
-        bullet_emmiter.time += dt;
+        bullet_emmiter.time += dt * mods.gun[index];
+    }
+}
+
+/// Multipliers for a ship's stats from the health of the parts feeding each damage link.
+/// A gun multiplier scales how fast it charges, so a destroyed part can stop it firing.
+pub fn damage_mods(
+    parts: &Vec<Part>,
+    damage: &Vec<Damage>,
+    partical_count: usize,
+    gun_count: usize,
+) -> ShipMods {
+    let mut mods = ShipMods {
+        speed: 1.0,
+        left_turn: 1.0,
+        right_turn: 1.0,
+        partical: vec![1.0; partical_count],
+        gun: vec![1.0; gun_count],
+    };
+    for damage in damage {
+        let mut health = 0.0;
+        let mut total_health = 0.0;
+        for src in &damage.src {
+            health += parts[*src].health;
+            total_health += parts[*src].starting_health;
+        }
+        let value = f32::max(health / total_health, 0.0);
+        let modifier = match damage.damage_type {
+            DamageType::Mult => value * damage.scale,
+            DamageType::Div => 1.0 / f32::max(value * damage.scale, 0.01),
+        };
+        match damage.des {
+            PartMod::Partical => mods.partical[damage.index] *= modifier,
+            PartMod::Gun => mods.gun[damage.index] *= modifier,
+            PartMod::TurnLeft => mods.left_turn *= modifier,
+            PartMod::TurnRight => mods.right_turn *= modifier,
+            PartMod::Speed => mods.speed *= modifier,
+        }
+    }
+    mods
+}
+
+pub fn update_part_damage(parts: &mut Vec<Part>, vel: Vector2, particals: &mut Particals, dt: f32) {
+    for part in parts {
+        part.flash = f32::max(part.flash - dt, 0.0);
+        let health = part.health / part.starting_health;
+        for damage_emmiter in &mut part.damage_emmiters {
+            let partical_emmiter = &mut damage_emmiter.partical_emmiter;
+            if health >= damage_emmiter.threshold {
+                partical_emmiter.time = 0.0;
+                continue;
+            }
+            // Emit faster the further the part has dropped below the threshold.
+            let intensity = 1.0 + (damage_emmiter.threshold - health) / damage_emmiter.threshold;
+            partical_emmiter.pos = part.pos;
+            partical_emmiter.time += dt * intensity;
+            while partical_emmiter.time > partical_emmiter.partical_interval {
+                spawn_partical(
+                    particals,
+                    &partical_emmiter.preset,
+                    partical_emmiter.pos,
+                    vel + angletovector(
+                        rand::thread_rng().gen_range(-std::f32::consts::PI..std::f32::consts::PI),
+                    ) * partical_emmiter.speed_orginal
+                        * rand::thread_rng().gen_range(0.5..1.0),
+                );
+                partical_emmiter.time -= partical_emmiter.partical_interval;
+            }
+        }
+    }
+}
+
+/// Redraws the region of `image` under each part tinted by its damage, plus a white flash
+/// after a hit. `pos` is the screen position the ship sprite is centered on. The flashes go
+/// on the flash layer so they're all blended in one go.
+pub fn draw_part_damage(
+    batch: &mut SpriteBatch,
+    assets: &Assets,
+    texture: TextureID,
+    scale: f32,
+    pos: Vector2,
+    ship_pos: Vector2,
+    dir: Vector2,
+    parts: &Vec<Part>,
+    layer: i32,
+) {
+    let size = assets.sizes[texture];
+    let rotation = vectortoangle(dir).to_degrees() + 90.0;
+    for part in parts {
+        let damage = 1.0 - (part.health / part.starting_health).clamp(0.0, 1.0);
+        if damage <= 0.0 && part.flash <= 0.0 {
+            continue;
+        }
+        // Part locations are rotated the opposite way to the sprite, so the part sits at
+        // -location in sprite space.
+        let center = Vector2::new(
+            size.x / 2.0 - part.location.x / scale,
+            size.y / 2.0 - part.location.y / scale,
+        );
+        let radius = part.size / scale;
+        let min = Vector2::new(
+            f32::max(center.x - radius, 0.0),
+            f32::max(center.y - radius, 0.0),
+        );
+        let max = Vector2::new(
+            f32::min(center.x + radius, size.x),
+            f32::min(center.y + radius, size.y),
+        );
+        if max.x <= min.x || max.y <= min.y {
+            continue;
+        }
+        let source = Rectangle::new(min.x, min.y, max.x - min.x, max.y - min.y);
+        let part_pos = pos + part.pos - ship_pos;
+        let dest = Rectangle::new(
+            part_pos.x,
+            part_pos.y,
+            source.width * scale,
+            source.height * scale,
+        );
+        let origin = (center - min) * scale;
+        let sprite = Sprite {
+            texture,
+            source,
+            dest,
+            origin,
+            rotation,
+            tint: Color::new(255, 60, 30, (damage * 200.0) as u8),
+            layer,
+            additive: false,
+        };
+        if damage > 0.0 {
+            push_sprite(batch, assets, sprite.clone());
+        }
+        if part.flash > 0.0 {
+            push_sprite(
+                batch,
+                assets,
+                Sprite {
+                    tint: Color::new(255, 255, 255, (part.flash / PART_FLASH_TIME * 255.0) as u8),
+                    layer: FLASH_LAYER,
+                    additive: true,
+                    ..sprite
+                },
+            );
+        }
     }
 }
 
 pub fn draw_player(
-    d: &mut RaylibDrawHandle,
+    batch: &mut SpriteBatch,
+    assets: &Assets,
     player: &Player,
-    ship_image: &Texture2D,
+    ship_texture: TextureID,
     screenwidth: i32,
     screenheight: i32,
 ) {
     let ship_scale = 2.0;
-    d.draw_texture_pro(
-        &ship_image,
-        Rectangle::new(0.0, 0.0, ship_image.width as f32, ship_image.height as f32),
-        Rectangle::new(
-            screenwidth as f32 / 2.0,
-            screenheight as f32 / 2.0,
-            ship_image.width as f32 * ship_scale,
-            ship_image.height as f32 * ship_scale,
-        ),
-        Vector2::new(
-            ship_image.width as f32 / 2.0 * ship_scale,
-            ship_image.height as f32 / 2.0 * ship_scale,
-        ),
+    let center = Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0);
+    push_animation(
+        batch,
+        assets,
+        &player.animator,
+        ship_texture,
+        center,
+        ship_scale,
         vectortoangle(player.dir).to_degrees() + 90.0,
-        Color::WHITE,
+        PLAYER_LAYER,
+    );
+    // The damage overlay is cut from the idle sprite, so it would float over the wreck.
+    if player.animator.state == AnimationState::Dying {
+        return;
+    }
+    draw_part_damage(
+        batch,
+        assets,
+        ship_texture,
+        ship_scale,
+        center,
+        player.pos,
+        player.dir,
+        &player.parts,
+        PLAYER_DAMAGE_LAYER,
     );
 }
+
+/// Drawn straight away rather than batched, after the sprites so it sits over the ship.
+pub fn draw_shield(d: &mut RaylibDrawHandle, player: &Player, screenwidth: i32, screenheight: i32) {
+    // Blink for the last two seconds so the player knows it is about to drop.
+    if player.shield > 2.0 || (player.shield > 0.0 && (player.shield * 8.0) as i32 % 2 == 0) {
+        let center = Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0);
+        d.draw_circle_v(center, 42.0, Color::new(140, 255, 251, 40));
+        d.draw_ring(
+            center,
+            40.0,
+            42.0,
+            0.0,
+            360.0,
+            48,
+            Color::new(140, 255, 251, 200),
+        );
+    }
+}
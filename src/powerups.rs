@@ -1,61 +1,83 @@
 use rand::prelude::*;
 use raylib::prelude::*;
 
-use crate::{angletovector, get_2_mut, Player, PowerUp, PowerUpType};
+use crate::{
+    angletovector, heal_player, play_sound, push_animation, push_texture, Assets, Audio, Player,
+    PowerUp, PowerUpType, SoundEffect, SpriteBatch, MARKER_LAYER, POWER_UP_LAYER,
+};
+
+pub const SHIELD_DURATION: f32 = 10.0;
+
+pub fn apply_power_up(player: &mut Player, power_type: &PowerUpType) {
+    match power_type {
+        PowerUpType::Shield => player.shield = SHIELD_DURATION,
+        PowerUpType::Repair => heal_player(player),
+    }
+}
+
+/// A fresh set for a new run: repair on top of the player, shield somewhere nearby.
+pub fn init_power_ups(player: &Player, templates: &Vec<PowerUp>, rng: &mut StdRng) -> Vec<PowerUp> {
+    let mut power_ups = templates.clone();
+    for power_up in power_ups.iter_mut() {
+        power_up.pos = match power_up.power_type {
+            PowerUpType::Repair => player.pos,
+            PowerUpType::Shield => {
+                player.pos
+                    + angletovector(rng.gen_range(-std::f32::consts::PI..std::f32::consts::PI))
+                        * 1500.0
+            }
+        };
+    }
+    power_ups
+}
 
 pub fn power_ups_update(
-    d: &mut RaylibDrawHandle,
+    batch: &mut SpriteBatch,
+    assets: &Assets,
     player: &mut Player,
     power_ups: &mut Vec<PowerUp>,
+    audio: &mut Audio,
+    rng: &mut StdRng,
     screenwidth: i32,
     screenheight: i32,
 ) {
-    for power_up in power_ups {
+    for power_up in power_ups.iter_mut() {
+        let center = Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0);
         for part_index in 0..player.parts.len() {
-            let part = &mut player.parts[part_index];
+            let part = &player.parts[part_index];
             if part.pos.distance_to(power_up.pos) < part.size + 16.0 {
-                match power_up.power_type {
-                    PowerUpType::Shield => {}
-                    PowerUpType::Repair => {
-                        for other_part_index in 0..player.parts.len() {
-                            let Some((part, other_part)) =
-                                get_2_mut(&mut player.parts, part_index, other_part_index)
-                            else {
-                                continue;
-                            };
-                            part.health = part.starting_health;
-                            other_part.health = other_part.starting_health;
-                        }
-                        power_up.pos = player.pos
-                            + angletovector(
-                                rand::thread_rng()
-                                    .gen_range(-std::f32::consts::PI..std::f32::consts::PI),
-                            ) * rand::thread_rng().gen_range(2000.0..2500.0)
-                    }
+                play_sound(audio, SoundEffect::PowerUp, power_up.pos, player.vel);
+                apply_power_up(player, &power_up.power_type);
+                if power_up.respawn {
+                    power_up.pos = player.pos
+                        + angletovector(rng.gen_range(-std::f32::consts::PI..std::f32::consts::PI))
+                            * rng.gen_range(2000.0..2500.0)
+                } else {
+                    power_up.collected = true;
                 }
             }
         }
-        d.draw_texture_v(
-            &power_up.texture,
-            power_up.pos - player.pos
-                + Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0)
-                - Vector2::new(
-                    *&power_up.texture.width as f32 / 2.0,
-                    *&power_up.texture.height as f32 / 2.0,
-                ),
-            Color::WHITE,
+        push_animation(
+            batch,
+            assets,
+            &power_up.animator,
+            power_up.texture,
+            power_up.pos - player.pos + center,
+            1.0,
+            0.0,
+            POWER_UP_LAYER,
         );
         if player.pos.distance_to(power_up.pos) > 210.0 {
-            d.draw_texture_v(
-                &&power_up.texture,
-                (power_up.pos - player.pos).normalized() * 210.0
-                    + Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0)
-                    - Vector2::new(
-                        *&power_up.texture.width as f32 / 2.0,
-                        *&power_up.texture.height as f32 / 2.0,
-                    ),
-                Color::WHITE,
-            )
+            push_texture(
+                batch,
+                assets,
+                power_up.texture,
+                (power_up.pos - player.pos).normalized() * 210.0 + center,
+                1.0,
+                0.0,
+                MARKER_LAYER,
+            );
         }
     }
+    power_ups.retain(|power_up| !power_up.collected);
 }
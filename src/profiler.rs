@@ -0,0 +1,352 @@
+use raylib::prelude::*;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::{
+    anchor_pos, asset_root, log, Anchor, LogCategory, LogLevel, ProfileFrame, ProfileScope,
+    Profiler,
+};
+
+pub const TRACE_FILE: &str = "profile_trace.json";
+// About four seconds at 60 fps, one bar per frame.
+const HISTORY_FRAMES: usize = 240;
+const BAR_WIDTH: f32 = 1.0;
+const GRAPH_HEIGHT: f32 = 100.0;
+// The top of the graph, twice the 60 fps budget.
+const GRAPH_MS: f32 = 1000.0 / 30.0;
+const BUDGET_MS: f32 = 1000.0 / 60.0;
+const ROW_HEIGHT: f32 = 16.0;
+const FONT_SIZE: f32 = 14.0;
+const MARGIN: f32 = 10.0;
+const COUNTS_PER_ROW: usize = 4;
+const PALETTE: [Color; 20] = [
+    Color::new(236, 28, 36, 255),
+    Color::new(255, 127, 39, 255),
+    Color::new(255, 242, 0, 255),
+    Color::new(34, 177, 76, 255),
+    Color::new(140, 255, 251, 255),
+    Color::new(0, 162, 232, 255),
+    Color::new(63, 72, 204, 255),
+    Color::new(163, 73, 164, 255),
+    Color::new(255, 174, 201, 255),
+    Color::new(181, 230, 29, 255),
+    Color::new(185, 122, 87, 255),
+    Color::new(200, 191, 231, 255),
+    Color::new(136, 0, 21, 255),
+    Color::new(239, 228, 176, 255),
+    Color::new(153, 217, 234, 255),
+    Color::new(112, 146, 190, 255),
+    Color::new(255, 201, 14, 255),
+    Color::new(0, 128, 128, 255),
+    Color::new(255, 255, 255, 255),
+    Color::new(128, 128, 0, 255),
+];
+
+pub fn new_profiler() -> Profiler {
+    Profiler {
+        epoch: Instant::now(),
+        frame_start: 0.0,
+        scopes: vec![],
+        counts: vec![],
+        history: VecDeque::new(),
+        open: false,
+    }
+}
+
+/// Microseconds since the profiler was made.
+pub fn profile_now(profiler: &Profiler) -> f64 {
+    profiler.epoch.elapsed().as_secs_f64() * 1_000_000.0
+}
+
+/// Records everything from `start` until now under `name`, then moves `start` up to now so the
+/// next system in line can be timed straight after.
+pub fn profile_scope(profiler: &mut Profiler, name: &'static str, start: &mut f64) {
+    let now = profile_now(profiler);
+    profiler.scopes.push(ProfileScope {
+        name,
+        start: *start,
+        duration: now - *start,
+    });
+    *start = now;
+}
+
+pub fn profile_counts(profiler: &mut Profiler, counts: &[(&'static str, usize)]) {
+    profiler.counts = counts.to_vec();
+}
+
+/// Called at the top of the loop, closes off the last frame and starts timing the next.
+pub fn end_profile_frame(profiler: &mut Profiler) {
+    let now = profile_now(profiler);
+    // The first "frame" is all the loading before the loop, it would squash the graph.
+    if !profiler.scopes.is_empty() {
+        profiler.history.push_back(ProfileFrame {
+            start: profiler.frame_start,
+            duration: now - profiler.frame_start,
+            scopes: std::mem::take(&mut profiler.scopes),
+            counts: profiler.counts.clone(),
+        });
+        if profiler.history.len() > HISTORY_FRAMES {
+            profiler.history.pop_front();
+        }
+    }
+    profiler.frame_start = now;
+}
+
+/// F10 shows the overlay, F9 writes the history out as a Chrome trace.
+pub fn update_profiler(profiler: &mut Profiler, rl: &RaylibHandle) {
+    if rl.is_key_pressed(KeyboardKey::KEY_F10) {
+        profiler.open = !profiler.open;
+    }
+    if rl.is_key_pressed(KeyboardKey::KEY_F9) {
+        // Next to the logs and crash reports rather than wherever the game was started from.
+        let path = asset_root().join(TRACE_FILE);
+        match export_trace(profiler, &path) {
+            Ok(events) => log(
+                LogLevel::Info,
+                LogCategory::Game,
+                &format!("wrote {events} trace events to {}", path.display()),
+            ),
+            Err(err) => log(
+                LogLevel::Error,
+                LogCategory::Game,
+                &format!("{}: {err}, trace not saved", path.display()),
+            ),
+        }
+    }
+}
+
+fn trace_event(name: &str, start: f64, duration: f64) -> String {
+    format!(
+        "{{\"name\":\"{name}\",\"ph\":\"X\",\"ts\":{start:.3},\"dur\":{duration:.3},\"pid\":1,\"tid\":1}}"
+    )
+}
+
+/// Writes the frames in the history in the Trace Event Format, which chrome://tracing and
+/// Perfetto can both open. Entity counts go in as counter tracks.
+pub fn export_trace(profiler: &Profiler, path: &Path) -> std::io::Result<usize> {
+    let mut events = vec![];
+    for frame in &profiler.history {
+        events.push(trace_event("frame", frame.start, frame.duration));
+        for scope in &frame.scopes {
+            events.push(trace_event(scope.name, scope.start, scope.duration));
+        }
+        let args: Vec<String> = frame
+            .counts
+            .iter()
+            .map(|(name, count)| format!("\"{name}\":{count}"))
+            .collect();
+        events.push(format!(
+            "{{\"name\":\"entities\",\"ph\":\"C\",\"ts\":{:.3},\"pid\":1,\"tid\":1,\"args\":{{{}}}}}",
+            frame.start,
+            args.join(",")
+        ));
+    }
+    fs::write(
+        path,
+        format!(
+            "{{\"traceEvents\":[\n{}\n],\"displayTimeUnit\":\"ms\"}}\n",
+            events.join(",\n")
+        ),
+    )?;
+    Ok(events.len())
+}
+
+/// Every scope name seen in the history, in the order the systems run.
+fn scope_names(profiler: &Profiler) -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = vec![];
+    for frame in &profiler.history {
+        for scope in &frame.scopes {
+            if !names.contains(&scope.name) {
+                names.push(scope.name);
+            }
+        }
+    }
+    names
+}
+
+fn scope_ms(frame: &ProfileFrame, name: &str) -> f32 {
+    frame
+        .scopes
+        .iter()
+        .filter(|scope| scope.name == name)
+        .map(|scope| scope.duration)
+        .sum::<f64>() as f32
+        / 1000.0
+}
+
+fn percentile(sorted: &[f32], fraction: f32) -> f32 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    sorted[((sorted.len() - 1) as f32 * fraction).round() as usize]
+}
+
+/// Average, 50th, 95th and 99th percentile.
+fn stats(mut times: Vec<f32>) -> [f32; 4] {
+    times.sort_by(f32::total_cmp);
+    let average = times.iter().sum::<f32>() / f32::max(times.len() as f32, 1.0);
+    [
+        average,
+        percentile(&times, 0.5),
+        percentile(&times, 0.95),
+        percentile(&times, 0.99),
+    ]
+}
+
+/// Stacked bar per frame along the bottom left, newest on the right, with a table of timings
+/// per system and the entity counts underneath. Time outside every scope is drawn gray.
+pub fn draw_profiler(
+    d: &mut RaylibDrawHandle,
+    profiler: &Profiler,
+    font: &WeakFont,
+    scale: f32,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    if !profiler.open {
+        return;
+    }
+    let names = scope_names(profiler);
+    let font_size = FONT_SIZE * scale;
+    let row_height = ROW_HEIGHT * scale;
+    let graph = Vector2::new(HISTORY_FRAMES as f32 * BAR_WIDTH, GRAPH_HEIGHT) * scale;
+    // The header, one row per scope, the frame total and two rows of entity counts.
+    let rows = names.len() + 4;
+    let size = Vector2::new(
+        f32::max(graph.x, 420.0 * scale),
+        graph.y + rows as f32 * row_height + 8.0 * scale,
+    );
+    let pos = anchor_pos(
+        &Anchor::BottomLeft,
+        Vector2::new(MARGIN, MARGIN) * scale,
+        size,
+        screenwidth,
+        screenheight,
+    );
+    d.draw_rectangle_v(
+        pos - Vector2::new(6.0, 6.0) * scale,
+        size + Vector2::new(12.0, 12.0) * scale,
+        Color::new(0, 0, 0, 200),
+    );
+
+    let ms_to_height = graph.y / GRAPH_MS;
+    for (index, frame) in profiler.history.iter().enumerate() {
+        let x =
+            pos.x + (HISTORY_FRAMES - profiler.history.len() + index) as f32 * BAR_WIDTH * scale;
+        let mut y = pos.y + graph.y;
+        let mut total = 0.0;
+        for (color, name) in names.iter().enumerate() {
+            let ms = scope_ms(frame, name);
+            let height = f32::min(ms * ms_to_height, y - pos.y);
+            d.draw_rectangle_v(
+                Vector2::new(x, y - height),
+                Vector2::new(BAR_WIDTH * scale, height),
+                PALETTE[color % PALETTE.len()],
+            );
+            y -= height;
+            total += ms;
+        }
+        let other = frame.duration as f32 / 1000.0 - total;
+        let height = f32::min(f32::max(other, 0.0) * ms_to_height, y - pos.y);
+        d.draw_rectangle_v(
+            Vector2::new(x, y - height),
+            Vector2::new(BAR_WIDTH * scale, height),
+            Color::new(80, 80, 80, 255),
+        );
+    }
+    let budget = pos.y + graph.y - BUDGET_MS * ms_to_height;
+    d.draw_line_ex(
+        Vector2::new(pos.x, budget),
+        Vector2::new(pos.x + graph.x, budget),
+        scale,
+        Color::new(255, 255, 255, 120),
+    );
+
+    let columns = [0.0, 170.0, 232.0, 294.0, 356.0].map(|column| pos.x + column * scale);
+    let mut y = pos.y + graph.y + 6.0 * scale;
+    let mut row = |d: &mut RaylibDrawHandle, cells: [String; 5], color: Color| {
+        for (cell, column) in cells.iter().zip(columns) {
+            d.draw_text_ex(
+                font,
+                cell,
+                Vector2::new(column, y),
+                font_size,
+                font_size / 10.0,
+                color,
+            );
+        }
+        y += row_height;
+    };
+    row(
+        d,
+        [
+            "ms".to_string(),
+            "avg".to_string(),
+            "p50".to_string(),
+            "p95".to_string(),
+            "p99".to_string(),
+        ],
+        Color::GRAY,
+    );
+    for (color, name) in names.iter().enumerate() {
+        let [average, p50, p95, p99] = stats(
+            profiler
+                .history
+                .iter()
+                .map(|frame| scope_ms(frame, name))
+                .collect(),
+        );
+        row(
+            d,
+            [
+                name.to_string(),
+                format!("{average:.2}"),
+                format!("{p50:.2}"),
+                format!("{p95:.2}"),
+                format!("{p99:.2}"),
+            ],
+            PALETTE[color % PALETTE.len()],
+        );
+    }
+    let [average, p50, p95, p99] = stats(
+        profiler
+            .history
+            .iter()
+            .map(|frame| frame.duration as f32 / 1000.0)
+            .collect(),
+    );
+    row(
+        d,
+        [
+            "frame".to_string(),
+            format!("{average:.2}"),
+            format!("{p50:.2}"),
+            format!("{p95:.2}"),
+            format!("{p99:.2}"),
+        ],
+        Color::WHITE,
+    );
+    let counts: Vec<String> = profiler
+        .history
+        .back()
+        .map(|frame| {
+            frame
+                .counts
+                .iter()
+                .map(|(name, count)| format!("{name} {count}"))
+                .collect()
+        })
+        .unwrap_or_default();
+    for (line, counts) in counts.chunks(COUNTS_PER_ROW).enumerate() {
+        d.draw_text_ex(
+            font,
+            &counts.join("  "),
+            Vector2::new(pos.x, y + line as f32 * row_height),
+            font_size,
+            font_size / 10.0,
+            Color::LIGHTGRAY,
+        );
+    }
+}
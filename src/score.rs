@@ -0,0 +1,114 @@
+use std::fs;
+use std::path::Path;
+
+use crate::{log, Enemy, HighScore, LogCategory, LogLevel, Score};
+
+pub const COMBO_TIME: f32 = 3.0;
+pub const HIGH_SCORE_FILE: &str = "highscores.txt";
+const HIGH_SCORES_PER_TABLE: usize = 5;
+
+pub fn new_score() -> Score {
+    Score {
+        points: 0,
+        kills: 0,
+        combo: 0,
+        combo_time: 0.0,
+        cheated: false,
+    }
+}
+
+pub fn update_score(score: &mut Score, dt: f32) {
+    score.combo_time = f32::max(score.combo_time - dt, 0.0);
+    if score.combo_time <= 0.0 {
+        score.combo = 0;
+    }
+}
+
+/// Kills inside the combo window stack the multiplier, which resets once the window lapses.
+pub fn add_kill(score: &mut Score, enemy: &Enemy) {
+    score.kills += 1;
+    score.combo += 1;
+    score.combo_time = COMBO_TIME;
+    score.points += enemy.points * score.combo;
+}
+
+/// One run per line: `table points kills time date`. A missing file is an empty table and
+/// lines that don't parse are skipped. Kept sorted best first across all tables.
+pub fn load_high_scores(path: &Path) -> Vec<HighScore> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return vec![];
+    };
+    let mut high_scores: Vec<HighScore> = text
+        .lines()
+        .filter_map(|line| {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.len() != 5 {
+                return None;
+            }
+            Some(HighScore {
+                table: words[0].to_string(),
+                points: words[1].parse().ok()?,
+                kills: words[2].parse().ok()?,
+                time: words[3].parse().ok()?,
+                date: words[4].to_string(),
+            })
+        })
+        .collect();
+    high_scores.sort_by_key(|entry| std::cmp::Reverse(entry.points));
+    high_scores
+}
+
+pub fn save_high_scores(path: &Path, high_scores: &Vec<HighScore>) {
+    let text: String = high_scores
+        .iter()
+        .map(|entry| {
+            format!(
+                "{} {} {} {:.1} {}\n",
+                entry.table, entry.points, entry.kills, entry.time, entry.date
+            )
+        })
+        .collect();
+    if let Err(err) = fs::write(path, text) {
+        log(
+            LogLevel::Error,
+            LogCategory::Game,
+            &format!("{}: {err}, high scores not saved", path.display()),
+        );
+    }
+}
+
+/// Best first, at most five.
+pub fn table_high_scores<'a>(high_scores: &'a Vec<HighScore>, table: &str) -> Vec<&'a HighScore> {
+    high_scores
+        .iter()
+        .filter(|entry| entry.table == table)
+        .take(HIGH_SCORES_PER_TABLE)
+        .collect()
+}
+
+/// Adds the run and drops whatever fell off the bottom of its table. Returns the place it
+/// got, if it made the table at all. Ties go to the older run.
+pub fn add_high_score(high_scores: &mut Vec<HighScore>, entry: HighScore) -> Option<usize> {
+    let index = high_scores
+        .iter()
+        .position(|other| other.points < entry.points)
+        .unwrap_or(high_scores.len());
+    let rank = high_scores[..index]
+        .iter()
+        .filter(|other| other.table == entry.table)
+        .count();
+    if rank >= HIGH_SCORES_PER_TABLE {
+        return None;
+    }
+    let table = entry.table.clone();
+    high_scores.insert(index, entry);
+    let mut count = 0;
+    high_scores.retain(|other| {
+        if other.table != table {
+            return true;
+        }
+        count += 1;
+        count <= HIGH_SCORES_PER_TABLE
+    });
+    Some(rank)
+}
@@ -0,0 +1,406 @@
+use raylib::prelude::*;
+
+use crate::{log, Assets, LogCategory, LogLevel, Sprite, SpriteBatch, TextureID};
+
+pub const ATLAS_FILE: &str = "atlas.txt";
+// Gap between packed sprites so filtering never picks up a neighbour's pixels.
+const ATLAS_PADDING: i32 = 2;
+const MIN_ATLAS_SIZE: i32 = 256;
+
+// Draw order, lowest first. Within a layer sprites are grouped by texture.
+pub const PARTICAL_LAYER: i32 = 0;
+pub const POWER_UP_LAYER: i32 = 1;
+pub const PLAYER_LAYER: i32 = 2;
+pub const PLAYER_DAMAGE_LAYER: i32 = 3;
+pub const ENEMY_LAYER: i32 = 4;
+pub const ENEMY_DAMAGE_LAYER: i32 = 5;
+pub const TURRET_LAYER: i32 = 6;
+pub const BULLET_LAYER: i32 = 7;
+pub const FLASH_LAYER: i32 = 8;
+pub const MARKER_LAYER: i32 = 9;
+
+pub fn new_sprite_batch() -> SpriteBatch {
+    SpriteBatch {
+        sprites: vec![],
+        drawn: 0,
+        draw_calls: 0,
+    }
+}
+
+/// Packs every image loaded so far into one texture, tallest first in rows, and writes where
+/// each one ended up to the atlas file. Sprites drawn from any of them get redirected to it.
+pub fn build_atlas(assets: &mut Assets, rl: &mut RaylibHandle, thread: &RaylibThread) {
+    let mut images = std::mem::take(&mut assets.images);
+    images.sort_by_key(|(_, image)| -image.height);
+    let widest = images
+        .iter()
+        .map(|(_, image)| image.width)
+        .max()
+        .unwrap_or(0);
+    let width = (widest + ATLAS_PADDING * 2).max(MIN_ATLAS_SIZE) as u32;
+    let width = width.next_power_of_two() as i32;
+
+    let mut places = vec![];
+    let mut x = ATLAS_PADDING;
+    let mut y = ATLAS_PADDING;
+    let mut row_height = 0;
+    for (_, image) in &images {
+        if x + image.width + ATLAS_PADDING > width {
+            x = ATLAS_PADDING;
+            y += row_height + ATLAS_PADDING;
+            row_height = 0;
+        }
+        places.push(Rectangle::new(
+            x as f32,
+            y as f32,
+            image.width as f32,
+            image.height as f32,
+        ));
+        x += image.width + ATLAS_PADDING;
+        row_height = row_height.max(image.height);
+    }
+    let height = ((y + row_height + ATLAS_PADDING) as u32).next_power_of_two() as i32;
+
+    let mut atlas = Image::gen_image_color(width, height, Color::BLANK);
+    let mut metadata = format!("# {width}x{height}, name x y width height\n");
+    for ((id, image), place) in images.iter().zip(&places) {
+        atlas.draw(
+            image,
+            Rectangle::new(0.0, 0.0, image.width as f32, image.height as f32),
+            *place,
+            Color::WHITE,
+        );
+        let name = assets
+            .texture_names
+            .iter()
+            .find(|(_, other)| *other == id)
+            .map(|(name, _)| name.as_str())
+            .unwrap_or("?");
+        metadata += &format!(
+            "{} {} {} {} {}\n",
+            name, place.x, place.y, place.width, place.height
+        );
+    }
+    let texture = match rl.load_texture_from_image(thread, &atlas) {
+        Ok(texture) => texture,
+        Err(err) => {
+            log(
+                LogLevel::Warn,
+                LogCategory::Assets,
+                &format!("{err}, drawing sprites from their own textures"),
+            );
+            return;
+        }
+    };
+    let atlas_id = assets.textures.insert(texture);
+    assets
+        .sizes
+        .insert(atlas_id, Vector2::new(width as f32, height as f32));
+    assets.atlas = Some(atlas_id);
+    for ((id, _), place) in images.iter().zip(places) {
+        assets.regions.insert(*id, place);
+    }
+    let path = assets.root.join(ATLAS_FILE);
+    if let Err(err) = std::fs::write(&path, metadata) {
+        log(
+            LogLevel::Warn,
+            LogCategory::Assets,
+            &format!("{}: {err}", path.display()),
+        );
+    }
+}
+
+/// Queues a sprite, swapping in the atlas and offsetting the source rectangle if the texture
+/// was packed into it. `source` is always relative to the original texture.
+pub fn push_sprite(batch: &mut SpriteBatch, assets: &Assets, mut sprite: Sprite) {
+    if let (Some(atlas), Some(region)) = (assets.atlas, assets.regions.get(sprite.texture)) {
+        sprite.texture = atlas;
+        sprite.source.x += region.x;
+        sprite.source.y += region.y;
+    }
+    batch.sprites.push(sprite);
+}
+
+/// A whole texture centered on `pos`.
+pub fn push_texture(
+    batch: &mut SpriteBatch,
+    assets: &Assets,
+    texture: TextureID,
+    pos: Vector2,
+    scale: f32,
+    rotation: f32,
+    layer: i32,
+) {
+    let size = assets.sizes[texture];
+    push_sprite(
+        batch,
+        assets,
+        Sprite {
+            texture,
+            source: Rectangle::new(0.0, 0.0, size.x, size.y),
+            dest: Rectangle::new(pos.x, pos.y, size.x * scale, size.y * scale),
+            origin: size * scale / 2.0,
+            rotation,
+            tint: Color::WHITE,
+            layer,
+            additive: false,
+        },
+    );
+}
+
+/// A solid rectangle, drawn from the atlas' white square so it batches with everything else.
+pub fn push_rectangle(
+    batch: &mut SpriteBatch,
+    assets: &Assets,
+    dest: Rectangle,
+    origin: Vector2,
+    rotation: f32,
+    color: Color,
+    layer: i32,
+) {
+    push_sprite(
+        batch,
+        assets,
+        Sprite {
+            texture: assets.white,
+            // Just the middle of the square, so the edges never blend with the padding.
+            source: Rectangle::new(1.0, 1.0, 2.0, 2.0),
+            dest,
+            origin,
+            rotation,
+            tint: color,
+            layer,
+            additive: false,
+        },
+    );
+}
+
+pub fn push_circle(
+    batch: &mut SpriteBatch,
+    assets: &Assets,
+    center: Vector2,
+    radius: f32,
+    color: Color,
+    layer: i32,
+) {
+    let size = assets.sizes[assets.circle];
+    push_sprite(
+        batch,
+        assets,
+        Sprite {
+            texture: assets.circle,
+            source: Rectangle::new(0.0, 0.0, size.x, size.y),
+            dest: Rectangle::new(center.x, center.y, radius * 2.0, radius * 2.0),
+            origin: Vector2::new(radius, radius),
+            rotation: 0.0,
+            tint: color,
+            layer,
+            additive: false,
+        },
+    );
+}
+
+/// Whether two sorted sprites can go out in the same draw call.
+fn same_run(a: &Sprite, b: &Sprite) -> bool {
+    a.texture == b.texture && a.additive == b.additive
+}
+
+/// Stable, so sprites sharing a layer and texture keep the order they were queued in.
+pub fn sort_sprite_batch(batch: &mut SpriteBatch) {
+    batch
+        .sprites
+        .sort_by_key(|sprite| (sprite.layer, sprite.additive, sprite.texture));
+}
+
+/// Draws and empties the batch. raylib only flushes when the texture or blend mode changes,
+/// so every run of sprites sharing both goes out as one draw call.
+pub fn draw_sprite_batch(d: &mut RaylibDrawHandle, batch: &mut SpriteBatch, assets: &Assets) {
+    sort_sprite_batch(batch);
+    batch.drawn = batch.sprites.len();
+    batch.draw_calls = 0;
+    let mut start = 0;
+    while start < batch.sprites.len() {
+        let first = &batch.sprites[start];
+        let end = batch.sprites[start..]
+            .iter()
+            .position(|sprite| !same_run(first, sprite))
+            .map_or(batch.sprites.len(), |length| start + length);
+        let texture = &assets.textures[first.texture];
+        if first.additive {
+            let mut additive = d.begin_blend_mode(BlendMode::BLEND_ADDITIVE);
+            for sprite in &batch.sprites[start..end] {
+                additive.draw_texture_pro(
+                    texture,
+                    sprite.source,
+                    sprite.dest,
+                    sprite.origin,
+                    sprite.rotation,
+                    sprite.tint,
+                );
+            }
+        } else {
+            for sprite in &batch.sprites[start..end] {
+                d.draw_texture_pro(
+                    texture,
+                    sprite.source,
+                    sprite.dest,
+                    sprite.origin,
+                    sprite.rotation,
+                    sprite.tint,
+                );
+            }
+        }
+        batch.draw_calls += 1;
+        start = end;
+    }
+    batch.sprites.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::prelude::*;
+    use slotmap::{SecondaryMap, SlotMap};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::time::Instant;
+
+    use super::*;
+
+    /// Made up textures with sizes but nothing uploaded, enough to queue sprites with.
+    fn test_assets(count: usize) -> (Assets, Vec<TextureID>) {
+        let mut keys: SlotMap<TextureID, ()> = SlotMap::with_key();
+        let mut sizes = SecondaryMap::new();
+        let ids: Vec<TextureID> = (0..count)
+            .map(|index| {
+                let id = keys.insert(());
+                sizes.insert(id, Vector2::new(16.0 + index as f32, 16.0));
+                id
+            })
+            .collect();
+        let assets = Assets {
+            root: PathBuf::new(),
+            archive: None,
+            textures: SlotMap::with_key(),
+            sizes,
+            texture_names: HashMap::new(),
+            images: vec![],
+            atlas: None,
+            regions: SecondaryMap::new(),
+            white: ids[0],
+            circle: ids[1],
+            clips: HashMap::new(),
+        };
+        (assets, ids)
+    }
+
+    fn push_random(batch: &mut SpriteBatch, assets: &Assets, ids: &[TextureID], rng: &mut StdRng) {
+        let pos = Vector2::new(rng.gen_range(0.0..1920.0), rng.gen_range(0.0..1080.0));
+        match rng.gen_range(0..3) {
+            0 => push_texture(
+                batch,
+                assets,
+                ids[rng.gen_range(0..ids.len())],
+                pos,
+                2.0,
+                rng.gen_range(0.0..360.0),
+                rng.gen_range(0..MARKER_LAYER + 1),
+            ),
+            1 => push_rectangle(
+                batch,
+                assets,
+                Rectangle::new(pos.x, pos.y, 4.0, 8.0),
+                Vector2::new(2.0, 4.0),
+                rng.gen_range(0.0..360.0),
+                Color::GREEN,
+                BULLET_LAYER,
+            ),
+            _ => push_circle(batch, assets, pos, 3.0, Color::ORANGE, PARTICAL_LAYER),
+        }
+    }
+
+    fn draw_calls(batch: &SpriteBatch) -> usize {
+        if batch.sprites.is_empty() {
+            return 0;
+        }
+        1 + batch
+            .sprites
+            .windows(2)
+            .filter(|pair| !same_run(&pair[0], &pair[1]))
+            .count()
+    }
+
+    #[test]
+    fn sort_groups_by_layer_then_texture_keeping_queue_order() {
+        let (assets, ids) = test_assets(6);
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut batch = new_sprite_batch();
+        for index in 0..500 {
+            push_random(&mut batch, &assets, &ids, &mut rng);
+            // Remember the queue order in a field the sort doesn't look at.
+            batch.sprites[index].rotation = index as f32;
+        }
+        sort_sprite_batch(&mut batch);
+        for pair in batch.sprites.windows(2) {
+            let key = |sprite: &Sprite| (sprite.layer, sprite.additive, sprite.texture);
+            assert!(key(&pair[0]) <= key(&pair[1]));
+            if key(&pair[0]) == key(&pair[1]) {
+                assert!(pair[0].rotation < pair[1].rotation);
+            }
+        }
+        // At most one draw call per texture per layer.
+        assert!(draw_calls(&batch) <= ids.len() * (MARKER_LAYER + 1) as usize);
+    }
+
+    #[test]
+    fn packed_textures_draw_from_the_atlas() {
+        let (mut assets, ids) = test_assets(4);
+        assets.atlas = Some(ids[3]);
+        assets
+            .regions
+            .insert(ids[2], Rectangle::new(40.0, 8.0, 18.0, 16.0));
+        let mut batch = new_sprite_batch();
+        push_texture(&mut batch, &assets, ids[2], Vector2::zero(), 1.0, 0.0, 0);
+        let sprite = &batch.sprites[0];
+        assert!(sprite.texture == ids[3]);
+        assert_eq!((sprite.source.x, sprite.source.y), (40.0, 8.0));
+        assert_eq!((sprite.source.width, sprite.source.height), (18.0, 16.0));
+    }
+
+    /// Times queueing and sorting batches of random sprites, run it with
+    /// `cargo test --release benchmark_sprite_batch -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn benchmark_sprite_batch() {
+        let (assets, ids) = test_assets(24);
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut batch = new_sprite_batch();
+        println!("sprites  frames  build ms  sort ms  total ms/frame  draw calls");
+        for amount in [1_000, 10_000, 50_000, 100_000] {
+            let frames = 50;
+            let mut build = 0.0;
+            let mut sort = 0.0;
+            let mut calls = 0;
+            for _ in 0..frames {
+                let start = Instant::now();
+                for _ in 0..amount {
+                    push_random(&mut batch, &assets, &ids, &mut rng);
+                }
+                let built = Instant::now();
+                sort_sprite_batch(&mut batch);
+                build += (built - start).as_secs_f64() * 1000.0;
+                sort += built.elapsed().as_secs_f64() * 1000.0;
+                calls = draw_calls(&batch);
+                batch.sprites.clear();
+            }
+            println!(
+                "{:>7}  {:>6}  {:>8.3}  {:>7.3}  {:>14.3}  {:>10}",
+                amount,
+                frames,
+                build / frames as f64,
+                sort / frames as f64,
+                (build + sort) / frames as f64,
+                calls
+            );
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use raylib::prelude::*;
+
+/// Earliest time a projectile leaving `shooter` at `speed` can meet a target moving at a
+/// constant velocity. Velocities are relative, so pass the target velocity minus whatever
+/// the projectile inherits from the shooter. None when the target is outrunning it.
+pub fn intercept_time(
+    shooter: Vector2,
+    target: Vector2,
+    target_vel: Vector2,
+    speed: f32,
+) -> Option<f32> {
+    let offset = target - shooter;
+    let a = target_vel.dot(target_vel) - speed * speed;
+    let b = 2.0 * offset.dot(target_vel);
+    let c = offset.dot(offset);
+    // `a` scales with the speeds squared, so how close to zero counts depends on the speed.
+    if a.abs() <= 1e-4 * speed * speed {
+        // Target is moving as fast as the projectile, so the quadratic collapses to a line.
+        if b >= 0.0 {
+            return None;
+        }
+        return Some(-c / b);
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    // The textbook formula cancels badly when `a` is small next to `b`, so only the root that
+    // adds like signs is found that way and the other comes from their product, c / a.
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let t1 = q / a;
+    let t2 = if q != 0.0 { c / q } else { t1 };
+    let time = if t1 > 0.0 && (t1 < t2 || t2 <= 0.0) {
+        t1
+    } else {
+        t2
+    };
+    if time > 0.0 {
+        Some(time)
+    } else {
+        None
+    }
+}
+
+/// Where to aim to hit the target, falling back to where it is now when there's no solution.
+pub fn intercept_point(
+    shooter: Vector2,
+    target: Vector2,
+    target_vel: Vector2,
+    speed: f32,
+) -> Vector2 {
+    match intercept_time(shooter, target, target_vel, speed) {
+        Some(time) => target + target_vel * time,
+        None => target,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// After `time` the target has reached the aim point, and so has a projectile fired at it.
+    fn assert_meets(shooter: Vector2, target: Vector2, target_vel: Vector2, speed: f32, time: f32) {
+        let aim = intercept_point(shooter, target, target_vel, speed);
+        assert!((aim - (target + target_vel * time)).length() < 0.01);
+        let projectile = shooter + (aim - shooter).normalized() * speed * time;
+        assert!((projectile - aim).length() < 0.01);
+    }
+
+    #[test]
+    fn stationary_target_is_hit_after_distance_over_speed() {
+        let shooter = Vector2::new(10.0, 20.0);
+        let target = Vector2::new(310.0, 420.0);
+        let time = intercept_time(shooter, target, Vector2::zero(), 100.0).unwrap();
+        assert!((time - 5.0).abs() < 0.001);
+        assert_eq!(
+            intercept_point(shooter, target, Vector2::zero(), 100.0),
+            target
+        );
+        assert_meets(shooter, target, Vector2::zero(), 100.0, time);
+    }
+
+    #[test]
+    fn crossing_target_is_met_at_the_returned_point() {
+        let shooter = Vector2::zero();
+        let target = Vector2::new(400.0, -200.0);
+        let target_vel = Vector2::new(-30.0, 80.0);
+        let time = intercept_time(shooter, target, target_vel, 250.0).unwrap();
+        assert!(time > 0.0);
+        assert_meets(shooter, target, target_vel, 250.0, time);
+    }
+
+    #[test]
+    fn target_outrunning_the_projectile_falls_back_to_its_position() {
+        let shooter = Vector2::zero();
+        let target = Vector2::new(100.0, 0.0);
+        let target_vel = Vector2::new(300.0, 0.0);
+        assert_eq!(intercept_time(shooter, target, target_vel, 200.0), None);
+        assert_eq!(intercept_point(shooter, target, target_vel, 200.0), target);
+    }
+
+    #[test]
+    fn target_as_fast_as_the_projectile_uses_the_linear_case() {
+        let shooter = Vector2::zero();
+        let target = Vector2::new(300.0, 0.0);
+        // Coming straight at the shooter they meet in the middle.
+        let approaching = Vector2::new(-100.0, 0.0);
+        let time = intercept_time(shooter, target, approaching, 100.0).unwrap();
+        assert!((time - 1.5).abs() < 0.001);
+        assert_meets(shooter, target, approaching, 100.0, time);
+        // Crossing at the same speed the projectile still catches up eventually.
+        let crossing = Vector2::new(-60.0, 80.0);
+        let time = intercept_time(shooter, target, crossing, 100.0).unwrap();
+        assert_meets(shooter, target, crossing, 100.0, time);
+        // Moving away it never does.
+        let receding = Vector2::new(100.0, 0.0);
+        assert_eq!(intercept_time(shooter, target, receding, 100.0), None);
+        // Nothing moving at all never meets either.
+        assert_eq!(intercept_time(shooter, target, Vector2::zero(), 0.0), None);
+    }
+
+    #[test]
+    fn target_almost_as_fast_as_the_projectile_stays_stable() {
+        let shooter = Vector2::zero();
+        let target = Vector2::new(300.0, 0.0);
+        let speed = 100.0;
+        let approaching = Vector2::new(-0.9999 * speed, 0.0);
+        let time = intercept_time(shooter, target, approaching, speed).unwrap();
+        assert!((time - 300.0 / (1.9999 * speed)).abs() < 0.001);
+        assert_meets(shooter, target, approaching, speed, time);
+        let crossing = Vector2::new(-0.6, 0.8) * 0.9999 * speed;
+        let time = intercept_time(shooter, target, crossing, speed).unwrap();
+        assert_meets(shooter, target, crossing, speed, time);
+        // Only gaining a hundredth of a unit a second it takes a long time, but it gets there.
+        let receding = Vector2::new(0.9999 * speed, 0.0);
+        let time = intercept_time(shooter, target, receding, speed).unwrap();
+        let expected = 300.0 / (0.0001 * speed);
+        assert!((time - expected).abs() / expected < 0.01);
+    }
+}
@@ -0,0 +1,219 @@
+use rand::rngs::StdRng;
+use raylib::prelude::*;
+
+use crate::{
+    anchor_pos, Anchor, Bullet, Campaign, Director, Enemy, Player, PowerUp, Score, Snapshot,
+    TimeControl, Wave, World, Wreck,
+};
+
+/// How much play the rewind buffer holds on to.
+pub const REWIND_SECONDS: f32 = 5.0;
+/// A single step always advances one 60 fps tick, whatever the frame rate is.
+pub const STEP_TIME: f32 = 1.0 / 60.0;
+pub const MIN_TIME_SCALE: f32 = 0.1;
+pub const MAX_TIME_SCALE: f32 = 4.0;
+const TIME_SCALES: [f32; 6] = [0.1, 0.25, 0.5, 1.0, 2.0, 4.0];
+const SCRUB_BOOST: usize = 10;
+
+pub fn new_time_control() -> TimeControl {
+    TimeControl {
+        paused: false,
+        step: false,
+        time_scale: 1.0,
+        history: Default::default(),
+        scrub: None,
+    }
+}
+
+/// F5 pauses, F6 steps a single tick, F7 and F8 go down and up through the speed presets and
+/// comma and period scrub back and forward through the rewind buffer while paused (shift
+/// moves ten snapshots). Returns true when the scrub moved and a snapshot needs loading.
+pub fn update_time_control(time_control: &mut TimeControl, rl: &RaylibHandle) -> bool {
+    time_control.step = false;
+    if rl.is_key_pressed(KeyboardKey::KEY_F5) {
+        time_control.paused = !time_control.paused;
+    }
+    if rl.is_key_pressed(KeyboardKey::KEY_F6) {
+        time_control.paused = true;
+        time_control.step = true;
+    }
+    if rl.is_key_pressed(KeyboardKey::KEY_F7) {
+        if let Some(slower) = TIME_SCALES
+            .iter()
+            .rev()
+            .find(|scale| **scale < time_control.time_scale - 0.001)
+        {
+            time_control.time_scale = *slower;
+        }
+    }
+    if rl.is_key_pressed(KeyboardKey::KEY_F8) {
+        if let Some(faster) = TIME_SCALES
+            .iter()
+            .find(|scale| **scale > time_control.time_scale + 0.001)
+        {
+            time_control.time_scale = *faster;
+        }
+    }
+    // Playing on from a rewound snapshot throws away everything that came after it.
+    if !time_control.paused || time_control.step {
+        if let Some(index) = time_control.scrub.take() {
+            time_control.history.truncate(index + 1);
+        }
+        return false;
+    }
+    if time_control.history.is_empty() {
+        return false;
+    }
+    let amount = if rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) {
+        SCRUB_BOOST
+    } else {
+        1
+    };
+    let newest = time_control.history.len() - 1;
+    let current = time_control.scrub.unwrap_or(newest);
+    let target = if rl.is_key_pressed(KeyboardKey::KEY_COMMA) {
+        current.saturating_sub(amount)
+    } else if rl.is_key_pressed(KeyboardKey::KEY_PERIOD) {
+        usize::min(current + amount, newest)
+    } else {
+        current
+    };
+    if target == current {
+        return false;
+    }
+    time_control.scrub = Some(target);
+    true
+}
+
+/// Records the end of this tick, dropping anything older than the rewind window. Particals
+/// aren't kept, there are far too many of them and they don't affect anything.
+pub fn save_snapshot(
+    time_control: &mut TimeControl,
+    time: f32,
+    player: &Player,
+    enemies: &Vec<Enemy>,
+    bullets: &Vec<Bullet>,
+    power_ups: &Vec<PowerUp>,
+    wrecks: &Vec<Wreck>,
+    world: &World,
+    waves: &Vec<Wave>,
+    campaign: &Option<Campaign>,
+    score: &Score,
+    director: &Director,
+    spawn_rng: &StdRng,
+    rng: &StdRng,
+) {
+    time_control.history.push_back(Snapshot {
+        time,
+        player: player.clone(),
+        enemies: enemies.clone(),
+        bullets: bullets.clone(),
+        power_ups: power_ups.clone(),
+        wrecks: wrecks.clone(),
+        world: world.clone(),
+        waves: waves.clone(),
+        campaign: campaign.clone(),
+        score: score.clone(),
+        director: director.clone(),
+        spawn_rng: spawn_rng.clone(),
+        rng: rng.clone(),
+    });
+    while let Some(oldest) = time_control.history.front() {
+        if time - oldest.time <= REWIND_SECONDS {
+            break;
+        }
+        time_control.history.pop_front();
+    }
+}
+
+/// Puts the world back how it was at the scrubbed snapshot, campaign progress included, so
+/// scripted events inside the rewind window play again.
+pub fn load_snapshot(
+    time_control: &TimeControl,
+    time: &mut f32,
+    player: &mut Player,
+    enemies: &mut Vec<Enemy>,
+    bullets: &mut Vec<Bullet>,
+    power_ups: &mut Vec<PowerUp>,
+    wrecks: &mut Vec<Wreck>,
+    world: &mut World,
+    waves: &mut Vec<Wave>,
+    campaign: &mut Option<Campaign>,
+    score: &mut Score,
+    director: &mut Director,
+    spawn_rng: &mut StdRng,
+    rng: &mut StdRng,
+) {
+    let Some(snapshot) = time_control
+        .scrub
+        .and_then(|index| time_control.history.get(index))
+    else {
+        return;
+    };
+    *time = snapshot.time;
+    *player = snapshot.player.clone();
+    *enemies = snapshot.enemies.clone();
+    *bullets = snapshot.bullets.clone();
+    *power_ups = snapshot.power_ups.clone();
+    *wrecks = snapshot.wrecks.clone();
+    *world = snapshot.world.clone();
+    *waves = snapshot.waves.clone();
+    *campaign = snapshot.campaign.clone();
+    *score = snapshot.score.clone();
+    *director = snapshot.director.clone();
+    *spawn_rng = snapshot.spawn_rng.clone();
+    *rng = snapshot.rng.clone();
+}
+
+/// Back to normal speed, playing on from wherever the scrub was.
+pub fn reset_time_control(time_control: &mut TimeControl) {
+    time_control.paused = false;
+    time_control.step = false;
+    time_control.time_scale = 1.0;
+    if let Some(index) = time_control.scrub.take() {
+        time_control.history.truncate(index + 1);
+    }
+}
+
+pub fn clear_snapshots(time_control: &mut TimeControl) {
+    time_control.history.clear();
+    time_control.scrub = None;
+}
+
+/// Shows the speed whenever it isn't normal and how far back the scrub is while paused.
+pub fn draw_time_control(
+    d: &mut RaylibDrawHandle,
+    time_control: &TimeControl,
+    font: &WeakFont,
+    scale: f32,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    let mut text = if time_control.paused {
+        "PAUSED".to_string()
+    } else if time_control.time_scale != 1.0 {
+        format!("x{}", time_control.time_scale)
+    } else {
+        return;
+    };
+    if let (Some(index), Some(newest)) = (time_control.scrub, time_control.history.back()) {
+        text += &format!(
+            "  rewind -{:.2}s  ({}/{})",
+            newest.time - time_control.history[index].time,
+            index + 1,
+            time_control.history.len()
+        );
+    } else if time_control.paused && time_control.time_scale != 1.0 {
+        text += &format!("  x{}", time_control.time_scale);
+    }
+    let font_size = 22.0 * scale;
+    let bounds = font.measure_text(&text, font_size, font_size / 10.0);
+    let pos = anchor_pos(
+        &Anchor::Top,
+        Vector2::new(0.0, screenheight as f32 * 0.22),
+        bounds,
+        screenwidth,
+        screenheight,
+    );
+    d.draw_text_ex(font, &text, pos, font_size, font_size / 10.0, Color::GOLD);
+}
@@ -1,60 +1,824 @@
 use raylib::prelude::*;
 
-use crate::Player;
-
-pub fn draw_timer(d: &mut RaylibDrawHandle, time: f32, screenwidth: i32) {
-    d.draw_text(
-        format!("Time: {:.1}", time).as_str(),
-        screenwidth / 2 - 50,
-        10,
-        36,
-        Color::WHITE,
+use crate::{
+    campaign_finished, colorlerp, difficulty_name, environment_at, high_score_table,
+    mode_description, mode_name, sector_name, table_high_scores, vectortoangle, windup_charge,
+    Anchor, Campaign, Difficulty, Enemy, GameMode, HighScore, Player, Score, Settings, World,
+    COMBO_TIME, GAME_MODES, SHIELD_DURATION, TIME_ATTACK_LIMIT, TIME_ATTACK_QUOTA,
+};
+
+const PANEL_WIDTH: f32 = 230.0;
+const MARGIN: f32 = 10.0;
+
+/// Top left corner of a widget of `size` pinned to `anchor`, pushed `offset` in from the edges.
+pub fn anchor_pos(
+    anchor: &Anchor,
+    offset: Vector2,
+    size: Vector2,
+    screenwidth: i32,
+    screenheight: i32,
+) -> Vector2 {
+    let (x, y) = match anchor {
+        Anchor::TopLeft => (0.0, 0.0),
+        Anchor::Top => (0.5, 0.0),
+        Anchor::Bottom => (0.5, 1.0),
+        Anchor::TopRight => (1.0, 0.0),
+        Anchor::BottomLeft => (0.0, 1.0),
+        Anchor::BottomRight => (1.0, 1.0),
+    };
+    Vector2::new(
+        (screenwidth as f32 - size.x) * x + offset.x * (1.0 - 2.0 * x),
+        (screenheight as f32 - size.y) * y + offset.y * (1.0 - 2.0 * y),
+    )
+}
+
+fn draw_hud_text(
+    d: &mut RaylibDrawHandle,
+    font: &WeakFont,
+    text: &str,
+    pos: Vector2,
+    font_size: f32,
+    color: Color,
+) {
+    d.draw_text_ex(font, text, pos, font_size, font_size / 10.0, color);
+}
+
+fn draw_panel(d: &mut RaylibDrawHandle, pos: Vector2, size: Vector2, scale: f32) {
+    let rect = Rectangle::new(
+        pos.x - 6.0 * scale,
+        pos.y - 6.0 * scale,
+        size.x + 12.0 * scale,
+        size.y + 12.0 * scale,
     );
+    d.draw_rectangle_rec(rect, Color::new(0, 0, 0, 120));
+    d.draw_rectangle_lines_ex(rect, scale, Color::new(140, 255, 251, 60));
+}
+
+fn draw_bar(d: &mut RaylibDrawHandle, pos: Vector2, size: Vector2, value: f32, color: Color) {
+    d.draw_rectangle_v(pos, size, Color::new(40, 40, 40, 200));
+    d.draw_rectangle_v(
+        pos,
+        Vector2::new(size.x * value.clamp(0.0, 1.0), size.y),
+        color,
+    );
+}
+
+fn draw_segmented_bar(
+    d: &mut RaylibDrawHandle,
+    pos: Vector2,
+    size: Vector2,
+    segments: usize,
+    value: f32,
+    color: Color,
+    scale: f32,
+) {
+    let gap = 2.0 * scale;
+    let segment_width = (size.x - gap * (segments - 1) as f32) / segments as f32;
+    for segment in 0..segments {
+        let fill = (value * segments as f32 - segment as f32).clamp(0.0, 1.0);
+        draw_bar(
+            d,
+            Vector2::new(pos.x + segment as f32 * (segment_width + gap), pos.y),
+            Vector2::new(segment_width, size.y),
+            fill,
+            color,
+        );
+    }
+}
+
+pub fn draw_hud(
+    d: &mut RaylibDrawHandle,
+    player: &Player,
+    enemies: &Vec<Enemy>,
+    world: &World,
+    score: &Score,
+    time: f32,
+    font: &WeakFont,
+    scale: f32,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    draw_threats(d, player, enemies, world, scale, screenwidth, screenheight);
+    draw_timer(d, score, time, font, scale, screenwidth, screenheight);
+    draw_boss_health(d, enemies, font, scale, screenwidth, screenheight);
+    draw_part_health(d, player, font, scale, screenwidth, screenheight);
+    draw_weapons(d, player, font, scale, screenwidth, screenheight);
+    draw_power_ups(d, player, font, scale, screenwidth, screenheight);
+}
+
+pub fn draw_timer(
+    d: &mut RaylibDrawHandle,
+    score: &Score,
+    time: f32,
+    font: &WeakFont,
+    scale: f32,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    let time_text = format!("Time: {:.1}", time);
+    let time_size = 36.0 * scale;
+    let time_bounds = font.measure_text(&time_text, time_size, time_size / 10.0);
+    let pos = anchor_pos(
+        &Anchor::Top,
+        Vector2::new(0.0, MARGIN * scale),
+        time_bounds,
+        screenwidth,
+        screenheight,
+    );
+    draw_hud_text(d, font, &time_text, pos, time_size, Color::WHITE);
+
+    let score_text = format!("Score: {}", score.points);
+    let score_size = 22.0 * scale;
+    let score_bounds = font.measure_text(&score_text, score_size, score_size / 10.0);
+    let pos = anchor_pos(
+        &Anchor::Top,
+        Vector2::new(0.0, MARGIN * scale + time_bounds.y),
+        score_bounds,
+        screenwidth,
+        screenheight,
+    );
+    draw_hud_text(d, font, &score_text, pos, score_size, Color::WHITE);
+
+    if score.combo > 1 {
+        let combo_text = format!("x{} combo", score.combo);
+        let combo_bounds = font.measure_text(&combo_text, score_size, score_size / 10.0);
+        let pos = anchor_pos(
+            &Anchor::Top,
+            Vector2::new(0.0, MARGIN * scale + time_bounds.y + score_bounds.y),
+            combo_bounds,
+            screenwidth,
+            screenheight,
+        );
+        draw_hud_text(d, font, &combo_text, pos, score_size, Color::GOLD);
+        draw_bar(
+            d,
+            Vector2::new(pos.x, pos.y + combo_bounds.y),
+            Vector2::new(combo_bounds.x, 4.0 * scale),
+            score.combo_time / COMBO_TIME,
+            Color::GOLD,
+        );
+    }
 }
 
 pub fn draw_part_health(
     d: &mut RaylibDrawHandle,
     player: &Player,
-    default_font: &WeakFont,
+    font: &WeakFont,
+    scale: f32,
     screenwidth: i32,
+    screenheight: i32,
 ) {
-    let longest_name_len = player
-        .parts
-        .iter()
-        .map(|part| part.name.len())
-        .max()
-        .unwrap_or(0);
+    let font_size = 18.0 * scale;
+    let bar_height = 10.0 * scale;
+    let row_height = font_size + bar_height + 8.0 * scale;
+    let size = Vector2::new(PANEL_WIDTH * scale, row_height * player.parts.len() as f32);
+    let pos = anchor_pos(
+        &Anchor::TopRight,
+        Vector2::new(MARGIN * scale, MARGIN * scale),
+        size,
+        screenwidth,
+        screenheight,
+    );
+    draw_panel(d, pos, size, scale);
 
     for part_index in 0..player.parts.len() {
         let part = &player.parts[part_index];
-        let font_size = 18.0;
-        let spacing = 5.0;
-        let name_length = longest_name_len as f32 * spacing;
-        d.draw_text_pro(
-            &default_font,
-            part.name.as_str(),
-            Vector2 {
-                x: screenwidth as f32 - name_length as f32 * 3.0,
-                y: 10.0 + (part_index as f32 * (font_size + 15.0)),
-            },
-            Vector2 { x: 0.0, y: 0.0 },
-            0.0,
+        let row = pos + Vector2::new(0.0, row_height * part_index as f32);
+        let health = (part.health / part.starting_health).clamp(0.0, 1.0);
+        draw_hud_text(d, font, part.name.as_str(), row, font_size, Color::WHITE);
+        let percent = format!("{:.0}%", health * 100.0);
+        let percent_bounds = font.measure_text(&percent, font_size, font_size / 10.0);
+        draw_hud_text(
+            d,
+            font,
+            &percent,
+            Vector2::new(row.x + size.x - percent_bounds.x, row.y),
             font_size,
-            spacing,
             Color::WHITE,
         );
-        d.draw_text_pro(
-            &default_font,
-            format!("Health: {:.1}%", part.health / part.starting_health * 100.0).as_str(),
-            Vector2 {
-                x: screenwidth as f32 - name_length as f32 * 3.0 + 10.0,
-                y: 28.0 + (part_index as f32 * (font_size + 15.0)),
-            },
-            Vector2 { x: 0.0, y: 0.0 },
-            0.0,
-            font_size / 1.1,
-            spacing / 1.1,
+        draw_segmented_bar(
+            d,
+            Vector2::new(row.x, row.y + font_size + 2.0 * scale),
+            Vector2::new(size.x, bar_height),
+            f32::max(part.starting_health.ceil(), 1.0) as usize,
+            health,
+            colorlerp(Color::RED, Color::GREEN, health),
+            scale,
+        );
+    }
+}
+
+pub fn draw_weapons(
+    d: &mut RaylibDrawHandle,
+    player: &Player,
+    font: &WeakFont,
+    scale: f32,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    let font_size = 16.0 * scale;
+    let bar_height = 6.0 * scale;
+    let row_height = font_size + bar_height + 8.0 * scale;
+    let size = Vector2::new(
+        PANEL_WIDTH * scale,
+        row_height * player.bullet_emmiters.len() as f32,
+    );
+    let pos = anchor_pos(
+        &Anchor::BottomLeft,
+        Vector2::new(MARGIN * scale, MARGIN * scale),
+        size,
+        screenwidth,
+        screenheight,
+    );
+    draw_panel(d, pos, size, scale);
+
+    for (index, bullet_emmiter) in player.bullet_emmiters.iter().enumerate() {
+        let row = pos + Vector2::new(0.0, row_height * index as f32);
+        draw_hud_text(
+            d,
+            font,
+            &format!(
+                "Gun {}  {:.1}/s",
+                index + 1,
+                1.0 / bullet_emmiter.bullet_interval
+            ),
+            row,
+            font_size,
+            Color::WHITE,
+        );
+        draw_bar(
+            d,
+            Vector2::new(row.x, row.y + font_size + 2.0 * scale),
+            Vector2::new(size.x, bar_height),
+            bullet_emmiter.time / bullet_emmiter.bullet_interval,
+            Color::new(140, 255, 251, 255),
+        );
+    }
+}
+
+pub fn draw_power_ups(
+    d: &mut RaylibDrawHandle,
+    player: &Player,
+    font: &WeakFont,
+    scale: f32,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    if player.shield <= 0.0 {
+        return;
+    }
+    let font_size = 16.0 * scale;
+    let bar_height = 6.0 * scale;
+    let size = Vector2::new(PANEL_WIDTH * scale, font_size + bar_height + 2.0 * scale);
+    let pos = anchor_pos(
+        &Anchor::BottomRight,
+        Vector2::new(MARGIN * scale, MARGIN * scale),
+        size,
+        screenwidth,
+        screenheight,
+    );
+    draw_panel(d, pos, size, scale);
+    draw_hud_text(
+        d,
+        font,
+        &format!("Shield  {:.1}s", player.shield),
+        pos,
+        font_size,
+        Color::WHITE,
+    );
+    draw_bar(
+        d,
+        Vector2::new(pos.x, pos.y + font_size + 2.0 * scale),
+        Vector2::new(size.x, bar_height),
+        player.shield / SHIELD_DURATION,
+        Color::new(140, 255, 251, 255),
+    );
+}
+
+pub fn draw_boss_health(
+    d: &mut RaylibDrawHandle,
+    enemies: &Vec<Enemy>,
+    font: &WeakFont,
+    scale: f32,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    let Some(boss) = enemies.iter().find(|enemy| !enemy.parts.is_empty()) else {
+        return;
+    };
+    let font_size = 18.0 * scale;
+    let bar_height = 12.0 * scale;
+    let part_height = 5.0 * scale;
+    let size = Vector2::new(
+        PANEL_WIDTH * 2.0 * scale,
+        font_size + bar_height + part_height + 6.0 * scale,
+    );
+    let pos = anchor_pos(
+        &Anchor::Bottom,
+        Vector2::new(0.0, MARGIN * scale),
+        size,
+        screenwidth,
+        screenheight,
+    );
+    draw_panel(d, pos, size, scale);
+    draw_hud_text(d, font, boss.name.as_str(), pos, font_size, Color::WHITE);
+    let phase = format!("Phase {}", boss.phase + 1);
+    let phase_bounds = font.measure_text(&phase, font_size, font_size / 10.0);
+    draw_hud_text(
+        d,
+        font,
+        &phase,
+        Vector2::new(pos.x + size.x - phase_bounds.x, pos.y),
+        font_size,
+        Color::GOLD,
+    );
+
+    let mut health = 0.0;
+    let mut total_health = 0.0;
+    for part in &boss.parts {
+        health += f32::max(part.health, 0.0);
+        total_health += part.starting_health;
+    }
+    draw_bar(
+        d,
+        Vector2::new(pos.x, pos.y + font_size + 2.0 * scale),
+        Vector2::new(size.x, bar_height),
+        health / total_health,
+        Color::RED,
+    );
+
+    // One pip per part underneath, so it's clear which hit-zones are left.
+    let gap = 2.0 * scale;
+    let part_width = (size.x - gap * (boss.parts.len() - 1) as f32) / boss.parts.len() as f32;
+    for (index, part) in boss.parts.iter().enumerate() {
+        let health = (part.health / part.starting_health).clamp(0.0, 1.0);
+        draw_bar(
+            d,
+            Vector2::new(
+                pos.x + index as f32 * (part_width + gap),
+                pos.y + font_size + bar_height + 4.0 * scale,
+            ),
+            Vector2::new(part_width, part_height),
+            health,
+            colorlerp(Color::RED, Color::GREEN, health),
+        );
+    }
+}
+
+/// Arrows on the screen edge pointing at off-screen enemies that are winding up a shot.
+pub fn draw_threats(
+    d: &mut RaylibDrawHandle,
+    player: &Player,
+    enemies: &Vec<Enemy>,
+    world: &World,
+    scale: f32,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    let center = Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0);
+    let inset = center - Vector2::new(MARGIN * 3.0 * scale, MARGIN * 3.0 * scale);
+    for enemy in enemies {
+        if environment_at(world, enemy.pos).hidden {
+            continue;
+        }
+        for bullet_emmiter in &enemy.bullet_emmiters {
+            let charge = windup_charge(bullet_emmiter);
+            let offset = bullet_emmiter.pos - player.pos;
+            if charge <= 0.0 || (offset.x.abs() < center.x && offset.y.abs() < center.y) {
+                continue;
+            }
+            // Slide along the direction until it hits the inset screen edge.
+            let edge = f32::min(
+                inset.x / offset.x.abs().max(0.001),
+                inset.y / offset.y.abs().max(0.001),
+            );
+            let pos = center + offset * edge;
+            let color = Color::new(236, 28, 36, (120.0 + 135.0 * charge) as u8);
+            d.draw_poly(
+                pos,
+                3,
+                (10.0 + 6.0 * charge) * scale,
+                vectortoangle(offset).to_degrees(),
+                color,
+            );
+            d.draw_ring(
+                pos,
+                (14.0 + 6.0 * charge) * scale,
+                (16.0 + 6.0 * charge) * scale,
+                0.0,
+                360.0 * charge,
+                24,
+                color,
+            );
+        }
+    }
+}
+
+pub fn draw_pause_menu(
+    d: &mut RaylibDrawHandle,
+    settings: &Settings,
+    font: &WeakFont,
+    scale: f32,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    let title_size = 48.0 * scale;
+    let font_size = 22.0 * scale;
+    let row_height = font_size + 8.0 * scale;
+    let difficulties = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+    let size = Vector2::new(
+        PANEL_WIDTH * 1.5 * scale,
+        title_size + row_height * (difficulties.len() + 6) as f32,
+    );
+    let pos = Vector2::new(
+        (screenwidth as f32 - size.x) / 2.0,
+        (screenheight as f32 - size.y) / 2.0,
+    );
+    draw_panel(d, pos, size, scale);
+    draw_hud_text(d, font, "Paused", pos, title_size, Color::WHITE);
+
+    let mut row = pos + Vector2::new(0.0, title_size);
+    for (index, difficulty) in difficulties.iter().enumerate() {
+        let selected = *difficulty == settings.difficulty;
+        draw_hud_text(
+            d,
+            font,
+            &format!(
+                "{} {} {}",
+                if selected { ">" } else { " " },
+                index + 1,
+                difficulty_name(difficulty)
+            ),
+            row,
+            font_size,
+            if selected { Color::GOLD } else { Color::WHITE },
+        );
+        row.y += row_height;
+    }
+    let adaptive = if settings.adaptive { "On" } else { "Off" };
+    draw_hud_text(
+        d,
+        font,
+        &format!("  A Adaptive: {}", adaptive),
+        row,
+        font_size,
+        Color::WHITE,
+    );
+    row.y += row_height;
+    for (key, name, volume) in [
+        (4, "Master", settings.master_volume),
+        (5, "Effects", settings.effects_volume),
+        (6, "Music", settings.music_volume),
+    ] {
+        draw_hud_text(
+            d,
+            font,
+            &format!("  {} {} volume: {:.0}%", key, name, volume * 100.0),
+            row,
+            font_size,
             Color::WHITE,
         );
+        row.y += row_height;
+    }
+    draw_hud_text(d, font, "  Q Quit to menu", row, font_size, Color::WHITE);
+    row.y += row_height;
+    draw_hud_text(
+        d,
+        font,
+        "  Esc Resume",
+        row,
+        font_size,
+        Color::new(140, 255, 251, 255),
+    );
+}
+
+/// Mission name in the corner and the current script message across the middle.
+pub fn draw_campaign(
+    d: &mut RaylibDrawHandle,
+    campaign: &Campaign,
+    font: &WeakFont,
+    scale: f32,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    let (message, alpha) = match campaign.levels.get(campaign.current) {
+        Some(level) => {
+            let font_size = 18.0 * scale;
+            let text = format!(
+                "Mission {}/{}: {}",
+                campaign.current + 1,
+                campaign.levels.len(),
+                level.name
+            );
+            let bounds = font.measure_text(&text, font_size, font_size / 10.0);
+            let pos = anchor_pos(
+                &Anchor::TopLeft,
+                Vector2::new(MARGIN * scale, MARGIN * scale),
+                bounds,
+                screenwidth,
+                screenheight,
+            );
+            draw_panel(d, pos, bounds, scale);
+            draw_hud_text(d, font, &text, pos, font_size, Color::WHITE);
+            // Fade out over the last half second.
+            (
+                level.message.clone(),
+                (level.message_time / 0.5).clamp(0.0, 1.0),
+            )
+        }
+        None => ("Campaign complete".to_string(), 1.0),
+    };
+    if alpha <= 0.0 || message.is_empty() {
+        return;
+    }
+    let font_size = 40.0 * scale;
+    let bounds = font.measure_text(&message, font_size, font_size / 10.0);
+    let pos = anchor_pos(
+        &Anchor::Top,
+        Vector2::new(0.0, screenheight as f32 * 0.25),
+        bounds,
+        screenwidth,
+        screenheight,
+    );
+    let color = if campaign_finished(campaign) {
+        Color::GOLD
+    } else {
+        Color::WHITE
+    };
+    draw_hud_text(
+        d,
+        font,
+        &message,
+        pos,
+        font_size,
+        Color::new(color.r, color.g, color.b, (255.0 * alpha) as u8),
+    );
+}
+
+fn format_time(time: f32) -> String {
+    format!("{}:{:02}", time as u32 / 60, time as u32 % 60)
+}
+
+/// Rows of a high score table starting at `row`, with `highlight` in gold. Returns where the
+/// next row goes.
+fn draw_high_scores(
+    d: &mut RaylibDrawHandle,
+    font: &WeakFont,
+    entries: &Vec<&HighScore>,
+    highlight: Option<usize>,
+    mut row: Vector2,
+    font_size: f32,
+    row_height: f32,
+) -> Vector2 {
+    if entries.is_empty() {
+        draw_hud_text(d, font, "  No runs yet", row, font_size, Color::GRAY);
+        row.y += row_height;
+    }
+    for (index, entry) in entries.iter().enumerate() {
+        draw_hud_text(
+            d,
+            font,
+            &format!(
+                "  {}. {:>7} {:>4} kills {:>6}  {}",
+                index + 1,
+                entry.points,
+                entry.kills,
+                format_time(entry.time),
+                entry.date
+            ),
+            row,
+            font_size,
+            if highlight == Some(index) {
+                Color::GOLD
+            } else {
+                Color::WHITE
+            },
+        );
+        row.y += row_height;
+    }
+    row
+}
+
+pub fn draw_mode_select(
+    d: &mut RaylibDrawHandle,
+    selected: usize,
+    high_scores: &Vec<HighScore>,
+    font: &WeakFont,
+    scale: f32,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    let title_size = 48.0 * scale;
+    let font_size = 22.0 * scale;
+    let small_size = 16.0 * scale;
+    let row_height = font_size + 8.0 * scale;
+    let entries = table_high_scores(high_scores, &high_score_table(&GAME_MODES[selected]));
+    let size = Vector2::new(
+        PANEL_WIDTH * 2.5 * scale,
+        title_size
+            + row_height * (GAME_MODES.len() + 3) as f32
+            + (small_size + 6.0 * scale) * entries.len().max(1) as f32,
+    );
+    let pos = Vector2::new(
+        (screenwidth as f32 - size.x) / 2.0,
+        (screenheight as f32 - size.y) / 2.0,
+    );
+    draw_panel(d, pos, size, scale);
+    draw_hud_text(d, font, "Space Game", pos, title_size, Color::WHITE);
+
+    let mut row = pos + Vector2::new(0.0, title_size);
+    for (index, mode) in GAME_MODES.iter().enumerate() {
+        let chosen = index == selected;
+        draw_hud_text(
+            d,
+            font,
+            &format!("{} {}", if chosen { ">" } else { " " }, mode_name(mode)),
+            row,
+            font_size,
+            if chosen { Color::GOLD } else { Color::WHITE },
+        );
+        row.y += row_height;
     }
+    draw_hud_text(
+        d,
+        font,
+        &format!("  {}", mode_description(&GAME_MODES[selected])),
+        row,
+        font_size,
+        Color::new(140, 255, 251, 255),
+    );
+    row.y += row_height;
+    draw_hud_text(d, font, "  High scores", row, font_size, Color::WHITE);
+    row.y += row_height;
+    row = draw_high_scores(
+        d,
+        font,
+        &entries,
+        None,
+        row,
+        small_size,
+        small_size + 6.0 * scale,
+    );
+    draw_hud_text(
+        d,
+        font,
+        "  Up/Down Select  Enter Start",
+        row,
+        font_size,
+        Color::new(140, 255, 251, 255),
+    );
+}
+
+/// Result of the run and where it landed in its mode's table.
+pub fn draw_game_over(
+    d: &mut RaylibDrawHandle,
+    mode: &GameMode,
+    won: bool,
+    score: &Score,
+    time: f32,
+    high_scores: &Vec<HighScore>,
+    rank: Option<usize>,
+    font: &WeakFont,
+    scale: f32,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    let title_size = 48.0 * scale;
+    let font_size = 22.0 * scale;
+    let small_size = 16.0 * scale;
+    let row_height = font_size + 8.0 * scale;
+    let entries = table_high_scores(high_scores, &high_score_table(mode));
+    let size = Vector2::new(
+        PANEL_WIDTH * 2.5 * scale,
+        title_size + row_height * 4.0 + (small_size + 6.0 * scale) * entries.len().max(1) as f32,
+    );
+    let pos = Vector2::new(
+        (screenwidth as f32 - size.x) / 2.0,
+        (screenheight as f32 - size.y) / 2.0,
+    );
+    draw_panel(d, pos, size, scale);
+    if won {
+        draw_hud_text(d, font, "Victory", pos, title_size, Color::GOLD);
+    } else {
+        draw_hud_text(d, font, "Game Over", pos, title_size, Color::RED);
+    }
+
+    let mut row = pos + Vector2::new(0.0, title_size);
+    draw_hud_text(d, font, mode_name(mode), row, font_size, Color::WHITE);
+    row.y += row_height;
+    draw_hud_text(
+        d,
+        font,
+        &format!(
+            "Score {}  Kills {}  Time {}",
+            score.points,
+            score.kills,
+            format_time(time)
+        ),
+        row,
+        font_size,
+        Color::WHITE,
+    );
+    row.y += row_height;
+    let placed = match rank {
+        Some(rank) => format!("New high score, #{}", rank + 1),
+        None if score.cheated => "Debug tools used, not ranked".to_string(),
+        None => "High scores".to_string(),
+    };
+    draw_hud_text(d, font, &placed, row, font_size, Color::WHITE);
+    row.y += row_height;
+    row = draw_high_scores(
+        d,
+        font,
+        &entries,
+        rank,
+        row,
+        small_size,
+        small_size + 6.0 * scale,
+    );
+    draw_hud_text(
+        d,
+        font,
+        "Enter Mode select",
+        row,
+        font_size,
+        Color::new(140, 255, 251, 255),
+    );
+}
+
+/// Kill quota progress and the clock running down.
+pub fn draw_time_attack(
+    d: &mut RaylibDrawHandle,
+    score: &Score,
+    time: f32,
+    font: &WeakFont,
+    scale: f32,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    let font_size = 18.0 * scale;
+    let remaining = f32::max(TIME_ATTACK_LIMIT - time, 0.0);
+    let text = format!(
+        "Kills {}/{}  {} left",
+        score.kills,
+        TIME_ATTACK_QUOTA,
+        format_time(remaining)
+    );
+    let bounds = font.measure_text(&text, font_size, font_size / 10.0);
+    let size = Vector2::new(bounds.x, bounds.y + 8.0 * scale);
+    let pos = anchor_pos(
+        &Anchor::TopLeft,
+        Vector2::new(MARGIN * scale, MARGIN * scale),
+        size,
+        screenwidth,
+        screenheight,
+    );
+    draw_panel(d, pos, size, scale);
+    // Red once there's less than half a minute to go.
+    let color = if remaining < 30.0 {
+        Color::RED
+    } else {
+        Color::WHITE
+    };
+    draw_hud_text(d, font, &text, pos, font_size, color);
+    draw_bar(
+        d,
+        Vector2::new(pos.x, pos.y + bounds.y + 4.0 * scale),
+        Vector2::new(bounds.x, 4.0 * scale),
+        score.kills as f32 / TIME_ATTACK_QUOTA as f32,
+        Color::GOLD,
+    );
+}
+
+/// Names the sector for a few seconds after crossing into it.
+pub fn draw_sector(
+    d: &mut RaylibDrawHandle,
+    world: &World,
+    font: &WeakFont,
+    scale: f32,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    if world.sector_time <= 0.0 {
+        return;
+    }
+    let text = format!("Sector {}", sector_name(world.sector));
+    let font_size = 28.0 * scale;
+    let bounds = font.measure_text(&text, font_size, font_size / 10.0);
+    let pos = anchor_pos(
+        &Anchor::Top,
+        Vector2::new(0.0, screenheight as f32 * 0.15),
+        bounds,
+        screenwidth,
+        screenheight,
+    );
+    let alpha = (world.sector_time / 0.5).clamp(0.0, 1.0);
+    draw_hud_text(
+        d,
+        font,
+        &text,
+        pos,
+        font_size,
+        Color::new(140, 255, 251, (255.0 * alpha) as u8),
+    );
 }
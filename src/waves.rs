@@ -1,16 +1,361 @@
+use rand::rngs::StdRng;
 use rand::Rng;
 use raylib::prelude::*;
-use slotmap::SlotMap;
 
 use crate::{
-    angletovector, BulletEmitter, Enemy, ParticalEmitter, ParticalShape, Player, TextureID, Wave,
+    angletovector, load_texture, log, new_animator, part_damage_emmiters, scale_enemy,
+    spawn_formation, AnimationState, Assets, BulletEmitter, Damage, DamageType, DifficultyPreset,
+    Enemy, EnemySpawner, FormationShape, LogCategory, LogLevel, Part, PartMod, ParticalEmitter,
+    Player, Wave, ENEMY_EXHAUST, MOTHERSHIP_EXHAUST, TURRET_EXHAUST,
 };
 
-pub fn init_waves(
-    textures: &mut SlotMap<TextureID, Texture2D>,
+/// Every enemy type, looked up by name by the wave table and level scripts.
+pub fn init_archetypes(
+    assets: &mut Assets,
     rl: &mut RaylibHandle,
     thread: &RaylibThread,
-) -> Vec<Wave> {
+) -> Vec<Enemy> {
+    let basic = Enemy {
+        name: format!("Basic"),
+        pos: Vector2::zero(),
+        vel: Vector2::zero(),
+        dir: Vector2::zero(),
+        targetpos: Vector2 { x: 200.0, y: 200.0 },
+        speed: 600.0,
+        turningspeed: 100.0,
+        predictive: true,
+        accuracy: 0.0,
+        aim_jitter: 0.0,
+        texture_scale: 1.0,
+        friction: 1.0,
+        size: 16.0,
+        health: 1.0,
+        partical_emmiters: vec![ParticalEmitter {
+            pos: Vector2::zero(),
+            location: Vector2 { x: 0.0, y: -13.0 },
+            vel: Vector2::zero(),
+            speed_orginal: 400.0,
+            preset: ENEMY_EXHAUST,
+            partical_interval: 1.0 / 400.0,
+            time: 0.0,
+            speed: 0.0,
+        }],
+        bullet_emmiters: vec![],
+        texture_id: load_texture(assets, rl, thread, "Images/V1Enemy.png"),
+        extra_texture_ids: vec![],
+        points: 100,
+        parts: vec![],
+        damage: vec![],
+        phase: 0,
+        flocking: true,
+        formation: None,
+        blast_radius: 0.0,
+        blast_damage: 0.0,
+        lifetime: f32::INFINITY,
+        killed_by_player: false,
+        spawners: vec![],
+        animator: new_animator(&[
+            (AnimationState::Thrusting, "basic_thrust"),
+            (AnimationState::BankLeft, "basic_bank_left"),
+            (AnimationState::BankRight, "basic_bank_right"),
+            (AnimationState::Dying, "basic_dying"),
+        ]),
+    };
+    let turret = Enemy {
+        name: format!("Turret"),
+        pos: Vector2::zero(),
+        vel: Vector2::zero(),
+        dir: Vector2::zero(),
+        targetpos: Vector2 { x: 200.0, y: 200.0 },
+        speed: 500.0,
+        turningspeed: 100.0,
+        predictive: false,
+        accuracy: 0.8,
+        aim_jitter: 4.0,
+        texture_scale: 1.5,
+        friction: 1.0,
+        size: 24.0,
+        health: 7.0,
+        partical_emmiters: vec![ParticalEmitter {
+            pos: Vector2::zero(),
+            location: Vector2 { x: 0.0, y: -15.0 },
+            vel: Vector2::zero(),
+            speed_orginal: 800.0,
+            preset: TURRET_EXHAUST,
+            partical_interval: 1.0 / 400.0,
+            time: 0.0,
+            speed: 0.0,
+        }],
+        bullet_emmiters: vec![BulletEmitter {
+            pos: Vector2::zero(),
+            location: Vector2 { x: 0.0, y: 10.0 },
+            size: 5.0,
+            speed: 1000.0,
+            damage: 0.3,
+            friendly: false,
+            duration: 2.0,
+            bullet_interval: 1.0 / 2.0,
+            time: 0.0,
+            windup: 0.3,
+            aim_line: false,
+            aim: Vector2::zero(),
+        }],
+        texture_id: load_texture(assets, rl, thread, "Images/V2EnemyBase.png"),
+        extra_texture_ids: vec![load_texture(assets, rl, thread, "Images/V2EnemyCannon.png")],
+        points: 300,
+        parts: vec![],
+        damage: vec![],
+        phase: 0,
+        flocking: false,
+        formation: None,
+        blast_radius: 0.0,
+        blast_damage: 0.0,
+        lifetime: f32::INFINITY,
+        killed_by_player: false,
+        spawners: vec![],
+        animator: new_animator(&[(AnimationState::Dying, "turret_dying")]),
+    };
+    let kamikaze = Enemy {
+        name: format!("Kamikaze"),
+        speed: 750.0,
+        turningspeed: 140.0,
+        size: 14.0,
+        health: 2.0,
+        texture_id: load_texture(assets, rl, thread, "Images/Kamikaze.png"),
+        points: 150,
+        flocking: false,
+        blast_radius: 160.0,
+        blast_damage: 1.5,
+        animator: new_animator(&[
+            (AnimationState::Thrusting, "kamikaze_thrust"),
+            (AnimationState::BankLeft, "kamikaze_bank_left"),
+            (AnimationState::BankRight, "kamikaze_bank_right"),
+            (AnimationState::Dying, "kamikaze_dying"),
+        ]),
+        ..basic.clone()
+    };
+    let sniper = Enemy {
+        name: format!("Sniper"),
+        speed: 400.0,
+        turningspeed: 120.0,
+        predictive: false,
+        accuracy: 1.0,
+        aim_jitter: 0.5,
+        texture_scale: 1.2,
+        size: 18.0,
+        health: 4.0,
+        bullet_emmiters: vec![BulletEmitter {
+            pos: Vector2::zero(),
+            location: Vector2 { x: 0.0, y: 10.0 },
+            size: 6.0,
+            speed: 1800.0,
+            damage: 1.0,
+            friendly: false,
+            duration: 1.5,
+            bullet_interval: 3.5,
+            time: 0.0,
+            windup: 1.0,
+            aim_line: true,
+            aim: Vector2::zero(),
+        }],
+        texture_id: load_texture(assets, rl, thread, "Images/Sniper.png"),
+        points: 250,
+        flocking: false,
+        animator: new_animator(&[
+            (AnimationState::Thrusting, "sniper_thrust"),
+            (AnimationState::Dying, "sniper_dying"),
+        ]),
+        ..basic.clone()
+    };
+    let drone = Enemy {
+        name: format!("Drone"),
+        speed: 650.0,
+        texture_scale: 0.6,
+        size: 10.0,
+        health: 0.5,
+        points: 25,
+        ..basic.clone()
+    };
+    let carrier = Enemy {
+        name: format!("Carrier"),
+        speed: 250.0,
+        turningspeed: 50.0,
+        predictive: false,
+        texture_scale: 2.0,
+        size: 30.0,
+        health: 15.0,
+        texture_id: load_texture(assets, rl, thread, "Images/Carrier.png"),
+        points: 500,
+        flocking: false,
+        spawners: vec![EnemySpawner {
+            location: Vector2 { x: 0.0, y: 45.0 },
+            launch_speed: 400.0,
+            interval: 5.0,
+            time: 0.0,
+            enemy: Box::new(drone.clone()),
+        }],
+        animator: new_animator(&[
+            (AnimationState::Thrusting, "carrier_thrust"),
+            (AnimationState::Dying, "carrier_dying"),
+        ]),
+        ..basic.clone()
+    };
+    let mine = Enemy {
+        name: format!("Mine"),
+        speed: 0.0,
+        turningspeed: 0.0,
+        predictive: false,
+        texture_scale: 1.5,
+        size: 10.0,
+        health: 1.0,
+        partical_emmiters: vec![],
+        texture_id: load_texture(assets, rl, thread, "Images/Mine.png"),
+        points: 10,
+        flocking: false,
+        blast_radius: 130.0,
+        blast_damage: 1.0,
+        lifetime: 25.0,
+        killed_by_player: false,
+        animator: new_animator(&[
+            (AnimationState::Idle, "mine_idle"),
+            (AnimationState::Dying, "mine_dying"),
+        ]),
+        ..basic.clone()
+    };
+    let mine_layer = Enemy {
+        name: format!("Mine Layer"),
+        speed: 450.0,
+        turningspeed: 90.0,
+        predictive: false,
+        size: 18.0,
+        health: 5.0,
+        texture_id: load_texture(assets, rl, thread, "Images/MineLayer.png"),
+        points: 200,
+        flocking: false,
+        spawners: vec![EnemySpawner {
+            location: Vector2 { x: 0.0, y: -40.0 },
+            launch_speed: 0.0,
+            interval: 2.5,
+            time: 0.0,
+            enemy: Box::new(mine.clone()),
+        }],
+        animator: new_animator(&[
+            (AnimationState::Thrusting, "mine_layer_thrust"),
+            (AnimationState::Dying, "mine_layer_dying"),
+        ]),
+        ..basic.clone()
+    };
+    let mothership = Enemy {
+        name: format!("Mothership"),
+        pos: Vector2::zero(),
+        vel: Vector2::zero(),
+        dir: Vector2::zero(),
+        targetpos: Vector2 { x: 200.0, y: 200.0 },
+        speed: 150.0,
+        turningspeed: 40.0,
+        predictive: false,
+        accuracy: 0.5,
+        aim_jitter: 8.0,
+        texture_scale: 2.5,
+        friction: 1.0,
+        size: 65.0,
+        health: 60.0,
+        partical_emmiters: vec![
+            mothership_engine(Vector2 { x: 24.0, y: -75.0 }),
+            mothership_engine(Vector2 { x: -24.0, y: -75.0 }),
+        ],
+        bullet_emmiters: vec![
+            mothership_cannon(Vector2 { x: 55.0, y: 45.0 }),
+            mothership_cannon(Vector2 { x: -54.0, y: 45.0 }),
+        ],
+        texture_id: load_texture(assets, rl, thread, "Images/Mothership.png"),
+        extra_texture_ids: vec![],
+        points: 5000,
+        parts: vec![
+            mothership_part("Core", Vector2 { x: 0.0, y: 0.0 }, 20.0, 60.0),
+            mothership_part("Left Cannon", Vector2 { x: 55.0, y: 5.0 }, 18.0, 25.0),
+            mothership_part("Right Cannon", Vector2 { x: -54.0, y: 5.0 }, 18.0, 25.0),
+            mothership_part("Left Engine", Vector2 { x: 24.0, y: -45.0 }, 16.0, 20.0),
+            mothership_part("Right Engine", Vector2 { x: -24.0, y: -45.0 }, 16.0, 20.0),
+        ],
+        damage: vec![
+            Damage {
+                src: vec![1],
+                des: PartMod::Gun,
+                index: 0,
+                damage_type: DamageType::Mult,
+                scale: 1.0,
+            },
+            Damage {
+                src: vec![2],
+                des: PartMod::Gun,
+                index: 1,
+                damage_type: DamageType::Mult,
+                scale: 1.0,
+            },
+            Damage {
+                src: vec![3, 4],
+                des: PartMod::Speed,
+                index: 0,
+                damage_type: DamageType::Mult,
+                scale: 1.0,
+            },
+            Damage {
+                src: vec![4],
+                des: PartMod::TurnLeft,
+                index: 0,
+                damage_type: DamageType::Mult,
+                scale: 1.0,
+            },
+            Damage {
+                src: vec![3],
+                des: PartMod::TurnRight,
+                index: 0,
+                damage_type: DamageType::Mult,
+                scale: 1.0,
+            },
+            Damage {
+                src: vec![3],
+                des: PartMod::Partical,
+                index: 0,
+                damage_type: DamageType::Mult,
+                scale: 1.0,
+            },
+            Damage {
+                src: vec![4],
+                des: PartMod::Partical,
+                index: 1,
+                damage_type: DamageType::Mult,
+                scale: 1.0,
+            },
+        ],
+        phase: 0,
+        flocking: false,
+        formation: None,
+        blast_radius: 0.0,
+        blast_damage: 0.0,
+        lifetime: f32::INFINITY,
+        killed_by_player: false,
+        spawners: vec![],
+        animator: new_animator(&[
+            (AnimationState::Thrusting, "mothership_thrust"),
+            (AnimationState::Damaged, "mothership_damaged"),
+            (AnimationState::Dying, "mothership_dying"),
+        ]),
+    };
+    vec![
+        basic, turret, mothership, kamikaze, sniper, drone, carrier, mine, mine_layer,
+    ]
+}
+
+pub fn archetype<'a>(archetypes: &'a Vec<Enemy>, name: &str) -> &'a Enemy {
+    archetypes
+        .iter()
+        .find(|enemy| enemy.name == name)
+        .unwrap_or_else(|| panic!("no enemy archetype called {name}"))
+}
+
+pub fn init_waves(archetypes: &Vec<Enemy>) -> Vec<Wave> {
     vec![
         Wave {
             interval: 6.0,
@@ -19,48 +364,10 @@ pub fn init_waves(
             double_spawn_chance: 0.5,
             max_double_spawn_chance: 0.7,
             time: 0.0,
-            enemy: Enemy {
-                name: format!("Basic"),
-                pos: Vector2::zero(),
-                vel: Vector2::zero(),
-                dir: Vector2::zero(),
-                targetpos: Vector2 { x: 200.0, y: 200.0 },
-                speed: 600.0,
-                turningspeed: 100.0,
-                predictive: false,
-                texture_scale: 1.0,
-                friction: 1.0,
-                size: 16.0,
-                health: 1.0,
-                partical_emmiters: vec![ParticalEmitter {
-                    pos: Vector2::zero(),
-                    location: Vector2 { x: 0.0, y: -13.0 },
-                    vel: Vector2::zero(),
-                    speed_orginal: 400.0,
-                    size: 5.0,
-                    shape: ParticalShape::Square,
-                    starting_color: Color {
-                        r: 255,
-                        g: 255,
-                        b: 0,
-                        a: 255,
-                    },
-                    ending_color: Color {
-                        r: 255,
-                        g: 0,
-                        b: 50,
-                        a: 0,
-                    },
-                    duration: 1.0,
-                    partical_interval: 1.0 / 400.0,
-                    time: 0.0,
-                    speed: 0.0,
-                }],
-                bullet_emmiters: vec![],
-                texture_id: textures
-                    .insert(rl.load_texture(&thread, "Images/V1Enemy.png").unwrap()),
-                extra_texture_ids: vec![],
-            },
+            milestones: vec![],
+            formation: FormationShape::Single,
+            formation_size: 1,
+            enemy: archetype(archetypes, "Basic").clone(),
         },
         Wave {
             interval: 16.0,
@@ -69,92 +376,280 @@ pub fn init_waves(
             double_spawn_chance: 0.1,
             max_double_spawn_chance: 0.5,
             time: 0.0,
-            enemy: Enemy {
-                name: format!("Turret"),
-                pos: Vector2::zero(),
-                vel: Vector2::zero(),
-                dir: Vector2::zero(),
-                targetpos: Vector2 { x: 200.0, y: 200.0 },
-                speed: 500.0,
-                turningspeed: 100.0,
-                predictive: false,
-                texture_scale: 1.5,
-                friction: 1.0,
-                size: 24.0,
-                health: 7.0,
-                partical_emmiters: vec![ParticalEmitter {
-                    pos: Vector2::zero(),
-                    location: Vector2 { x: 0.0, y: -15.0 },
-                    vel: Vector2::zero(),
-                    speed_orginal: 800.0,
-                    size: 10.0,
-                    shape: ParticalShape::Square,
-                    starting_color: Color {
-                        r: 255,
-                        g: 255,
-                        b: 0,
-                        a: 255,
-                    },
-                    ending_color: Color {
-                        r: 255,
-                        g: 0,
-                        b: 50,
-                        a: 0,
-                    },
-                    duration: 1.0,
-                    partical_interval: 1.0 / 400.0,
-                    time: 0.0,
-                    speed: 0.0,
-                }],
-                bullet_emmiters: vec![BulletEmitter {
-                    pos: Vector2::zero(),
-                    location: Vector2 { x: 0.0, y: 10.0 },
-                    size: 5.0,
-                    damage: 0.3,
-                    friendly: false,
-                    duration: 2.0,
-                    bullet_interval: 1.0 / 2.0,
-                    time: 0.0,
-                }],
-                texture_id: textures
-                    .insert(rl.load_texture(&thread, "Images/V2EnemyBase.png").unwrap()),
-                extra_texture_ids: vec![textures.insert(
-                    rl.load_texture(&thread, "Images/V2EnemyCannon.png")
-                        .unwrap(),
-                )],
-            },
+            milestones: vec![],
+            formation: FormationShape::Single,
+            formation_size: 1,
+            enemy: archetype(archetypes, "Turret").clone(),
+        },
+        Wave {
+            interval: f32::INFINITY,
+            min_interval: f32::INFINITY,
+            interval_delta: 0.0,
+            double_spawn_chance: 0.0,
+            max_double_spawn_chance: 0.0,
+            time: 0.0,
+            milestones: vec![120.0, 300.0, 480.0],
+            formation: FormationShape::Single,
+            formation_size: 1,
+            enemy: archetype(archetypes, "Mothership").clone(),
+        },
+        Wave {
+            interval: 30.0,
+            min_interval: 30.0,
+            interval_delta: 0.0,
+            double_spawn_chance: 0.0,
+            max_double_spawn_chance: 0.0,
+            time: 0.0,
+            milestones: vec![],
+            formation: FormationShape::V,
+            formation_size: 5,
+            enemy: archetype(archetypes, "Basic").clone(),
+        },
+        Wave {
+            interval: 55.0,
+            min_interval: 55.0,
+            interval_delta: 0.0,
+            double_spawn_chance: 0.0,
+            max_double_spawn_chance: 0.0,
+            time: 0.0,
+            milestones: vec![],
+            formation: FormationShape::Ring,
+            formation_size: 9,
+            enemy: archetype(archetypes, "Basic").clone(),
+        },
+        Wave {
+            interval: 70.0,
+            min_interval: 70.0,
+            interval_delta: 0.0,
+            double_spawn_chance: 0.0,
+            max_double_spawn_chance: 0.0,
+            time: 0.0,
+            milestones: vec![],
+            formation: FormationShape::Line,
+            formation_size: 3,
+            enemy: archetype(archetypes, "Turret").clone(),
+        },
+        // Negative start times hold these waves back until later in the run.
+        Wave {
+            interval: 12.0,
+            min_interval: 4.0,
+            interval_delta: 0.3,
+            double_spawn_chance: 0.2,
+            max_double_spawn_chance: 0.6,
+            time: -40.0,
+            milestones: vec![],
+            formation: FormationShape::Single,
+            formation_size: 1,
+            enemy: archetype(archetypes, "Kamikaze").clone(),
+        },
+        Wave {
+            interval: 25.0,
+            min_interval: 10.0,
+            interval_delta: 0.3,
+            double_spawn_chance: 0.0,
+            max_double_spawn_chance: 0.3,
+            time: -60.0,
+            milestones: vec![],
+            formation: FormationShape::Single,
+            formation_size: 1,
+            enemy: archetype(archetypes, "Sniper").clone(),
+        },
+        Wave {
+            interval: 45.0,
+            min_interval: 25.0,
+            interval_delta: 0.3,
+            double_spawn_chance: 0.0,
+            max_double_spawn_chance: 0.2,
+            time: -90.0,
+            milestones: vec![],
+            formation: FormationShape::Single,
+            formation_size: 1,
+            enemy: archetype(archetypes, "Carrier").clone(),
+        },
+        Wave {
+            interval: 35.0,
+            min_interval: 15.0,
+            interval_delta: 0.3,
+            double_spawn_chance: 0.0,
+            max_double_spawn_chance: 0.3,
+            time: -75.0,
+            milestones: vec![],
+            formation: FormationShape::Single,
+            formation_size: 1,
+            enemy: archetype(archetypes, "Mine Layer").clone(),
         },
     ]
 }
 
-pub fn update_waves(waves: &mut Vec<Wave>, player: &Player, enemies: &mut Vec<Enemy>, dt: f32) {
+fn mothership_part(name: &str, location: Vector2, size: f32, health: f32) -> Part {
+    Part {
+        pos: Vector2::zero(),
+        location,
+        health,
+        starting_health: health,
+        size,
+        name: name.to_string(),
+        flash: 0.0,
+        damage_emmiters: part_damage_emmiters(),
+    }
+}
+
+fn mothership_engine(location: Vector2) -> ParticalEmitter {
+    ParticalEmitter {
+        pos: Vector2::zero(),
+        location,
+        vel: Vector2::zero(),
+        speed_orginal: 300.0,
+        preset: MOTHERSHIP_EXHAUST,
+        partical_interval: 1.0 / 200.0,
+        time: 0.0,
+        speed: 0.0,
+    }
+}
+
+fn mothership_cannon(location: Vector2) -> BulletEmitter {
+    BulletEmitter {
+        pos: Vector2::zero(),
+        location,
+        size: 7.0,
+        speed: 1000.0,
+        damage: 0.4,
+        friendly: false,
+        duration: 2.5,
+        bullet_interval: 1.0 / 1.5,
+        time: 0.0,
+        windup: 0.5,
+        aim_line: true,
+        aim: Vector2::zero(),
+    }
+}
+
+fn place_enemy(
+    enemy: &Enemy,
+    pos: Option<Vector2>,
+    player: &Player,
+    difficulty: &DifficultyPreset,
+    rng: &mut StdRng,
+) -> Enemy {
+    let mut enemy = enemy.clone();
+    scale_enemy(&mut enemy, difficulty);
+    enemy.pos = player.pos
+        + pos.unwrap_or(
+            angletovector(rng.gen_range(-std::f32::consts::PI..std::f32::consts::PI)) * 2000.0,
+        );
+    enemy.dir = angletovector(rng.gen_range(-std::f32::consts::PI..std::f32::consts::PI));
+    enemy
+}
+
+/// Spawns `amount` enemies, either one by one or as a single formation, at `pos` relative
+/// to the player or from a random direction.
+pub fn spawn_group(
+    enemy: &Enemy,
+    formation: &FormationShape,
+    amount: usize,
+    pos: Option<Vector2>,
+    player: &Player,
+    enemies: &mut Vec<Enemy>,
+    difficulty: &DifficultyPreset,
+    rng: &mut StdRng,
+) {
+    log(
+        LogLevel::Debug,
+        LogCategory::Waves,
+        &format!("spawning {amount} {}", enemy.name),
+    );
+    match formation {
+        FormationShape::Single => {
+            for _ in 0..amount {
+                let mut enemy = place_enemy(enemy, pos, player, difficulty, rng);
+                if pos.is_some() {
+                    // Scatter a little so a group at one spot doesn't collide on spawn.
+                    enemy.pos +=
+                        angletovector(rng.gen_range(-std::f32::consts::PI..std::f32::consts::PI))
+                            * rng.gen_range(0.0..40.0 * amount as f32);
+                }
+                enemies.push(enemy);
+            }
+        }
+        _ => {
+            let mut enemy = place_enemy(enemy, pos, player, difficulty, rng);
+            // Formations arrive already heading in, so the shape reads before they break up.
+            enemy.dir = (player.pos - enemy.pos).normalized();
+            spawn_formation(&enemy, formation, amount, enemies, rng);
+        }
+    }
+}
+
+fn spawn_enemy(
+    wave: &Wave,
+    player: &Player,
+    enemies: &mut Vec<Enemy>,
+    difficulty: &DifficultyPreset,
+    rng: &mut StdRng,
+) {
+    spawn_group(
+        &wave.enemy,
+        &wave.formation,
+        wave.formation_size,
+        None,
+        player,
+        enemies,
+        difficulty,
+        rng,
+    );
+}
+
+/// Brings forward whichever wave is closest to spawning, for testing.
+pub fn next_wave(
+    waves: &mut Vec<Wave>,
+    player: &Player,
+    enemies: &mut Vec<Enemy>,
+    difficulty: &DifficultyPreset,
+    rng: &mut StdRng,
+) {
+    let next = waves
+        .iter_mut()
+        .max_by(|a, b| (a.time / a.interval).total_cmp(&(b.time / b.interval)));
+    if let Some(wave) = next {
+        spawn_enemy(wave, player, enemies, difficulty, rng);
+        wave.time = 0.0;
+    }
+}
+
+pub fn update_waves(
+    waves: &mut Vec<Wave>,
+    player: &Player,
+    enemies: &mut Vec<Enemy>,
+    difficulty: &DifficultyPreset,
+    rng: &mut StdRng,
+    time: f32,
+    dt: f32,
+) {
     for wave in waves.iter_mut() {
+        while wave
+            .milestones
+            .first()
+            .is_some_and(|milestone| time >= *milestone)
+        {
+            spawn_enemy(wave, player, enemies, difficulty, rng);
+            wave.milestones.remove(0);
+        }
         while wave.time > wave.interval {
             let mut amount = 1;
-            while rand::thread_rng().gen_range(0.0..1.0)
-                < wave.double_spawn_chance / (amount * amount) as f32
-            {
+            while rng.gen_range(0.0..1.0) < wave.double_spawn_chance / (amount * amount) as f32 {
                 amount += 1;
             }
             wave.double_spawn_chance = f32::min(
                 wave.double_spawn_chance + 0.05,
                 wave.max_double_spawn_chance,
             );
-            wave.interval = f32::max(wave.interval + wave.interval_delta, wave.min_interval);
+            wave.interval = f32::max(wave.interval - wave.interval_delta, wave.min_interval);
 
             for _ in 0..amount {
-                let mut enemy = wave.enemy.clone();
-                enemy.pos = player.pos
-                    + angletovector(
-                        rand::thread_rng().gen_range(-std::f32::consts::PI..std::f32::consts::PI),
-                    ) * 2000.0;
-                enemy.dir = angletovector(
-                    rand::thread_rng().gen_range(-std::f32::consts::PI..std::f32::consts::PI),
-                );
-                enemies.push(enemy);
+                spawn_enemy(wave, player, enemies, difficulty, rng);
             }
             wave.time -= wave.interval;
         }
-        wave.time += dt;
+        wave.time += dt * difficulty.spawn_rate;
     }
 }
@@ -0,0 +1,531 @@
+use std::collections::HashSet;
+
+use noise::{Fbm, NoiseFn};
+use rand::prelude::*;
+use raylib::prelude::*;
+
+use crate::{
+    add_damage_number, angletovector, colorlerp, emit, play_sound, rotatevector, Asteroid, Audio,
+    Bullet, DamageNumber, Debris, Enemy, Environment, ParticalPreset, Particals, Player,
+    SoundEffect, World, Zone, ZoneKind, PART_FLASH_TIME, ROCK_BREAK, ROCK_CHIPS,
+};
+
+pub const CHUNK_SIZE: f32 = 1000.0;
+pub const SECTOR_CHUNKS: i32 = 8;
+const LOAD_RADIUS: i32 = 2;
+const UNLOAD_RADIUS: i32 = 3;
+// Keeps the start of a run clear so the player doesn't spawn inside a rock.
+const SPAWN_CLEARANCE: f32 = 400.0;
+const MIN_ASTEROID_RADIUS: f32 = 14.0;
+const MAX_ASTEROID_RADIUS: f32 = 80.0;
+const IMPACT_DAMAGE: f32 = 0.004;
+const SECTOR_BANNER_TIME: f32 = 3.0;
+
+pub fn new_world(seed: u64) -> World {
+    World {
+        seed,
+        density: Fbm::new(seed as u32),
+        chunks: HashSet::new(),
+        asteroids: vec![],
+        debris: vec![],
+        zones: vec![],
+        // Nowhere, so the first update announces the starting sector.
+        sector: (i32::MAX, i32::MAX),
+        sector_time: 0.0,
+    }
+}
+
+pub fn chunk_of(pos: Vector2) -> (i32, i32) {
+    (
+        (pos.x / CHUNK_SIZE).floor() as i32,
+        (pos.y / CHUNK_SIZE).floor() as i32,
+    )
+}
+
+pub fn sector_of(chunk: (i32, i32)) -> (i32, i32) {
+    (
+        chunk.0.div_euclid(SECTOR_CHUNKS),
+        chunk.1.div_euclid(SECTOR_CHUNKS),
+    )
+}
+
+/// Compass style, with north being up the screen, e.g. `N2 E5`.
+pub fn sector_name(sector: (i32, i32)) -> String {
+    format!(
+        "{}{} {}{}",
+        if sector.1 < 0 { 'N' } else { 'S' },
+        sector.1.unsigned_abs(),
+        if sector.0 < 0 { 'W' } else { 'E' },
+        sector.0.unsigned_abs()
+    )
+}
+
+/// Every chunk gets its own rng from the world seed and its coordinates, so the same seed
+/// always builds the same chunk no matter which order they're visited in.
+fn chunk_rng(seed: u64, chunk: (i32, i32)) -> StdRng {
+    StdRng::seed_from_u64(
+        seed ^ (chunk.0 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            ^ (chunk.1 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F),
+    )
+}
+
+/// Some sectors are packed with rock and some are nearly empty.
+fn sector_richness(seed: u64, sector: (i32, i32)) -> f32 {
+    chunk_rng(seed ^ 0x5EC7, sector).gen_range(0.4..1.6)
+}
+
+fn new_asteroid(
+    rng: &mut impl Rng,
+    pos: Vector2,
+    vel: Vector2,
+    radius: f32,
+    chunk: (i32, i32),
+) -> Asteroid {
+    let points = 8 + (radius / 10.0) as usize;
+    Asteroid {
+        pos,
+        vel,
+        radius,
+        health: radius * 0.12,
+        rotation: rng.gen_range(0.0..std::f32::consts::TAU),
+        spin: rng.gen_range(-0.6..0.6),
+        shape: (0..points).map(|_| rng.gen_range(0.75..1.0)).collect(),
+        chunk,
+        flash: 0.0,
+    }
+}
+
+fn generate_chunk(world: &mut World, chunk: (i32, i32)) {
+    let mut rng = chunk_rng(world.seed, chunk);
+    let origin = Vector2::new(chunk.0 as f32, chunk.1 as f32) * CHUNK_SIZE;
+    // Low frequency so a field spans several chunks instead of speckling every one.
+    let density = world
+        .density
+        .get([chunk.0 as f64 * 0.15, chunk.1 as f64 * 0.15]) as f32
+        * sector_richness(world.seed, sector_of(chunk));
+    let count = ((density - 0.1) * 40.0).max(0.0) as usize;
+    for _ in 0..count {
+        let pos = origin
+            + Vector2::new(
+                rng.gen_range(0.0..CHUNK_SIZE),
+                rng.gen_range(0.0..CHUNK_SIZE),
+            );
+        let vel = angletovector(rng.gen_range(-std::f32::consts::PI..std::f32::consts::PI))
+            * rng.gen_range(0.0..20.0);
+        let radius = rng.gen_range(MIN_ASTEROID_RADIUS..MAX_ASTEROID_RADIUS);
+        let asteroid = new_asteroid(&mut rng, pos, vel, radius, chunk);
+        if pos.length() > SPAWN_CLEARANCE + radius {
+            world.asteroids.push(asteroid);
+        }
+    }
+
+    // Now and then the wreck of something that didn't make it.
+    if rng.gen_range(0.0..1.0) < 0.15 {
+        let center = origin
+            + Vector2::new(
+                rng.gen_range(0.0..CHUNK_SIZE),
+                rng.gen_range(0.0..CHUNK_SIZE),
+            );
+        let shade = rng.gen_range(70..130);
+        for _ in 0..rng.gen_range(4..12) {
+            world.debris.push(Debris {
+                pos: center
+                    + angletovector(rng.gen_range(-std::f32::consts::PI..std::f32::consts::PI))
+                        * rng.gen_range(0.0..150.0),
+                vel: angletovector(rng.gen_range(-std::f32::consts::PI..std::f32::consts::PI))
+                    * rng.gen_range(0.0..8.0),
+                size: Vector2::new(rng.gen_range(8.0..40.0), rng.gen_range(4.0..16.0)),
+                rotation: rng.gen_range(0.0..std::f32::consts::TAU),
+                spin: rng.gen_range(-0.3..0.3),
+                color: Color::new(shade, shade, shade + 10, 255),
+                chunk,
+            });
+        }
+    }
+
+    // At most one hazard per chunk, never on top of the start.
+    let roll = rng.gen_range(0.0..1.0);
+    let (kind, radius, strength) = if roll < 0.06 {
+        (
+            ZoneKind::GravityWell,
+            rng.gen_range(300.0..500.0),
+            rng.gen_range(250.0..400.0),
+        )
+    } else if roll < 0.14 {
+        (
+            ZoneKind::Nebula,
+            rng.gen_range(400.0..800.0),
+            rng.gen_range(0.6..1.0),
+        )
+    } else if roll < 0.19 {
+        (
+            ZoneKind::IonStorm,
+            rng.gen_range(300.0..600.0),
+            rng.gen_range(0.6..0.9),
+        )
+    } else {
+        return;
+    };
+    let pos = origin
+        + Vector2::new(
+            rng.gen_range(0.0..CHUNK_SIZE),
+            rng.gen_range(0.0..CHUNK_SIZE),
+        );
+    let blobs = (0..8)
+        .map(|_| {
+            (
+                angletovector(rng.gen_range(-std::f32::consts::PI..std::f32::consts::PI))
+                    * rng.gen_range(0.0..radius * 0.6),
+                rng.gen_range(radius * 0.3..radius * 0.5),
+            )
+        })
+        .collect();
+    if pos.length() > SPAWN_CLEARANCE + radius {
+        world.zones.push(Zone {
+            pos,
+            radius,
+            kind,
+            strength,
+            blobs,
+            chunk,
+        });
+    }
+}
+
+/// What the zones covering `pos` do to anything there. Gravity falls off towards the edge of
+/// a well, nebula drag stacks and the strongest ion storm wins.
+pub fn environment_at(world: &World, pos: Vector2) -> Environment {
+    let mut environment = Environment {
+        gravity: Vector2::zero(),
+        drag: 0.0,
+        ion: 0.0,
+        hidden: false,
+    };
+    for zone in &world.zones {
+        let offset = zone.pos - pos;
+        let distance = offset.length();
+        if distance > zone.radius {
+            continue;
+        }
+        match zone.kind {
+            ZoneKind::GravityWell => {
+                environment.gravity +=
+                    offset.normalized() * zone.strength * (1.0 - distance / zone.radius)
+            }
+            ZoneKind::Nebula => {
+                environment.drag += zone.strength;
+                environment.hidden = true;
+            }
+            ZoneKind::IonStorm => environment.ion = f32::max(environment.ion, zone.strength),
+        }
+    }
+    environment
+}
+
+/// Ion storms cost turning speed and kick the heading around.
+pub fn ion_turn(dir: Vector2, environment: &Environment, rng: &mut StdRng, dt: f32) -> Vector2 {
+    if environment.ion <= 0.0 {
+        return dir;
+    }
+    rotatevector(dir, rng.gen_range(-1.0..1.0) * environment.ion * 3.0 * dt)
+}
+
+/// Loads chunks around the player, drops far away ones and resolves asteroid collisions.
+/// Unloaded chunks regenerate from the seed, so a cleared field is back if you leave and
+/// come back.
+pub fn update_world(
+    world: &mut World,
+    player: &mut Player,
+    enemies: &mut Vec<Enemy>,
+    bullets: &mut Vec<Bullet>,
+    particals: &mut Particals,
+    audio: &mut Audio,
+    damage_numbers: &mut Vec<DamageNumber>,
+    rng: &mut StdRng,
+    dt: f32,
+) {
+    let center = chunk_of(player.pos);
+    for x in -LOAD_RADIUS..=LOAD_RADIUS {
+        for y in -LOAD_RADIUS..=LOAD_RADIUS {
+            let chunk = (center.0 + x, center.1 + y);
+            if world.chunks.insert(chunk) {
+                generate_chunk(world, chunk);
+            }
+        }
+    }
+    world.chunks.retain(|chunk| {
+        (chunk.0 - center.0).abs() <= UNLOAD_RADIUS && (chunk.1 - center.1).abs() <= UNLOAD_RADIUS
+    });
+    let chunks = &world.chunks;
+    world
+        .asteroids
+        .retain(|asteroid| chunks.contains(&asteroid.chunk));
+    world.debris.retain(|debris| chunks.contains(&debris.chunk));
+    world.zones.retain(|zone| chunks.contains(&zone.chunk));
+
+    let sector = sector_of(center);
+    if sector != world.sector {
+        world.sector = sector;
+        world.sector_time = SECTOR_BANNER_TIME;
+    }
+    world.sector_time = f32::max(world.sector_time - dt, 0.0);
+
+    // Gravity wells bend every shot in flight, not just ships.
+    for bullet in bullets.iter_mut() {
+        bullet.vel += environment_at(world, bullet.pos).gravity * dt;
+    }
+
+    for debris in &mut world.debris {
+        debris.pos += debris.vel * dt;
+        debris.rotation += debris.spin * dt;
+    }
+
+    let mut fragments = vec![];
+    for asteroid in &mut world.asteroids {
+        asteroid.pos += asteroid.vel * dt;
+        asteroid.rotation += asteroid.spin * dt;
+        asteroid.flash = f32::max(asteroid.flash - dt, 0.0);
+
+        // Rock stops everyone's bullets.
+        for bullet in bullets.iter_mut() {
+            if bullet.time >= bullet.duration
+                || bullet.pos.distance_to(asteroid.pos) > asteroid.radius + bullet.size
+            {
+                continue;
+            }
+            asteroid.health -= bullet.damage - bullet.time / bullet.duration * bullet.damage;
+            asteroid.flash = PART_FLASH_TIME;
+            bullet.time = bullet.duration;
+            emit(particals, &ROCK_CHIPS, bullet.pos, asteroid.vel);
+            play_sound(audio, SoundEffect::Hit, bullet.pos, asteroid.vel);
+        }
+
+        // Push the player out and hurt whichever part hit, harder the faster the impact.
+        for part_index in 0..player.parts.len() {
+            let offset = player.parts[part_index].pos - asteroid.pos;
+            let overlap = asteroid.radius + player.parts[part_index].size - offset.length();
+            if overlap <= 0.0 {
+                continue;
+            }
+            let normal = offset.normalized();
+            let impact = (asteroid.vel - player.vel).dot(normal);
+            player.pos += normal * overlap;
+            if impact <= 0.0 {
+                continue;
+            }
+            player.vel += normal * impact * 1.5;
+            let damage = impact * IMPACT_DAMAGE;
+            asteroid.health -= damage;
+            asteroid.flash = PART_FLASH_TIME;
+            if player.shield <= 0.0 {
+                let part = &mut player.parts[part_index];
+                part.health -= damage;
+                part.flash = PART_FLASH_TIME;
+                add_damage_number(damage_numbers, part.pos, player.vel, damage, false);
+                play_sound(audio, SoundEffect::PartDamage, part.pos, player.vel);
+            }
+        }
+
+        for enemy in enemies.iter_mut() {
+            let offset = enemy.pos - asteroid.pos;
+            let overlap = asteroid.radius + enemy.size - offset.length();
+            if overlap <= 0.0 {
+                continue;
+            }
+            let normal = offset.normalized();
+            enemy.pos += normal * overlap;
+            let impact = (asteroid.vel - enemy.vel).dot(normal);
+            if impact > 0.0 {
+                enemy.vel += normal * impact;
+            }
+        }
+
+        if asteroid.health <= 0.0 {
+            play_sound(audio, SoundEffect::EnemyDeath, asteroid.pos, asteroid.vel);
+            emit(
+                particals,
+                &ParticalPreset {
+                    amount: asteroid.radius as usize * 4,
+                    force_max: asteroid.radius * 4.0,
+                    ..ROCK_BREAK
+                },
+                asteroid.pos,
+                asteroid.vel,
+            );
+            // Big rocks break into a few smaller ones flying apart.
+            if asteroid.radius * 0.5 >= MIN_ASTEROID_RADIUS {
+                let pieces = rng.gen_range(2..4);
+                for piece in 0..pieces {
+                    let dir = angletovector(
+                        std::f32::consts::TAU * piece as f32 / pieces as f32
+                            + rng.gen_range(-0.5..0.5),
+                    );
+                    let radius = asteroid.radius * rng.gen_range(0.5..0.6);
+                    let vel = asteroid.vel + dir * rng.gen_range(40.0..120.0);
+                    fragments.push(new_asteroid(
+                        rng,
+                        asteroid.pos + dir * asteroid.radius * 0.4,
+                        vel,
+                        radius,
+                        asteroid.chunk,
+                    ));
+                }
+            }
+        }
+    }
+    world.asteroids.retain(|asteroid| asteroid.health > 0.0);
+    world.asteroids.append(&mut fragments);
+}
+
+pub fn draw_world(
+    d: &mut RaylibDrawHandle,
+    player: &Player,
+    world: &World,
+    screenwidth: i32,
+    screenheight: i32,
+) {
+    let offset = Vector2::new(screenwidth as f32 / 2.0, screenheight as f32 / 2.0) - player.pos;
+    let on_screen = |pos: Vector2, radius: f32| {
+        pos.x + radius > 0.0
+            && pos.y + radius > 0.0
+            && pos.x - radius < screenwidth as f32
+            && pos.y - radius < screenheight as f32
+    };
+
+    // Sector boundaries, each labelled in its top left corner.
+    let sector_size = CHUNK_SIZE * SECTOR_CHUNKS as f32;
+    let boundary_color = Color::new(140, 255, 251, 40);
+    let first = ((-offset) / sector_size).x.floor() as i32;
+    let last = ((Vector2::new(screenwidth as f32, 0.0) - offset) / sector_size)
+        .x
+        .floor() as i32;
+    let top = ((-offset) / sector_size).y.floor() as i32;
+    let bottom = ((Vector2::new(0.0, screenheight as f32) - offset) / sector_size)
+        .y
+        .floor() as i32;
+    for sector_x in first..=last {
+        let x = sector_x as f32 * sector_size + offset.x;
+        d.draw_line_ex(
+            Vector2::new(x, 0.0),
+            Vector2::new(x, screenheight as f32),
+            2.0,
+            boundary_color,
+        );
+        for sector_y in top..=bottom {
+            let y = sector_y as f32 * sector_size + offset.y;
+            d.draw_text(
+                &sector_name((sector_x, sector_y)),
+                x as i32 + 8,
+                y as i32 + 8,
+                20,
+                boundary_color,
+            );
+        }
+    }
+    for sector_y in top..=bottom {
+        let y = sector_y as f32 * sector_size + offset.y;
+        d.draw_line_ex(
+            Vector2::new(0.0, y),
+            Vector2::new(screenwidth as f32, y),
+            2.0,
+            boundary_color,
+        );
+    }
+
+    let time = d.get_time() as f32;
+    let mut rng = rand::thread_rng();
+    for zone in &world.zones {
+        let pos = zone.pos + offset;
+        if !on_screen(pos, zone.radius) {
+            continue;
+        }
+        match zone.kind {
+            ZoneKind::GravityWell => {
+                // Rings drifting in towards a dark core.
+                for ring in 0..5 {
+                    let t = (time * 0.25 + ring as f32 / 5.0) % 1.0;
+                    d.draw_circle_lines(
+                        pos.x as i32,
+                        pos.y as i32,
+                        zone.radius * (1.0 - t),
+                        Color::new(150, 90, 255, (90.0 * t) as u8),
+                    );
+                }
+                d.draw_circle_v(pos, zone.radius * 0.08, Color::new(5, 0, 10, 255));
+                d.draw_ring(
+                    pos,
+                    zone.radius * 0.08,
+                    zone.radius * 0.1,
+                    0.0,
+                    360.0,
+                    32,
+                    Color::new(150, 90, 255, 200),
+                );
+            }
+            ZoneKind::Nebula => {
+                for (blob, radius) in &zone.blobs {
+                    d.draw_circle_v(pos + *blob, *radius, Color::new(120, 60, 160, 30));
+                }
+            }
+            ZoneKind::IonStorm => {
+                for (blob, radius) in &zone.blobs {
+                    d.draw_circle_v(pos + *blob, *radius, Color::new(60, 120, 255, 20));
+                }
+                // Now and then a bolt jumps between two of the clouds.
+                if rng.gen_range(0.0..1.0) < 0.3 {
+                    let start = pos + zone.blobs[rng.gen_range(0..zone.blobs.len())].0;
+                    let end = pos + zone.blobs[rng.gen_range(0..zone.blobs.len())].0;
+                    let mut from = start;
+                    for step in 1..=6 {
+                        let to = start
+                            + (end - start) * (step as f32 / 6.0)
+                            + Vector2::new(rng.gen_range(-20.0..20.0), rng.gen_range(-20.0..20.0));
+                        d.draw_line_ex(from, to, 2.0, Color::new(170, 210, 255, 200));
+                        from = to;
+                    }
+                }
+            }
+        }
+    }
+
+    for debris in &world.debris {
+        let pos = debris.pos + offset;
+        if !on_screen(pos, debris.size.x) {
+            continue;
+        }
+        d.draw_rectangle_pro(
+            Rectangle::new(pos.x, pos.y, debris.size.x, debris.size.y),
+            debris.size / 2.0,
+            debris.rotation.to_degrees(),
+            debris.color,
+        );
+    }
+
+    for asteroid in &world.asteroids {
+        let pos = asteroid.pos + offset;
+        if !on_screen(pos, asteroid.radius) {
+            continue;
+        }
+        let flash = asteroid.flash / PART_FLASH_TIME;
+        let fill = colorlerp(Color::new(70, 62, 56, 255), Color::WHITE, flash);
+        let outline = colorlerp(Color::new(130, 120, 110, 255), Color::WHITE, flash);
+        let points: Vec<Vector2> = asteroid
+            .shape
+            .iter()
+            .enumerate()
+            .map(|(index, scale)| {
+                pos + rotatevector(
+                    Vector2::new(asteroid.radius * scale, 0.0),
+                    asteroid.rotation
+                        + std::f32::consts::TAU * index as f32 / asteroid.shape.len() as f32,
+                )
+            })
+            .collect();
+        for index in 0..points.len() {
+            let next = points[(index + 1) % points.len()];
+            // Raylib wants counter-clockwise on screen, which is decreasing angle with y down.
+            d.draw_triangle(pos, next, points[index], fill);
+            d.draw_line_ex(points[index], next, 2.0, outline);
+        }
+    }
+}